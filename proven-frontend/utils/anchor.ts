@@ -1,5 +1,5 @@
 import { Connection, PublicKey } from '@solana/web3.js';
-import { AnchorProvider, Program, Idl } from '@coral-xyz/anchor';
+import { AnchorProvider, Program, Idl, utils } from '@coral-xyz/anchor';
 import { useWallet } from '@solana/wallet-adapter-react';
 import { PROGRAM_ID } from '../config/blockchain';
 
@@ -127,6 +127,7 @@ export const useAnchorProgram = () => {
 // It has been removed and replaced with the proper IDL import above.
 
 // Helper functions for PDAs
+// Challenges are seeded by sha256(challengeId), so ids may exceed 32 bytes
 export const getChallengeAddress = async (
   challengeId: string,
   admin: PublicKey
@@ -134,7 +135,7 @@ export const getChallengeAddress = async (
   return PublicKey.findProgramAddressSync(
     [
       Buffer.from('challenge'),
-      Buffer.from(challengeId),
+      Buffer.from(utils.sha256.hash(challengeId), 'hex'),
       admin.toBuffer()
     ],
     PROGRAM_ID
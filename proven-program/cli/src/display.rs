@@ -0,0 +1,252 @@
+//! Pretty-printing for challenge and participant state, along with the
+//! values the program derives from it at settlement.
+
+use std::fmt::Write;
+
+use proven_stake_client::program::{Challenge, ChallengeStatus, Participant, SettlementPreview};
+use proven_stake_client::settlement;
+use solana_sdk::pubkey::Pubkey;
+
+/// Proof days needed to win; the program derives this at settlement.
+pub fn required_days(challenge: &Challenge) -> u32 {
+    settlement::required_days(challenge.total_days, challenge.threshold_bps)
+}
+
+/// Per-winner bonus and remainder, as `finalize_settlement` records them
+/// or would from the participants settled so far. `None` for team and
+/// forfeiture challenges, which don't pay a flat per-winner bonus.
+pub fn winner_bonus(challenge: &Challenge) -> Option<(u64, u64)> {
+    if challenge.team_mode || challenge.forfeit_beneficiary.is_some() {
+        return None;
+    }
+    if challenge.status == ChallengeStatus::Settled {
+        return Some((challenge.bonus_per_winner, challenge.remainder));
+    }
+    if challenge.winner_count == 0 {
+        return None;
+    }
+    let distribution = settlement::distribute(
+        challenge.stake_amount,
+        challenge.loser_count,
+        challenge.winner_count,
+        challenge.platform_fee_bps,
+    )?;
+    Some((distribution.bonus_per_share, distribution.remainder))
+}
+
+fn status(challenge: &Challenge) -> &'static str {
+    match challenge.status {
+        ChallengeStatus::Created => "created",
+        ChallengeStatus::Started => "started",
+        ChallengeStatus::Ended => "ended",
+        ChallengeStatus::Settled => "settled",
+        ChallengeStatus::Cancelled => "cancelled",
+    }
+}
+
+fn optional(key: Option<Pubkey>) -> String {
+    key.map_or_else(|| "-".to_string(), |key| key.to_string())
+}
+
+pub fn challenge(address: &Pubkey, challenge: &Challenge) -> String {
+    let mut out = String::new();
+    let mut line = |label: &str, value: String| {
+        let _ = writeln!(out, "{label:<22}{value}");
+    };
+    line("Challenge", address.to_string());
+    line("Id", challenge.challenge_id.clone());
+    line("Status", status(challenge).to_string());
+    line("Authority", challenge.authority.to_string());
+    line("Mint", challenge.token_mint.to_string());
+    line("Escrow", challenge.escrow_vault.to_string());
+    line("Stake", challenge.stake_amount.to_string());
+    line(
+        "Schedule",
+        format!("{} .. {}", challenge.start_ts, challenge.end_ts),
+    );
+    line(
+        "Required days",
+        format!(
+            "{} of {} ({} bps)",
+            required_days(challenge),
+            challenge.total_days,
+            challenge.threshold_bps
+        ),
+    );
+    line(
+        "Participants",
+        format!(
+            "{} ({} active)",
+            challenge.participant_count, challenge.active_participants
+        ),
+    );
+    line(
+        "Settled",
+        format!(
+            "{} winners, {} losers",
+            challenge.winner_count, challenge.loser_count
+        ),
+    );
+    line(
+        "Platform fee",
+        format!(
+            "{} bps ({} bps treasury, {} bps charity)",
+            challenge.platform_fee_bps, challenge.treasury_fee_bps, challenge.charity_fee_bps
+        ),
+    );
+    line("Oracle", challenge.oracle_signer.to_string());
+    line("Oracle registry", optional(challenge.oracle_registry));
+    if challenge.status == ChallengeStatus::Settled {
+        line(
+            "Fees",
+            format!(
+                "{} host, {} treasury, {} charity, {} referrals owed",
+                challenge.fee_amount,
+                challenge.treasury_fee_amount,
+                challenge.charity_fee_amount,
+                challenge.referral_owed
+            ),
+        );
+        line(
+            "Claimed",
+            format!(
+                "{} of {} payouts",
+                challenge.payouts_claimed_count, challenge.winner_count
+            ),
+        );
+    }
+    if let Some((bonus, remainder)) = winner_bonus(challenge) {
+        let label = if challenge.status == ChallengeStatus::Settled {
+            "Winner payout"
+        } else {
+            "Projected payout"
+        };
+        line(
+            label,
+            format!(
+                "{} (stake + {bonus} bonus, {remainder} remainder)",
+                challenge.stake_amount + bonus
+            ),
+        );
+    }
+    out
+}
+
+/// Result of simulating `preview_settlement`.
+pub fn preview(challenge: &Challenge, preview: &SettlementPreview) -> String {
+    let mut out = String::new();
+    let mut line = |label: &str, value: String| {
+        let _ = writeln!(out, "{label:<22}{value}");
+    };
+    line(
+        "Projected",
+        format!(
+            "{} winners, {} losers",
+            preview.winner_count, preview.loser_count
+        ),
+    );
+    line("Platform fee", preview.platform_fee.to_string());
+    if challenge.forfeit_beneficiary.is_some() {
+        line("Forfeited", preview.forfeited.to_string());
+    }
+    if preview.winner_count > 0 {
+        line(
+            "Winner payout",
+            format!(
+                "{} (stake + {} bonus, {} remainder)",
+                challenge.stake_amount + preview.bonus_per_winner,
+                preview.bonus_per_winner,
+                preview.remainder
+            ),
+        );
+    } else {
+        line("Unclaimable", preview.remainder.to_string());
+    }
+    out
+}
+
+pub fn participant(address: &Pubkey, challenge: &Challenge, participant: &Participant) -> String {
+    let mut out = String::new();
+    let mut line = |label: &str, value: String| {
+        let _ = writeln!(out, "{label:<22}{value}");
+    };
+    line("Participant", address.to_string());
+    line("User", participant.user.to_string());
+    line("Stake deposited", participant.stake_deposited.to_string());
+    line(
+        "Proof days",
+        format!(
+            "{} of {} required",
+            participant.proof_days,
+            required_days(challenge)
+        ),
+    );
+    let outcome = match (participant.is_settled, participant.is_winner) {
+        (false, _) => "unsettled",
+        (true, true) => "won",
+        (true, false) => "lost",
+    };
+    line("Outcome", outcome.to_string());
+    line(
+        "Claimed",
+        format!(
+            "payout {}, refund {}",
+            participant.payout_claimed, participant.refund_claimed
+        ),
+    );
+    line("Auto-roll", participant.auto_roll.to_string());
+    line("Team", optional(participant.team));
+    line("Referrer", optional(participant.referrer));
+    if participant.is_winner && !participant.payout_claimed {
+        if let Some((bonus, _)) = winner_bonus(challenge) {
+            line(
+                "Expected payout",
+                (challenge.stake_amount + bonus).to_string(),
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{AnchorDeserialize, Space};
+
+    fn challenge() -> Challenge {
+        let mut challenge = Challenge::deserialize(&mut &[0u8; Challenge::INIT_SPACE][..]).unwrap();
+        challenge.stake_amount = 1_000;
+        challenge.total_days = 30;
+        challenge.threshold_bps = 8000;
+        challenge.platform_fee_bps = 500;
+        challenge
+    }
+
+    #[test]
+    fn required_days_rounds_down() {
+        let mut challenge = challenge();
+        assert_eq!(required_days(&challenge), 24);
+        challenge.threshold_bps = 8333;
+        assert_eq!(required_days(&challenge), 24);
+    }
+
+    #[test]
+    fn projects_bonus_before_finalize() {
+        let mut challenge = challenge();
+        challenge.status = ChallengeStatus::Ended;
+        assert_eq!(winner_bonus(&challenge), None);
+
+        // 2 losers: 2000 staked, 100 fee, 1900 across 3 winners
+        challenge.winner_count = 3;
+        challenge.loser_count = 2;
+        assert_eq!(winner_bonus(&challenge), Some((633, 1)));
+
+        challenge.status = ChallengeStatus::Settled;
+        challenge.bonus_per_winner = 7;
+        challenge.remainder = 2;
+        assert_eq!(winner_bonus(&challenge), Some((7, 2)));
+
+        challenge.forfeit_beneficiary = Some(Pubkey::new_unique());
+        assert_eq!(winner_bonus(&challenge), None);
+    }
+}
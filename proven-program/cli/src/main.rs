@@ -0,0 +1,451 @@
+//! `proven`: admin and participant commands for proven-stake challenges.
+//!
+//! Every command signs with `--keypair` and sends a single instruction
+//! built by `proven-stake-client`. Challenge-scoped commands take the
+//! challenge address and read its state first, so mints, oracle registries
+//! and mode-dependent accounts come from chain rather than flags.
+
+mod display;
+mod reconcile;
+
+use std::path::PathBuf;
+
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use proven_stake_client::program::{instruction, Challenge, Participant, SettlementPreview};
+use proven_stake_client::{accounts, instructions, pda, tooling};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+#[derive(Parser)]
+#[command(name = "proven", version, about = "Manage proven-stake challenges")]
+struct Cli {
+    /// JSON RPC endpoint; defaults to a local validator.
+    #[arg(
+        long,
+        short = 'u',
+        env = "PROVEN_RPC_URL",
+        default_value = "http://127.0.0.1:8899"
+    )]
+    url: String,
+    /// Keypair file that signs and pays; defaults to the Solana CLI keypair.
+    #[arg(long, short = 'k', env = "PROVEN_KEYPAIR")]
+    keypair: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a challenge administered by the signer.
+    Create {
+        #[arg(long)]
+        id: String,
+        #[arg(long)]
+        mint: Pubkey,
+        /// Stake per participant, in base units of the mint.
+        #[arg(long)]
+        stake: u64,
+        #[arg(long)]
+        days: u32,
+        #[arg(long, default_value_t = 8000)]
+        threshold_bps: u16,
+        #[arg(long, default_value_t = 0)]
+        fee_bps: u16,
+        /// Unix timestamp the challenge starts at.
+        #[arg(long)]
+        start_ts: i64,
+        #[arg(long)]
+        oracle: Pubkey,
+        #[arg(long, default_value_t = 0)]
+        treasury_fee_bps: u16,
+        #[arg(long, default_value_t = 0)]
+        charity_fee_bps: u16,
+        #[arg(long)]
+        charity: Option<Pubkey>,
+    },
+    /// Join a challenge, depositing the stake from the signer's token account.
+    Join {
+        challenge: Pubkey,
+        #[arg(long)]
+        referrer: Option<Pubkey>,
+        /// Allowlist Merkle proof nodes, hex encoded, leaf to root.
+        #[arg(long = "proof", value_parser = parse_hash)]
+        proof: Vec<[u8; 32]>,
+    },
+    /// Record a day of proof for a participant; the signer is the oracle.
+    RecordProof { challenge: Pubkey, user: Pubkey },
+    /// End the challenge so participants can be settled; signed by the oracle.
+    Settle { challenge: Pubkey },
+    /// Settle one participant against the required days; signed by the oracle.
+    SettleParticipant { challenge: Pubkey, user: Pubkey },
+    /// Compute payouts once every participant is settled; signed by the oracle.
+    Finalize { challenge: Pubkey },
+    /// Claim the signer's winner payout.
+    Claim { challenge: Pubkey },
+    /// Cancel a challenge before it starts.
+    Cancel { challenge: Pubkey },
+    /// Claim the signer's stake back from a cancelled challenge.
+    Refund { challenge: Pubkey },
+    /// Withdraw the host's share of the platform fee to the signer.
+    WithdrawFees { challenge: Pubkey },
+    /// Close a finished challenge, or a participant with `--user`.
+    Close {
+        challenge: Pubkey,
+        #[arg(long)]
+        user: Option<Pubkey>,
+    },
+    /// Print a challenge's state and derived values.
+    Show { challenge: Pubkey },
+    /// Project what finalizing would pay out, by simulating
+    /// `preview_settlement`; every participant's wallet must be listed.
+    Preview {
+        challenge: Pubkey,
+        #[arg(required = true)]
+        users: Vec<Pubkey>,
+    },
+    /// Compare an export of the backend's submissions with the proof days
+    /// recorded on chain, listing the `record_proof` calls still owed.
+    Reconcile {
+        challenge: Pubkey,
+        /// CSV, or a JSON array when the file ends in `.json`, with
+        /// `wallet`, `submissionDate` and optionally `status` columns.
+        #[arg(long)]
+        submissions: PathBuf,
+        /// Send the missing `record_proof` calls; the signer is the oracle.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Print a participant's state; defaults to the signer.
+    ShowParticipant {
+        challenge: Pubkey,
+        user: Option<Pubkey>,
+    },
+}
+
+fn parse_hash(value: &str) -> Result<[u8; 32], String> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    if value.len() != 64 || !value.is_ascii() {
+        return Err("expected 32 hex-encoded bytes".to_string());
+    }
+    let mut hash = [0u8; 32];
+    for (byte, pair) in hash.iter_mut().zip(value.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|e| e.to_string())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|e| e.to_string())?;
+    }
+    Ok(hash)
+}
+
+struct Client {
+    rpc: RpcClient,
+    keypair: PathBuf,
+}
+
+impl Client {
+    fn signer(&self) -> Result<Keypair> {
+        read_keypair_file(&self.keypair)
+            .map_err(|e| anyhow!("reading keypair {}: {e}", self.keypair.display()))
+    }
+
+    fn fetch(&self, address: &Pubkey) -> Result<Vec<u8>> {
+        let account = self
+            .rpc
+            .get_account(address)
+            .with_context(|| format!("fetching {address}"))?;
+        if account.owner != proven_stake_client::PROGRAM_ID {
+            bail!("{address} is not owned by the proven-stake program");
+        }
+        Ok(account.data)
+    }
+
+    fn challenge(&self, address: &Pubkey) -> Result<Challenge> {
+        let data = self.fetch(address)?;
+        accounts::decode_challenge(&data).map_err(|e| anyhow!("decoding challenge: {e}"))
+    }
+
+    fn participant(&self, address: &Pubkey) -> Result<Participant> {
+        let data = self.fetch(address)?;
+        accounts::decode_participant(&data).map_err(|e| anyhow!("decoding participant: {e}"))
+    }
+
+    /// Every participant account of `challenge`, whatever its layout
+    /// version.
+    fn participants(&self, challenge: &Pubkey) -> Result<Vec<Participant>> {
+        // `challenge` follows `user`, and the version byte on current layouts
+        let mut participants = Vec::new();
+        for offset in [8 + 1 + 32, 8 + 32] {
+            let config = RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        0,
+                        Participant::DISCRIMINATOR.to_vec(),
+                    )),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        offset,
+                        challenge.to_bytes().to_vec(),
+                    )),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            };
+            for (address, account) in self
+                .rpc
+                .get_program_accounts_with_config(&proven_stake_client::PROGRAM_ID, config)
+                .context("listing participants")?
+            {
+                // Either filter could match the other layout's bytes by chance
+                if let Ok(participant) = accounts::decode_participant(&account.data) {
+                    if participant.challenge == *challenge
+                        && address == pda::participant(challenge, &participant.user)
+                    {
+                        participants.push(participant);
+                    }
+                }
+            }
+        }
+        Ok(participants)
+    }
+
+    /// The cluster's clock, which is what `record_proof` checks against.
+    fn now(&self) -> Result<i64> {
+        Ok(tooling::cluster_time(&self.rpc)?)
+    }
+
+    fn send(&self, ix: Instruction) -> Result<()> {
+        self.send_all(&[ix])
+    }
+
+    fn send_all(&self, ixs: &[Instruction]) -> Result<()> {
+        let signer = self.signer()?;
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let tx =
+            Transaction::new_signed_with_payer(ixs, Some(&signer.pubkey()), &[&signer], blockhash);
+        let signature = self.rpc.send_and_confirm_transaction(&tx)?;
+        println!("{signature}");
+        Ok(())
+    }
+
+    /// Simulates `ix` and returns the program's return data.
+    fn simulate(&self, ix: Instruction) -> Result<Vec<u8>> {
+        let signer = self.signer()?;
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&signer.pubkey()),
+            &[&signer],
+            blockhash,
+        );
+        let result = self.rpc.simulate_transaction(&tx)?.value;
+        if let Some(err) = result.err {
+            let logs = result.logs.unwrap_or_default().join("\n");
+            bail!("simulation failed: {err}\n{logs}");
+        }
+        let return_data = result
+            .return_data
+            .ok_or_else(|| anyhow!("simulation returned no data"))?;
+        STANDARD
+            .decode(return_data.data.0)
+            .context("decoding return data")
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let ctx = Client {
+        rpc: RpcClient::new_with_commitment(cli.url, CommitmentConfig::confirmed()),
+        keypair: match cli.keypair {
+            Some(path) => path,
+            None => tooling::default_keypair().ok_or_else(|| anyhow!("HOME is not set"))?,
+        },
+    };
+    // Read commands only need the keypair to default the participant
+    let me = || ctx.signer().map(|signer| signer.pubkey());
+
+    match cli.command {
+        Command::Create {
+            id,
+            mint,
+            stake,
+            days,
+            threshold_bps,
+            fee_bps,
+            start_ts,
+            oracle,
+            treasury_fee_bps,
+            charity_fee_bps,
+            charity,
+        } => {
+            let me = me()?;
+            println!("challenge {}", pda::challenge(&id, &me));
+            ctx.send(instructions::create_challenge(
+                me,
+                mint,
+                instruction::CreateChallenge {
+                    challenge_id: id,
+                    stake_amount: stake,
+                    total_days: days,
+                    threshold_bps,
+                    platform_fee_bps: fee_bps,
+                    start_ts,
+                    oracle_signer: oracle,
+                    treasury_fee_bps,
+                    charity_fee_bps,
+                    charity,
+                },
+            ))
+        }
+        Command::Join {
+            challenge,
+            referrer,
+            proof,
+        } => {
+            let c = ctx.challenge(&challenge)?;
+            if c.voucher_required {
+                bail!("this challenge requires a signed join voucher");
+            }
+            ctx.send(instructions::join_challenge(&c, me()?, proof, referrer))
+        }
+        Command::RecordProof { challenge, user } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::record_proof(&c, me()?, user))
+        }
+        Command::Settle { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::settle_challenge(&c, me()?))
+        }
+        Command::SettleParticipant { challenge, user } => {
+            let c = ctx.challenge(&challenge)?;
+            let p = ctx.participant(&pda::participant(&challenge, &user))?;
+            ctx.send(instructions::settle_participant(&c, me()?, &p))
+        }
+        Command::Finalize { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::finalize_settlement(&c, me()?))
+        }
+        Command::Claim { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::claim_payout(&c, me()?))
+        }
+        Command::Cancel { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::cancel_challenge(&c, me()?))
+        }
+        Command::Refund { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::claim_refund(&c, me()?))
+        }
+        Command::WithdrawFees { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            ctx.send(instructions::withdraw_fees(&c))
+        }
+        Command::Close { challenge, user } => {
+            let c = ctx.challenge(&challenge)?;
+            match user {
+                Some(user) => ctx.send(instructions::close_participant(&c, me()?, user)),
+                None => ctx.send(instructions::close_challenge(&c)),
+            }
+        }
+        Command::Show { challenge } => {
+            let c = ctx.challenge(&challenge)?;
+            print!("{}", display::challenge(&challenge, &c));
+            Ok(())
+        }
+        Command::Preview { challenge, users } => {
+            let c = ctx.challenge(&challenge)?;
+            let data = ctx.simulate(instructions::preview_settlement(&c, &users))?;
+            let preview = SettlementPreview::try_from_slice(&data)
+                .map_err(|e| anyhow!("decoding preview: {e}"))?;
+            print!("{}", display::preview(&c, &preview));
+            Ok(())
+        }
+        Command::Reconcile {
+            challenge,
+            submissions,
+            apply,
+        } => {
+            let c = ctx.challenge(&challenge)?;
+            let submissions = reconcile::load(&submissions)?;
+            let reports = reconcile::reconcile(&c, &submissions, &ctx.participants(&challenge)?);
+            print!("{}", reconcile::render(&reports));
+
+            let corrections = reconcile::corrections(&reports);
+            if corrections.is_empty() {
+                return Ok(());
+            }
+            let total: u32 = corrections.iter().map(|(_, days)| days).sum();
+            println!("\n{total} record_proof calls owed:");
+            for (user, days) in &corrections {
+                println!("  {days} x record-proof {challenge} {user}");
+            }
+            if let Err(err) = reconcile::correctable(&c, ctx.now()?) {
+                bail!("cannot correct on chain: {err}");
+            }
+            if !apply {
+                println!("re-run with --apply to send them");
+                return Ok(());
+            }
+            let oracle = me()?;
+            let mut nonce = 0;
+            for (user, days) in corrections {
+                for _ in 0..days {
+                    // A distinct price per call, so repeats sent under one
+                    // blockhash aren't the same transaction
+                    nonce += 1;
+                    ctx.send_all(&[
+                        ComputeBudgetInstruction::set_compute_unit_price(nonce),
+                        instructions::record_proof(&c, oracle, user),
+                    ])?;
+                }
+            }
+            Ok(())
+        }
+        Command::ShowParticipant { challenge, user } => {
+            let c = ctx.challenge(&challenge)?;
+            let address = pda::participant(
+                &challenge,
+                &match user {
+                    Some(user) => user,
+                    None => me()?,
+                },
+            );
+            let p = ctx.participant(&address)?;
+            print!("{}", display::participant(&address, &c, &p));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_proof_hashes() {
+        let hex = "ab".repeat(32);
+        assert_eq!(parse_hash(&hex), Ok([0xab; 32]));
+        assert_eq!(parse_hash(&format!("0x{hex}")), Ok([0xab; 32]));
+        assert!(parse_hash("abcd").is_err());
+        // 64 bytes, with a pair split inside a character
+        assert!(parse_hash(&format!("aé{}", &hex[3..])).is_err());
+    }
+}
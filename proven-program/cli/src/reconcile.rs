@@ -0,0 +1,384 @@
+//! Reconciles the backend's approved submissions against the proof days
+//! recorded on chain.
+//!
+//! The backend approves `Submission` rows, while the program only counts
+//! `record_proof` calls in `Participant::proof_days`. Each approved
+//! submission is placed on the challenge day it falls in, counted once per
+//! wallet and day, and compared with the participant's on-chain count.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use proven_stake_client::program::{Challenge, ChallengeStatus, Participant};
+use proven_stake_client::tooling::{self, SubmissionDate};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+
+/// One exported submission.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submission {
+    pub wallet: Pubkey,
+    pub ts: i64,
+    pub approved: bool,
+}
+
+/// A row of the export. The backend keys submissions by user id, so the
+/// export has to join in the participant's wallet.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Row {
+    #[serde(alias = "walletAddress", alias = "wallet_address")]
+    wallet: String,
+    #[serde(alias = "submission_date", alias = "date")]
+    submission_date: SubmissionDate,
+    /// Rows without a status are taken as approved.
+    #[serde(default)]
+    status: Option<String>,
+}
+
+impl Row {
+    fn submission(self) -> Result<Submission> {
+        Ok(Submission {
+            wallet: self
+                .wallet
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid wallet {:?}", self.wallet))?,
+            ts: self
+                .submission_date
+                .unix()
+                .ok_or_else(|| anyhow!("unrecognised submission date {}", self.submission_date))?,
+            approved: match self.status {
+                Some(status) => status.trim().eq_ignore_ascii_case("APPROVED"),
+                None => true,
+            },
+        })
+    }
+}
+
+/// Reads an export: a JSON array of rows when the file ends in `.json`,
+/// otherwise CSV with a header line.
+pub fn load(path: &Path) -> Result<Vec<Submission>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        parse_json(&text)
+    } else {
+        parse_csv(&text)
+    }
+    .with_context(|| format!("loading {}", path.display()))
+}
+
+fn parse_json(text: &str) -> Result<Vec<Submission>> {
+    let rows: Vec<Row> = serde_json::from_str(text)?;
+    rows.into_iter().map(Row::submission).collect()
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Submission>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut submissions = Vec::new();
+    for (i, row) in reader.deserialize::<Row>().enumerate() {
+        // Line 1 is the header
+        let row = row.with_context(|| format!("line {}", i + 2))?;
+        submissions.push(
+            row.submission()
+                .with_context(|| format!("line {}", i + 2))?,
+        );
+    }
+    Ok(submissions)
+}
+
+/// One wallet's approvals next to its on-chain participant.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserReport {
+    pub wallet: Pubkey,
+    /// Distinct challenge days with an approved submission.
+    pub approved_days: u32,
+    /// Approvals beyond the first on the same day, which the backend may
+    /// count but the chain should not.
+    pub duplicates: u32,
+    /// Approvals dated outside the challenge window, which `record_proof`
+    /// could never have accepted.
+    pub outside_window: u32,
+    /// `proof_days` of the joined participant; `None` when the wallet has
+    /// no participant account or never completed joining.
+    pub proof_days: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finding {
+    Matched,
+    /// Approved days that were never recorded.
+    Missing(u32),
+    /// Recorded days with no approval behind them.
+    Extra(u32),
+    /// Approved submissions from a wallet that isn't a participant.
+    NotJoined,
+}
+
+impl UserReport {
+    pub fn finding(&self) -> Finding {
+        match self.proof_days {
+            None if self.approved_days > 0 => Finding::NotJoined,
+            None => Finding::Matched,
+            Some(recorded) if recorded < self.approved_days => {
+                Finding::Missing(self.approved_days - recorded)
+            }
+            Some(recorded) if recorded > self.approved_days => {
+                Finding::Extra(recorded - self.approved_days)
+            }
+            Some(_) => Finding::Matched,
+        }
+    }
+}
+
+/// Compares `submissions` with the challenge's `participants`, returning one
+/// report per wallet seen on either side, ordered by wallet.
+pub fn reconcile(
+    challenge: &Challenge,
+    submissions: &[Submission],
+    participants: &[Participant],
+) -> Vec<UserReport> {
+    let mut days: BTreeMap<Pubkey, BTreeSet<u32>> = BTreeMap::new();
+    let mut reports: BTreeMap<Pubkey, UserReport> = BTreeMap::new();
+    for submission in submissions.iter().filter(|s| s.approved) {
+        let report = reports
+            .entry(submission.wallet)
+            .or_insert_with(|| UserReport {
+                wallet: submission.wallet,
+                ..UserReport::default()
+            });
+        match tooling::challenge_day(challenge.start_ts, challenge.total_days, submission.ts) {
+            Some(day) if days.entry(submission.wallet).or_default().insert(day) => {
+                report.approved_days += 1
+            }
+            Some(_) => report.duplicates += 1,
+            None => report.outside_window += 1,
+        }
+    }
+    for participant in participants.iter().filter(|p| p.joined) {
+        reports
+            .entry(participant.user)
+            .or_insert_with(|| UserReport {
+                wallet: participant.user,
+                ..UserReport::default()
+            })
+            .proof_days = Some(participant.proof_days);
+    }
+    reports.into_values().collect()
+}
+
+/// `record_proof` calls that would bring each participant up to its
+/// approved days.
+pub fn corrections(reports: &[UserReport]) -> Vec<(Pubkey, u32)> {
+    reports
+        .iter()
+        .filter_map(|report| match report.finding() {
+            Finding::Missing(days) => Some((report.wallet, days)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether `record_proof` still accepts proofs at `now`.
+pub fn correctable(challenge: &Challenge, now: i64) -> Result<()> {
+    if challenge.status != ChallengeStatus::Created && challenge.status != ChallengeStatus::Started
+    {
+        bail!("the challenge is no longer accepting proofs");
+    }
+    if now < challenge.start_ts {
+        bail!("the challenge has not started");
+    }
+    if now > challenge.end_ts {
+        bail!("the challenge window closed at {}", challenge.end_ts);
+    }
+    Ok(())
+}
+
+pub fn render(reports: &[UserReport]) -> String {
+    let mut out = String::new();
+    let matched = reports
+        .iter()
+        .filter(|report| report.finding() == Finding::Matched)
+        .count();
+    let _ = writeln!(
+        out,
+        "{} wallets, {matched} matched, {} mismatched",
+        reports.len(),
+        reports.len() - matched
+    );
+    for report in reports {
+        let finding = match report.finding() {
+            Finding::Matched => continue,
+            Finding::Missing(days) => format!("missing {days} record_proof"),
+            Finding::Extra(days) => format!("{days} recorded without approval"),
+            Finding::NotJoined => "not a participant".to_string(),
+        };
+        let recorded = report
+            .proof_days
+            .map_or_else(|| "-".to_string(), |days| days.to_string());
+        let _ = write!(
+            out,
+            "{:<46}approved {:>3}  on chain {:>3}  {finding}",
+            report.wallet, report.approved_days, recorded
+        );
+        if report.duplicates > 0 {
+            let _ = write!(out, " ({} same-day duplicates)", report.duplicates);
+        }
+        if report.outside_window > 0 {
+            let _ = write!(out, " ({} outside the window)", report.outside_window);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{AnchorDeserialize, Space};
+
+    const DAY: i64 = 24 * 60 * 60;
+    const START: i64 = 1_735_689_600; // 2025-01-01T00:00:00Z
+
+    fn challenge() -> Challenge {
+        let mut challenge = Challenge::deserialize(&mut &[0u8; Challenge::INIT_SPACE][..]).unwrap();
+        challenge.total_days = 10;
+        challenge.start_ts = START;
+        challenge.end_ts = START + 10 * DAY;
+        challenge.status = ChallengeStatus::Started;
+        challenge
+    }
+
+    fn participant(user: Pubkey, proof_days: u32) -> Participant {
+        let mut participant =
+            Participant::deserialize(&mut &[0u8; Participant::INIT_SPACE][..]).unwrap();
+        participant.user = user;
+        participant.joined = true;
+        participant.proof_days = proof_days;
+        participant
+    }
+
+    fn approved(wallet: Pubkey, ts: i64) -> Submission {
+        Submission {
+            wallet,
+            ts,
+            approved: true,
+        }
+    }
+
+    #[test]
+    fn parses_csv_and_json_exports() {
+        let wallet = Pubkey::new_unique();
+        let csv = format!(
+            "wallet,submissionDate,status\n\
+             {wallet},2025-01-02T09:30:00.000Z,APPROVED\n\
+             {wallet}, 2025-01-03 ,rejected\n\
+             {wallet},1735689600,approved\n"
+        );
+        assert_eq!(
+            parse_csv(&csv).unwrap(),
+            [
+                approved(wallet, START + DAY + 9 * 3600 + 1800),
+                Submission {
+                    approved: false,
+                    ..approved(wallet, START + 2 * DAY)
+                },
+                approved(wallet, START),
+            ]
+        );
+
+        let json = format!(
+            r#"[{{"walletAddress": "{wallet}", "submissionDate": {START}}},
+                {{"wallet": "{wallet}", "date": "2025-01-05", "status": "PENDING"}}]"#
+        );
+        let submissions = parse_json(&json).unwrap();
+        assert_eq!(submissions[0], approved(wallet, START));
+        assert!(!submissions[1].approved);
+
+        let bad = format!("wallet,submissionDate\n{wallet},yesterday\n");
+        assert!(format!("{:#}", parse_csv(&bad).unwrap_err()).contains("line 2"));
+        assert!(parse_csv("wallet,submissionDate\nnot-a-key,2025-01-02\n").is_err());
+    }
+
+    #[test]
+    fn counts_each_day_once_inside_the_window() {
+        let challenge = challenge();
+        let wallet = Pubkey::new_unique();
+        let submissions = [
+            approved(wallet, START),
+            approved(wallet, START + DAY - 1),
+            approved(wallet, START + DAY),
+            approved(wallet, START - 1),
+            approved(wallet, challenge.end_ts),
+            Submission {
+                approved: false,
+                ..approved(wallet, START + 2 * DAY)
+            },
+        ];
+        let reports = reconcile(&challenge, &submissions, &[]);
+        assert_eq!(
+            reports,
+            [UserReport {
+                wallet,
+                approved_days: 2,
+                duplicates: 1,
+                outside_window: 2,
+                proof_days: None,
+            }]
+        );
+        assert_eq!(reports[0].finding(), Finding::NotJoined);
+    }
+
+    #[test]
+    fn reports_discrepancies_and_corrections() {
+        let challenge = challenge();
+        let [matched, behind, ahead, silent, stranger] = [(); 5].map(|_| Pubkey::new_unique());
+        let days = |wallet, n: i64| (0..n).map(move |d| approved(wallet, START + d * DAY));
+        let submissions: Vec<_> = days(matched, 3)
+            .chain(days(behind, 5))
+            .chain(days(ahead, 1))
+            .chain(days(stranger, 2))
+            .collect();
+        let mut left = participant(Pubkey::new_unique(), 0);
+        left.joined = false;
+        let participants = [
+            participant(matched, 3),
+            participant(behind, 2),
+            participant(ahead, 4),
+            participant(silent, 0),
+            left,
+        ];
+
+        let reports = reconcile(&challenge, &submissions, &participants);
+        let finding = |wallet| {
+            reports
+                .iter()
+                .find(|report| report.wallet == wallet)
+                .map(UserReport::finding)
+        };
+        assert_eq!(finding(matched), Some(Finding::Matched));
+        assert_eq!(finding(behind), Some(Finding::Missing(3)));
+        assert_eq!(finding(ahead), Some(Finding::Extra(3)));
+        assert_eq!(finding(silent), Some(Finding::Matched));
+        assert_eq!(finding(stranger), Some(Finding::NotJoined));
+        assert_eq!(reports.len(), 5);
+        assert_eq!(corrections(&reports), [(behind, 3)]);
+        assert!(render(&reports).starts_with("5 wallets, 2 matched, 3 mismatched\n"));
+    }
+
+    #[test]
+    fn corrections_need_an_open_window() {
+        let mut challenge = challenge();
+        assert!(correctable(&challenge, START).is_ok());
+        assert!(correctable(&challenge, challenge.end_ts).is_ok());
+        assert!(correctable(&challenge, START - 1).is_err());
+        assert!(correctable(&challenge, challenge.end_ts + 1).is_err());
+        challenge.status = ChallengeStatus::Ended;
+        assert!(correctable(&challenge, START).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Decoders for the program's accounts.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use proven_stake::{
+    Allowlist, Beneficiary, Challenge, ChallengeSeries, Commitment, Duel, OracleRegistry,
+    Participant, PendingOracle, ProtocolConfig, ReferralAccrual, Team, TeamConfig,
+};
+
+macro_rules! proven_accounts {
+    ($($name:ident),* $(,)?) => {
+        /// Any account owned by the program, identified by discriminator.
+        #[allow(clippy::large_enum_variant)]
+        pub enum ProvenAccount {
+            $($name($name),)*
+        }
+
+        impl ProvenAccount {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$name(_) => stringify!($name),)*
+                }
+            }
+        }
+
+        fn decode_current(data: &[u8]) -> Result<ProvenAccount> {
+            let discriminator = data
+                .get(..8)
+                .ok_or(ErrorCode::AccountDiscriminatorNotFound)?;
+            $(
+                if discriminator == $name::DISCRIMINATOR {
+                    return Ok(ProvenAccount::$name($name::try_deserialize(&mut &data[..])?));
+                }
+            )*
+            Err(ErrorCode::AccountDiscriminatorMismatch.into())
+        }
+    };
+}
+
+proven_accounts!(
+    Challenge,
+    Participant,
+    OracleRegistry,
+    PendingOracle,
+    ChallengeSeries,
+    TeamConfig,
+    Team,
+    Allowlist,
+    ReferralAccrual,
+    Duel,
+    Commitment,
+    ProtocolConfig,
+    Beneficiary,
+);
+
+/// Decodes raw account data, upgrading challenges and participants still
+/// in an older layout to the current one.
+pub fn decode(data: &[u8]) -> Result<ProvenAccount> {
+    if data.len() < 8 + Challenge::INIT_SPACE && data.starts_with(&Challenge::DISCRIMINATOR) {
+        return Challenge::from_legacy_data(data).map(ProvenAccount::Challenge);
+    }
+    if data.len() < 8 + Participant::INIT_SPACE && data.starts_with(&Participant::DISCRIMINATOR) {
+        return Participant::from_legacy_data(data).map(ProvenAccount::Participant);
+    }
+    decode_current(data)
+}
+
+pub fn decode_challenge(data: &[u8]) -> Result<Challenge> {
+    match decode(data)? {
+        ProvenAccount::Challenge(challenge) => Ok(challenge),
+        _ => Err(ErrorCode::AccountDiscriminatorMismatch.into()),
+    }
+}
+
+pub fn decode_participant(data: &[u8]) -> Result<Participant> {
+    match decode(data)? {
+        ProvenAccount::Participant(participant) => Ok(participant),
+        _ => Err(ErrorCode::AccountDiscriminatorMismatch.into()),
+    }
+}
+
+/// Decodes an account of a known type in the current layout.
+pub fn decode_as<T: AccountDeserialize>(data: &[u8]) -> Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A challenge with every field zeroed.
+    pub(crate) fn sample_challenge() -> Challenge {
+        Challenge::deserialize(&mut &[0u8; Challenge::INIT_SPACE][..]).unwrap()
+    }
+
+    fn account_bytes<T: AccountSerialize + Space>(account: &T) -> Vec<u8> {
+        let mut data = vec![0u8; 8 + T::INIT_SPACE];
+        account.try_serialize(&mut &mut data[..]).unwrap();
+        data
+    }
+
+    #[test]
+    fn decodes_current_challenge() {
+        let mut challenge = sample_challenge();
+        challenge.version = Challenge::VERSION;
+        challenge.challenge_id = "walk-30".to_string();
+        challenge.stake_amount = 5_000_000;
+        challenge.charity = Some(Pubkey::new_unique());
+
+        let decoded = decode_challenge(&account_bytes(&challenge)).unwrap();
+        assert_eq!(decoded.challenge_id, "walk-30");
+        assert_eq!(decoded.stake_amount, 5_000_000);
+        assert_eq!(decoded.charity, challenge.charity);
+    }
+
+    #[test]
+    fn upgrades_older_layouts() {
+        let mut challenge = sample_challenge();
+        challenge.version = 1;
+        challenge.challenge_id = "walk-30".to_string();
+        challenge.fee_amount = 750_000;
+        let mut data = account_bytes(&challenge);
+        data.truncate(8 + Challenge::V1_LEN);
+
+        let decoded = decode_challenge(&data).unwrap();
+        assert_eq!(decoded.version, Challenge::VERSION);
+        assert_eq!(decoded.fee_amount, 750_000);
+    }
+
+    #[test]
+    fn dispatches_on_discriminator() {
+        let duel = Duel::deserialize(&mut &[0u8; Duel::INIT_SPACE][..]).unwrap();
+        let decoded = decode(&account_bytes(&duel)).unwrap();
+        assert_eq!(decoded.name(), "Duel");
+
+        assert!(decode(&[0u8; 64]).is_err());
+        assert!(decode(&[]).is_err());
+        assert!(decode_participant(&account_bytes(&duel)).is_err());
+    }
+}
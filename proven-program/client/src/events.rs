@@ -0,0 +1,222 @@
+//! Decoders for the events the program emits with `emit!`.
+//!
+//! Each event is logged as `Program data: <base64>`, where the payload is
+//! the event discriminator followed by its Borsh encoding.
+
+use ::proven_stake::*;
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+macro_rules! proven_events {
+    ($($name:ident),* $(,)?) => {
+        /// Any event emitted by the program.
+        pub enum ProvenEvent {
+            $($name($name),)*
+        }
+
+        impl ProvenEvent {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$name(_) => stringify!($name),)*
+                }
+            }
+
+            /// The raw payload, as logged: discriminator, then the Borsh
+            /// encoded event.
+            pub fn data(&self) -> Vec<u8> {
+                match self {
+                    $(Self::$name(event) => anchor_lang::Event::data(event),)*
+                }
+            }
+
+            /// Decodes a raw event payload: discriminator, then the Borsh
+            /// encoded event. Returns `None` for unknown discriminators.
+            pub fn decode(data: &[u8]) -> Option<Result<Self>> {
+                let (discriminator, mut body) = (data.get(..8)?, &data[8..]);
+                $(
+                    if discriminator == $name::DISCRIMINATOR {
+                        return Some(
+                            $name::deserialize(&mut body)
+                                .map(Self::$name)
+                                .map_err(Into::into),
+                        );
+                    }
+                )*
+                None
+            }
+        }
+    };
+}
+
+proven_events!(
+    ChallengeCreated,
+    ChallengeJoined,
+    ProofRecorded,
+    ChallengeSettlementStarted,
+    ParticipantSettled,
+    ChallengeSettled,
+    PayoutClaimed,
+    ChallengeCancelled,
+    RefundClaimed,
+    FeeWithdrawn,
+    ParticipantClosed,
+    ChallengeClosed,
+    ChallengeAmended,
+    OracleUpdated,
+    OracleRegistryLinked,
+    OracleUpdateQueued,
+    OracleRegistryCreated,
+    OracleRotationQueued,
+    OracleRotated,
+    AdminProposed,
+    AdminTransferred,
+    SeriesCreated,
+    SeriesEditionSpawned,
+    AutoRollUpdated,
+    StakeRolledOver,
+    TeamsEnabled,
+    TeamCreated,
+    TeamClosed,
+    TeamJoined,
+    TeamSettled,
+    AllowlistUpdated,
+    CommitmentCreated,
+    CommitmentProofRecorded,
+    CommitmentSettled,
+    BeneficiaryUpdated,
+    ForfeitureEnabled,
+    StakesForfeited,
+    FeeShareWithdrawn,
+    ProtocolTreasuryUpdated,
+    ReferralRateUpdated,
+    ReferralRecorded,
+    ReferralRewardClaimed,
+    VoucherRequirementUpdated,
+    DuelCreated,
+    DuelAccepted,
+    DuelProofRecorded,
+    DuelSettled,
+    DuelCancelled,
+    DuelClosed,
+    AccountMigrated,
+);
+
+/// Extracts the program's events from a transaction's log messages.
+///
+/// Tracks the invocation stack so `Program data` lines written by other
+/// programs, including ones this program invokes, are skipped. Payloads that
+/// fail to decode are returned as errors in place.
+pub fn decode_logs<S: AsRef<str>>(logs: &[S]) -> Vec<Result<ProvenEvent>> {
+    let program_id = ID.to_string();
+    let mut stack: Vec<String> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs.iter().map(AsRef::as_ref) {
+        if let Some(rest) = line.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(id), Some("invoke")) => {
+                    stack.push(id.to_string());
+                    continue;
+                }
+                (Some(id), Some("success" | "failed:"))
+                    if stack.last().map(String::as_str) == Some(id) =>
+                {
+                    stack.pop();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if stack.last() != Some(&program_id) {
+            continue;
+        }
+        let Some(payload) = line.strip_prefix(PROGRAM_DATA) else {
+            continue;
+        };
+        let Ok(data) = STANDARD.decode(payload) else {
+            continue;
+        };
+        if let Some(event) = ProvenEvent::decode(&data) {
+            events.push(event);
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+
+    fn log_line<E: Event>(event: &E) -> String {
+        format!("{PROGRAM_DATA}{}", STANDARD.encode(event.data()))
+    }
+
+    fn joined() -> ChallengeJoined {
+        ChallengeJoined {
+            challenge_id: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            stake_amount: 5_000_000,
+        }
+    }
+
+    #[test]
+    fn decodes_emitted_events() {
+        let event = joined();
+        let logs = vec![
+            format!("Program {ID} invoke [1]"),
+            "Program log: Instruction: JoinChallenge".to_string(),
+            log_line(&event),
+            format!("Program {ID} consumed 20000 of 200000 compute units"),
+            format!("Program {ID} success"),
+        ];
+
+        let decoded = decode_logs(&logs);
+        assert_eq!(decoded.len(), 1);
+        match decoded.into_iter().next().unwrap().unwrap() {
+            ProvenEvent::ChallengeJoined(decoded) => {
+                assert_eq!(decoded.challenge_id, event.challenge_id);
+                assert_eq!(decoded.user, event.user);
+                assert_eq!(decoded.stake_amount, event.stake_amount);
+            }
+            other => panic!("unexpected event {}", other.name()),
+        }
+    }
+
+    #[test]
+    fn skips_data_from_other_programs() {
+        let other = Pubkey::new_unique();
+        let logs = vec![
+            format!("Program {other} invoke [1]"),
+            log_line(&joined()),
+            format!("Program {ID} invoke [2]"),
+            log_line(&DuelClosed {
+                duel: Pubkey::new_unique(),
+                challenger: Pubkey::new_unique(),
+            }),
+            format!("Program {ID} success"),
+            log_line(&joined()),
+            format!("Program {other} success"),
+        ];
+
+        let decoded = decode_logs(&logs);
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(
+            decoded[0].as_ref().ok().map(ProvenEvent::name),
+            Some("DuelClosed")
+        );
+    }
+
+    #[test]
+    fn reports_truncated_payloads() {
+        let mut data = joined().data();
+        data.truncate(20);
+        assert!(ProvenEvent::decode(&data).unwrap().is_err());
+        assert!(ProvenEvent::decode(&[0u8; 8]).is_none());
+    }
+}
@@ -0,0 +1,1124 @@
+//! Instruction builders for every `proven_stake` instruction.
+//!
+//! Builders for existing accounts take the decoded account (see
+//! [`crate::accounts`]) so mints, oracle registries and mode-dependent
+//! optional accounts are filled in from on-chain state. Instructions with
+//! long argument lists take the program's generated argument struct.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{ed25519_program, system_program, sysvar};
+use anchor_lang::InstructionData;
+use anchor_spl::{associated_token, token};
+use proven_stake::{
+    accounts, instruction, Challenge, ChallengeSeries, Commitment, Duel, JoinVoucher, Participant,
+    TeamScoring, ID,
+};
+
+use crate::pda;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Escrow vault of an existing challenge.
+fn challenge_escrow(challenge: &Challenge) -> Pubkey {
+    pda::escrow_vault(&pda::challenge_of(challenge), &challenge.token_mint)
+}
+
+pub fn create_challenge(
+    admin: Pubkey,
+    token_mint: Pubkey,
+    args: instruction::CreateChallenge,
+) -> Instruction {
+    let challenge = pda::challenge(&args.challenge_id, &admin);
+    build(
+        accounts::CreateChallenge {
+            admin,
+            challenge,
+            token_mint,
+            escrow_vault: pda::escrow_vault(&challenge, &token_mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        args,
+    )
+}
+
+/// Joins `challenge`. Allowlisted challenges need a Merkle `proof`;
+/// voucher-gated ones need [`join_voucher`] placed directly before this
+/// instruction.
+pub fn join_challenge(
+    challenge: &Challenge,
+    user: Pubkey,
+    proof: Vec<[u8; 32]>,
+    referrer: Option<Pubkey>,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::JoinChallenge {
+            user,
+            challenge: address,
+            participant: pda::participant(&address, &user),
+            user_token_account: pda::escrow_vault(&user, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            allowlist: challenge.allowlisted.then(|| pda::allowlist(&address)),
+            oracle_registry: challenge
+                .oracle_registry
+                .filter(|_| challenge.voucher_required),
+            referral_accrual: referrer.map(|referrer| pda::referral_accrual(&address, &referrer)),
+            instructions: challenge
+                .voucher_required
+                .then_some(sysvar::instructions::ID),
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::JoinChallenge { proof, referrer },
+    )
+}
+
+/// The ed25519 verification instruction carrying a signed join voucher.
+pub fn join_voucher(signer: &Pubkey, signature: &[u8; 64], voucher: &JoinVoucher) -> Instruction {
+    const HEADER_LEN: u16 = 16;
+    let pubkey_offset = HEADER_LEN;
+    let signature_offset = pubkey_offset + 32;
+    let message_offset = signature_offset + 64;
+    let message = voucher.to_message();
+
+    let mut data = vec![1u8, 0];
+    for field in [
+        signature_offset,
+        u16::MAX,
+        pubkey_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(signer.as_ref());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(&message);
+
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+pub fn record_proof(challenge: &Challenge, oracle: Pubkey, user: Pubkey) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::RecordProof {
+            oracle,
+            challenge: address,
+            oracle_registry: challenge.oracle_registry,
+            participant: pda::participant(&address, &user),
+        },
+        instruction::RecordProof {},
+    )
+}
+
+pub fn settle_challenge(challenge: &Challenge, oracle: Pubkey) -> Instruction {
+    build(
+        accounts::SettleChallenge {
+            oracle,
+            challenge: pda::challenge_of(challenge),
+            oracle_registry: challenge.oracle_registry,
+        },
+        instruction::SettleChallenge {},
+    )
+}
+
+pub fn settle_participant(
+    challenge: &Challenge,
+    oracle: Pubkey,
+    participant: &Participant,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::SettleParticipant {
+            oracle,
+            challenge: address,
+            oracle_registry: challenge.oracle_registry,
+            participant: pda::participant(&address, &participant.user),
+            referral_accrual: participant
+                .referrer
+                .map(|referrer| pda::referral_accrual(&address, &referrer)),
+        },
+        instruction::SettleParticipant {},
+    )
+}
+
+pub fn finalize_settlement(challenge: &Challenge, oracle: Pubkey) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    let forfeiture = challenge.forfeit_beneficiary;
+    build(
+        accounts::FinalizeSettlement {
+            oracle,
+            challenge: address,
+            oracle_registry: challenge.oracle_registry,
+            team_config: challenge.team_mode.then(|| pda::team_config(&address)),
+            escrow_vault: forfeiture.map(|_| challenge_escrow(challenge)),
+            beneficiary_token_account: forfeiture
+                .map(|beneficiary| pda::escrow_vault(&beneficiary, &challenge.token_mint)),
+            token_program: forfeiture.map(|_| token::ID),
+        },
+        instruction::FinalizeSettlement {},
+    )
+}
+
+/// Read-only: simulate it with every participant's `user` and decode the
+/// return data as a [`SettlementPreview`](proven_stake::SettlementPreview).
+pub fn preview_settlement(challenge: &Challenge, users: &[Pubkey]) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    let mut ix = build(
+        accounts::PreviewSettlement { challenge: address },
+        instruction::PreviewSettlement {},
+    );
+    ix.accounts.extend(
+        users
+            .iter()
+            .map(|user| AccountMeta::new_readonly(pda::participant(&address, user), false)),
+    );
+    ix
+}
+
+pub fn claim_payout(challenge: &Challenge, user: Pubkey) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::ClaimPayout {
+            user,
+            challenge: address,
+            participant: pda::participant(&address, &user),
+            user_token_account: pda::escrow_vault(&user, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::ClaimPayout {},
+    )
+}
+
+pub fn cancel_challenge(challenge: &Challenge, admin: Pubkey) -> Instruction {
+    build(
+        accounts::CancelChallenge {
+            admin,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::CancelChallenge {},
+    )
+}
+
+pub fn claim_refund(challenge: &Challenge, user: Pubkey) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::ClaimRefund {
+            user,
+            challenge: address,
+            participant: pda::participant(&address, &user),
+            user_token_account: pda::escrow_vault(&user, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::ClaimRefund {},
+    )
+}
+
+pub fn withdraw_fees(challenge: &Challenge) -> Instruction {
+    let admin = challenge.authority;
+    build(
+        accounts::WithdrawFees {
+            admin,
+            challenge: pda::challenge_of(challenge),
+            admin_token_account: pda::escrow_vault(&admin, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::WithdrawFees {},
+    )
+}
+
+pub fn withdraw_treasury_fees(
+    challenge: &Challenge,
+    authority: Pubkey,
+    treasury: Pubkey,
+) -> Instruction {
+    build(
+        accounts::WithdrawTreasuryFees {
+            authority,
+            protocol_config: pda::protocol_config(),
+            challenge: pda::challenge_of(challenge),
+            treasury_token_account: pda::escrow_vault(&treasury, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::WithdrawTreasuryFees {},
+    )
+}
+
+/// Withdraws the charity's share; `None` if the challenge names no charity.
+pub fn withdraw_charity_fees(challenge: &Challenge) -> Option<Instruction> {
+    let charity = challenge.charity?;
+    Some(build(
+        accounts::WithdrawCharityFees {
+            charity,
+            challenge: pda::challenge_of(challenge),
+            charity_token_account: pda::escrow_vault(&charity, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::WithdrawCharityFees {},
+    ))
+}
+
+/// Closes `user`'s participant account, returning rent to `user`.
+/// `authority` is the user or the challenge authority.
+pub fn close_participant(challenge: &Challenge, authority: Pubkey, user: Pubkey) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::CloseParticipant {
+            authority,
+            challenge: address,
+            participant: pda::participant(&address, &user),
+            destination: user,
+        },
+        instruction::CloseParticipant {},
+    )
+}
+
+pub fn close_challenge(challenge: &Challenge) -> Instruction {
+    build(
+        accounts::CloseChallenge {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::CloseChallenge {},
+    )
+}
+
+pub fn amend_challenge(challenge: &Challenge, total_days: u32, threshold_bps: u16) -> Instruction {
+    build(
+        accounts::AmendChallenge {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::AmendChallenge {
+            total_days,
+            threshold_bps,
+        },
+    )
+}
+
+pub fn update_oracle(challenge: &Challenge, new_oracle: Pubkey) -> Instruction {
+    build(
+        accounts::UpdateOracle {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+            oracle_registry: challenge.oracle_registry,
+        },
+        instruction::UpdateOracle { new_oracle },
+    )
+}
+
+/// Links `challenge` to the oracle registry owned by `registry_authority`.
+pub fn link_oracle_registry(challenge: &Challenge, registry_authority: &Pubkey) -> Instruction {
+    build(
+        accounts::LinkOracleRegistry {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+            oracle_registry: pda::oracle_registry(registry_authority),
+            current_registry: challenge.oracle_registry,
+        },
+        instruction::LinkOracleRegistry {},
+    )
+}
+
+fn propose_oracle_update_with(
+    challenge: &Challenge,
+    new_oracle: Pubkey,
+    new_registry: Option<Pubkey>,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::ProposeOracleUpdate {
+            admin: challenge.authority,
+            challenge: address,
+            pending_oracle: pda::pending_oracle(&address),
+            oracle_registry: challenge.oracle_registry,
+            new_registry,
+            system_program: system_program::ID,
+        },
+        instruction::ProposeOracleUpdate { new_oracle },
+    )
+}
+
+/// Queues `new_oracle` as the direct signer of `challenge`, unlinking any
+/// registry once applied.
+pub fn propose_oracle_update(challenge: &Challenge, new_oracle: Pubkey) -> Instruction {
+    propose_oracle_update_with(challenge, new_oracle, None)
+}
+
+/// Queues a link to the oracle registry owned by `registry_authority`.
+pub fn propose_registry_link(challenge: &Challenge, registry_authority: &Pubkey) -> Instruction {
+    propose_oracle_update_with(
+        challenge,
+        challenge.oracle_signer,
+        Some(pda::oracle_registry(registry_authority)),
+    )
+}
+
+pub fn apply_oracle_update(challenge: &Challenge) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::ApplyOracleUpdate {
+            admin: challenge.authority,
+            challenge: address,
+            pending_oracle: pda::pending_oracle(&address),
+        },
+        instruction::ApplyOracleUpdate {},
+    )
+}
+
+pub fn cancel_oracle_update(challenge: &Challenge) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::CancelOracleUpdate {
+            admin: challenge.authority,
+            challenge: address,
+            pending_oracle: pda::pending_oracle(&address),
+        },
+        instruction::CancelOracleUpdate {},
+    )
+}
+
+pub fn propose_admin(challenge: &Challenge, new_admin: Pubkey) -> Instruction {
+    build(
+        accounts::ProposeAdmin {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::ProposeAdmin { new_admin },
+    )
+}
+
+pub fn accept_admin(challenge: &Challenge, new_admin: Pubkey) -> Instruction {
+    build(
+        accounts::AcceptAdmin {
+            new_admin,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::AcceptAdmin {},
+    )
+}
+
+/// Migrates a challenge account by address; its layout can't be decoded
+/// as a current `Challenge` until this runs.
+pub fn migrate_challenge(payer: Pubkey, challenge: Pubkey) -> Instruction {
+    build(
+        accounts::MigrateChallenge {
+            payer,
+            challenge,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateChallenge {},
+    )
+}
+
+pub fn migrate_participant(payer: Pubkey, participant: Pubkey) -> Instruction {
+    build(
+        accounts::MigrateParticipant {
+            payer,
+            participant,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateParticipant {},
+    )
+}
+
+pub fn create_series(
+    admin: Pubkey,
+    token_mint: Pubkey,
+    args: instruction::CreateSeries,
+) -> Instruction {
+    build(
+        accounts::CreateSeries {
+            admin,
+            series: pda::series(&args.series_id, &admin),
+            token_mint,
+            system_program: system_program::ID,
+        },
+        args,
+    )
+}
+
+pub fn spawn_next(series: &ChallengeSeries, payer: Pubkey) -> Instruction {
+    let challenge = pda::challenge(&series.edition_id(series.next_edition), &series.admin);
+    build(
+        accounts::SpawnNext {
+            payer,
+            series: pda::series_of(series),
+            challenge,
+            token_mint: series.token_mint,
+            escrow_vault: pda::escrow_vault(&challenge, &series.token_mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::SpawnNext {},
+    )
+}
+
+pub fn set_auto_roll(challenge: &Challenge, user: Pubkey, enabled: bool) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::SetAutoRoll {
+            user,
+            challenge: address,
+            participant: pda::participant(&address, &user),
+        },
+        instruction::SetAutoRoll { enabled },
+    )
+}
+
+/// Rolls `user`'s payout from `challenge` into `next`, the following
+/// edition of `series`.
+pub fn roll_over(
+    series: &ChallengeSeries,
+    challenge: &Challenge,
+    next: &Challenge,
+    user: Pubkey,
+    payer: Pubkey,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    let next_address = pda::challenge_of(next);
+    build(
+        accounts::RollOver {
+            payer,
+            series: pda::series_of(series),
+            challenge: address,
+            participant: pda::participant(&address, &user),
+            user,
+            next_challenge: next_address,
+            next_participant: pda::participant(&next_address, &user),
+            user_token_account: pda::escrow_vault(&user, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            next_escrow_vault: pda::escrow_vault(&next_address, &challenge.token_mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::RollOver {},
+    )
+}
+
+pub fn create_allowlist(challenge: &Challenge, root: [u8; 32]) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::CreateAllowlist {
+            admin: challenge.authority,
+            challenge: address,
+            allowlist: pda::allowlist(&address),
+            system_program: system_program::ID,
+        },
+        instruction::CreateAllowlist { root },
+    )
+}
+
+pub fn update_allowlist_root(challenge: &Challenge, root: [u8; 32]) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::UpdateAllowlistRoot {
+            admin: challenge.authority,
+            challenge: address,
+            allowlist: pda::allowlist(&address),
+        },
+        instruction::UpdateAllowlistRoot { root },
+    )
+}
+
+pub fn set_referral_bps(challenge: &Challenge, referral_bps: u16) -> Instruction {
+    build(
+        accounts::SetReferralBps {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::SetReferralBps { referral_bps },
+    )
+}
+
+pub fn open_referral_accrual(
+    challenge: &Challenge,
+    payer: Pubkey,
+    referrer: Pubkey,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::OpenReferralAccrual {
+            payer,
+            challenge: address,
+            referral_accrual: pda::referral_accrual(&address, &referrer),
+            system_program: system_program::ID,
+        },
+        instruction::OpenReferralAccrual { referrer },
+    )
+}
+
+pub fn claim_referral(challenge: &Challenge, referrer: Pubkey) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::ClaimReferral {
+            referrer,
+            challenge: address,
+            referral_accrual: pda::referral_accrual(&address, &referrer),
+            referrer_token_account: pda::escrow_vault(&referrer, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::ClaimReferral {},
+    )
+}
+
+pub fn set_voucher_required(challenge: &Challenge, required: bool) -> Instruction {
+    build(
+        accounts::SetVoucherRequired {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+        },
+        instruction::SetVoucherRequired { required },
+    )
+}
+
+pub fn enable_forfeiture(challenge: &Challenge, beneficiary_wallet: &Pubkey) -> Instruction {
+    build(
+        accounts::EnableForfeiture {
+            admin: challenge.authority,
+            challenge: pda::challenge_of(challenge),
+            beneficiary: pda::beneficiary(beneficiary_wallet),
+        },
+        instruction::EnableForfeiture {},
+    )
+}
+
+pub fn enable_teams(challenge: &Challenge, scoring: TeamScoring) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::EnableTeams {
+            admin: challenge.authority,
+            challenge: address,
+            team_config: pda::team_config(&address),
+            system_program: system_program::ID,
+        },
+        instruction::EnableTeams { scoring },
+    )
+}
+
+/// Creates the team at `index`, which must be the team config's current
+/// `team_count`.
+pub fn create_team(
+    challenge: &Challenge,
+    captain: Pubkey,
+    index: u32,
+    name: String,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::CreateTeam {
+            captain,
+            challenge: address,
+            team_config: pda::team_config(&address),
+            team: pda::team(&address, index),
+            system_program: system_program::ID,
+        },
+        instruction::CreateTeam { name },
+    )
+}
+
+/// Closes the empty team at `index`, returning its rent to `captain`.
+pub fn close_team(challenge: &Challenge, captain: Pubkey, index: u32) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::CloseTeam {
+            captain,
+            challenge: address,
+            team: pda::team(&address, index),
+        },
+        instruction::CloseTeam {},
+    )
+}
+
+pub fn join_team(
+    challenge: &Challenge,
+    user: Pubkey,
+    team_index: u32,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::JoinTeam {
+            user,
+            challenge: address,
+            team_config: pda::team_config(&address),
+            team: pda::team(&address, team_index),
+            participant: pda::participant(&address, &user),
+            user_token_account: pda::escrow_vault(&user, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            allowlist: challenge.allowlisted.then(|| pda::allowlist(&address)),
+            oracle_registry: challenge
+                .oracle_registry
+                .filter(|_| challenge.voucher_required),
+            instructions: challenge
+                .voucher_required
+                .then_some(sysvar::instructions::ID),
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::JoinTeam { proof },
+    )
+}
+
+/// Settles a team; `members` must list every member's wallet.
+pub fn settle_team(
+    challenge: &Challenge,
+    oracle: Pubkey,
+    team_index: u32,
+    members: &[Pubkey],
+) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    let mut ix = build(
+        accounts::SettleTeam {
+            oracle,
+            challenge: address,
+            oracle_registry: challenge.oracle_registry,
+            team_config: pda::team_config(&address),
+            team: pda::team(&address, team_index),
+        },
+        instruction::SettleTeam {},
+    );
+    ix.accounts.extend(
+        members
+            .iter()
+            .map(|user| AccountMeta::new(pda::participant(&address, user), false)),
+    );
+    ix
+}
+
+pub fn claim_team_payout(challenge: &Challenge, user: Pubkey, team_index: u32) -> Instruction {
+    let address = pda::challenge_of(challenge);
+    build(
+        accounts::ClaimTeamPayout {
+            user,
+            challenge: address,
+            team_config: pda::team_config(&address),
+            team: pda::team(&address, team_index),
+            participant: pda::participant(&address, &user),
+            user_token_account: pda::escrow_vault(&user, &challenge.token_mint),
+            escrow_vault: challenge_escrow(challenge),
+            token_program: token::ID,
+        },
+        instruction::ClaimTeamPayout {},
+    )
+}
+
+pub fn create_duel(
+    challenger: Pubkey,
+    token_mint: Pubkey,
+    args: instruction::CreateDuel,
+) -> Instruction {
+    let duel = pda::duel(&challenger, args.duel_id);
+    build(
+        accounts::CreateDuel {
+            challenger,
+            duel,
+            token_mint,
+            challenger_token_account: pda::escrow_vault(&challenger, &token_mint),
+            escrow_vault: pda::escrow_vault(&duel, &token_mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        args,
+    )
+}
+
+pub fn accept_duel(duel: &Duel) -> Instruction {
+    let address = pda::duel_of(duel);
+    build(
+        accounts::AcceptDuel {
+            opponent: duel.opponent,
+            duel: address,
+            opponent_token_account: pda::escrow_vault(&duel.opponent, &duel.token_mint),
+            escrow_vault: pda::escrow_vault(&address, &duel.token_mint),
+            token_program: token::ID,
+        },
+        instruction::AcceptDuel {},
+    )
+}
+
+pub fn record_duel_proof(duel: &Duel, user: Pubkey) -> Instruction {
+    build(
+        accounts::RecordDuelProof {
+            oracle: duel.oracle_signer,
+            duel: pda::duel_of(duel),
+        },
+        instruction::RecordDuelProof { user },
+    )
+}
+
+pub fn settle_duel(duel: &Duel) -> Instruction {
+    build(
+        accounts::SettleDuel {
+            duel: pda::duel_of(duel),
+        },
+        instruction::SettleDuel {},
+    )
+}
+
+pub fn claim_duel(duel: &Duel, user: Pubkey) -> Instruction {
+    let address = pda::duel_of(duel);
+    build(
+        accounts::ClaimDuel {
+            user,
+            duel: address,
+            user_token_account: pda::escrow_vault(&user, &duel.token_mint),
+            escrow_vault: pda::escrow_vault(&address, &duel.token_mint),
+            token_program: token::ID,
+        },
+        instruction::ClaimDuel {},
+    )
+}
+
+pub fn cancel_duel(duel: &Duel) -> Instruction {
+    let address = pda::duel_of(duel);
+    build(
+        accounts::CancelDuel {
+            challenger: duel.challenger,
+            duel: address,
+            challenger_token_account: pda::escrow_vault(&duel.challenger, &duel.token_mint),
+            escrow_vault: pda::escrow_vault(&address, &duel.token_mint),
+            token_program: token::ID,
+        },
+        instruction::CancelDuel {},
+    )
+}
+
+pub fn close_duel(duel: &Duel) -> Instruction {
+    let address = pda::duel_of(duel);
+    build(
+        accounts::CloseDuel {
+            challenger: duel.challenger,
+            duel: address,
+            challenger_token_account: pda::escrow_vault(&duel.challenger, &duel.token_mint),
+            escrow_vault: pda::escrow_vault(&address, &duel.token_mint),
+            token_program: token::ID,
+        },
+        instruction::CloseDuel {},
+    )
+}
+
+pub fn create_commitment(
+    user: Pubkey,
+    token_mint: Pubkey,
+    args: instruction::CreateCommitment,
+) -> Instruction {
+    let commitment = pda::commitment(&user, args.commitment_id);
+    build(
+        accounts::CreateCommitment {
+            user,
+            commitment,
+            token_mint,
+            user_token_account: pda::escrow_vault(&user, &token_mint),
+            escrow_vault: pda::escrow_vault(&commitment, &token_mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        args,
+    )
+}
+
+pub fn record_commitment_proof(commitment: &Commitment) -> Instruction {
+    build(
+        accounts::RecordCommitmentProof {
+            referee: commitment.referee,
+            commitment: pda::commitment_of(commitment),
+        },
+        instruction::RecordCommitmentProof {},
+    )
+}
+
+/// Settles `commitment`, paying the user's or the recipient's token
+/// account depending on the recorded proofs.
+pub fn settle_commitment(commitment: &Commitment) -> Instruction {
+    let address = pda::commitment_of(commitment);
+    let payee = if commitment.proof_days >= commitment.required_days {
+        commitment.user
+    } else {
+        commitment.recipient
+    };
+    build(
+        accounts::SettleCommitment {
+            user: commitment.user,
+            commitment: address,
+            destination_token_account: pda::escrow_vault(&payee, &commitment.token_mint),
+            escrow_vault: pda::escrow_vault(&address, &commitment.token_mint),
+            token_program: token::ID,
+        },
+        instruction::SettleCommitment {},
+    )
+}
+
+pub fn initialize_protocol(authority: Pubkey, treasury: Pubkey) -> Instruction {
+    build(
+        accounts::InitializeProtocol {
+            authority,
+            protocol_config: pda::protocol_config(),
+            program: ID,
+            program_data: pda::program_data(),
+            system_program: system_program::ID,
+        },
+        instruction::InitializeProtocol { treasury },
+    )
+}
+
+pub fn set_protocol_treasury(authority: Pubkey, treasury: Pubkey) -> Instruction {
+    build(
+        accounts::SetProtocolTreasury {
+            authority,
+            protocol_config: pda::protocol_config(),
+        },
+        instruction::SetProtocolTreasury { treasury },
+    )
+}
+
+pub fn register_beneficiary(authority: Pubkey, wallet: Pubkey, name: String) -> Instruction {
+    build(
+        accounts::RegisterBeneficiary {
+            authority,
+            protocol_config: pda::protocol_config(),
+            beneficiary: pda::beneficiary(&wallet),
+            system_program: system_program::ID,
+        },
+        instruction::RegisterBeneficiary { wallet, name },
+    )
+}
+
+pub fn set_beneficiary_active(authority: Pubkey, wallet: &Pubkey, active: bool) -> Instruction {
+    build(
+        accounts::SetBeneficiaryActive {
+            authority,
+            protocol_config: pda::protocol_config(),
+            beneficiary: pda::beneficiary(wallet),
+        },
+        instruction::SetBeneficiaryActive { active },
+    )
+}
+
+pub fn create_oracle_registry(authority: Pubkey, signer: Pubkey, update_delay: i64) -> Instruction {
+    build(
+        accounts::CreateOracleRegistry {
+            authority,
+            oracle_registry: pda::oracle_registry(&authority),
+            system_program: system_program::ID,
+        },
+        instruction::CreateOracleRegistry {
+            signer,
+            update_delay,
+        },
+    )
+}
+
+pub fn rotate_oracle(authority: Pubkey, new_signer: Pubkey) -> Instruction {
+    build(
+        accounts::RotateOracle {
+            authority,
+            oracle_registry: pda::oracle_registry(&authority),
+        },
+        instruction::RotateOracle { new_signer },
+    )
+}
+
+/// Applies a queued rotation once its time-lock has passed; permissionless.
+pub fn apply_oracle_rotation(registry_authority: &Pubkey) -> Instruction {
+    build(
+        accounts::ApplyOracleRotation {
+            oracle_registry: pda::oracle_registry(registry_authority),
+        },
+        instruction::ApplyOracleRotation {},
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::tests::sample_challenge;
+    use anchor_lang::Discriminator;
+
+    fn decode_args<T: Discriminator + AnchorDeserialize>(ix: &Instruction) -> T {
+        assert_eq!(ix.program_id, ID);
+        assert_eq!(&ix.data[..8], T::DISCRIMINATOR);
+        T::deserialize(&mut &ix.data[8..]).unwrap()
+    }
+
+    fn challenge() -> Challenge {
+        let mut challenge = sample_challenge();
+        challenge.initialize(
+            "walk-30".to_string(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::default(),
+            5_000_000,
+            30,
+            8000,
+            500,
+            1_700_000_000,
+            Pubkey::new_unique(),
+            255,
+        );
+        challenge
+    }
+
+    #[test]
+    fn create_challenge_derives_accounts() {
+        let admin = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let ix = create_challenge(
+            admin,
+            mint,
+            instruction::CreateChallenge {
+                challenge_id: "walk-30".to_string(),
+                stake_amount: 5_000_000,
+                total_days: 30,
+                threshold_bps: 8000,
+                platform_fee_bps: 500,
+                start_ts: 1_700_000_000,
+                oracle_signer: Pubkey::new_unique(),
+                treasury_fee_bps: 1000,
+                charity_fee_bps: 0,
+                charity: None,
+            },
+        );
+
+        let args: instruction::CreateChallenge = decode_args(&ix);
+        assert_eq!(args.challenge_id, "walk-30");
+        assert_eq!(args.treasury_fee_bps, 1000);
+
+        let challenge = pda::challenge("walk-30", &admin);
+        assert_eq!(ix.accounts[0], AccountMeta::new(admin, true));
+        assert_eq!(ix.accounts[1], AccountMeta::new(challenge, false));
+        assert_eq!(ix.accounts[2], AccountMeta::new_readonly(mint, false));
+        assert_eq!(ix.accounts[3].pubkey, pda::escrow_vault(&challenge, &mint));
+    }
+
+    #[test]
+    fn join_challenge_fills_optional_accounts_from_state() {
+        let mut challenge = challenge();
+        let address = pda::challenge_of(&challenge);
+        let user = Pubkey::new_unique();
+
+        // Anchor marks an omitted optional account with the program id
+        let ix = join_challenge(&challenge, user, vec![], None);
+        let optional: Vec<Pubkey> = ix.accounts[5..9].iter().map(|m| m.pubkey).collect();
+        assert_eq!(optional, vec![ID; 4]);
+
+        challenge.allowlisted = true;
+        challenge.voucher_required = true;
+        challenge.oracle_registry = Some(Pubkey::new_unique());
+        let referrer = Pubkey::new_unique();
+        let ix = join_challenge(&challenge, user, vec![[7; 32]], Some(referrer));
+        let optional: Vec<Pubkey> = ix.accounts[5..9].iter().map(|m| m.pubkey).collect();
+        assert_eq!(
+            optional,
+            vec![
+                pda::allowlist(&address),
+                challenge.oracle_registry.unwrap(),
+                pda::referral_accrual(&address, &referrer),
+                sysvar::instructions::ID,
+            ]
+        );
+        assert_eq!(ix.accounts[2].pubkey, pda::participant(&address, &user));
+
+        let args: instruction::JoinChallenge = decode_args(&ix);
+        assert_eq!(args.proof, vec![[7; 32]]);
+        assert_eq!(args.referrer, Some(referrer));
+    }
+
+    #[test]
+    fn join_voucher_is_what_the_program_parses() {
+        let signer = Pubkey::new_unique();
+        let voucher = JoinVoucher {
+            challenge: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            expiry: 1_700_000_000,
+        };
+        let ix = join_voucher(&signer, &[9; 64], &voucher);
+
+        let (parsed_signer, parsed) = JoinVoucher::from_ed25519_instruction(&ix).unwrap();
+        assert_eq!(parsed_signer, signer);
+        assert_eq!(parsed, voucher);
+    }
+
+    #[test]
+    fn settle_team_appends_member_participants() {
+        let challenge = challenge();
+        let address = pda::challenge_of(&challenge);
+        let members = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let ix = settle_team(&challenge, challenge.oracle_signer, 3, &members);
+
+        assert_eq!(ix.accounts[4].pubkey, pda::team(&address, 3));
+        assert_eq!(
+            ix.accounts[5..],
+            [
+                AccountMeta::new(pda::participant(&address, &members[0]), false),
+                AccountMeta::new(pda::participant(&address, &members[1]), false),
+            ]
+        );
+        let _: instruction::SettleTeam = decode_args(&ix);
+    }
+
+    #[test]
+    fn finalize_settlement_routes_forfeitures() {
+        let mut challenge = challenge();
+        let ix = finalize_settlement(&challenge, challenge.oracle_signer);
+        assert!(ix.accounts[4..].iter().all(|m| m.pubkey == ID));
+
+        let beneficiary = Pubkey::new_unique();
+        challenge.forfeit_beneficiary = Some(beneficiary);
+        let ix = finalize_settlement(&challenge, challenge.oracle_signer);
+        assert_eq!(ix.accounts[4].pubkey, challenge_escrow(&challenge));
+        assert_eq!(
+            ix.accounts[5].pubkey,
+            pda::escrow_vault(&beneficiary, &challenge.token_mint)
+        );
+        assert_eq!(ix.accounts[6].pubkey, token::ID);
+    }
+
+    #[test]
+    fn settle_commitment_pays_by_outcome() {
+        let mut commitment =
+            Commitment::deserialize(&mut &[0u8; Commitment::INIT_SPACE][..]).unwrap();
+        commitment.user = Pubkey::new_unique();
+        commitment.recipient = Pubkey::new_unique();
+        commitment.token_mint = Pubkey::new_unique();
+        commitment.required_days = 5;
+
+        commitment.proof_days = 4;
+        let ix = settle_commitment(&commitment);
+        assert_eq!(
+            ix.accounts[2].pubkey,
+            pda::escrow_vault(&commitment.recipient, &commitment.token_mint)
+        );
+
+        commitment.proof_days = 5;
+        let ix = settle_commitment(&commitment);
+        assert_eq!(
+            ix.accounts[2].pubkey,
+            pda::escrow_vault(&commitment.user, &commitment.token_mint)
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Client-side helpers for the `proven_stake` program.
+//!
+//! Everything here is built on the program crate's own types, so the
+//! instruction layouts, PDA seeds and account formats can't drift from
+//! what is deployed:
+//!
+//! - [`pda`]: addresses for every program-derived account and escrow ATA
+//! - [`instructions`]: one builder per program instruction
+//! - [`accounts`]: decoders for every account type, including legacy layouts
+//! - [`events`]: decoders for the events the program logs
+//! - [`settlement`]: the program's own payout arithmetic, for predicting
+//!   what settlement will book without a transaction
+//! - [`tooling`]: the default keypair, the cluster's clock and submission
+//!   dates, for the off-chain tools
+
+pub mod accounts;
+pub mod events;
+pub mod instructions;
+pub mod pda;
+pub mod tooling;
+
+pub use proven_stake::{self as program, settlement, ID as PROGRAM_ID};
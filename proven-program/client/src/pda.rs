@@ -0,0 +1,188 @@
+//! Program-derived addresses, mirroring the `seeds` constraints in the
+//! program's account contexts.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use anchor_spl::associated_token::get_associated_token_address;
+use proven_stake::{Challenge, ChallengeSeries, Commitment, Duel, ID};
+
+/// Address of a newly created challenge, seeded by `hash(challenge_id)`.
+pub fn challenge(challenge_id: &str, admin: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"challenge",
+            Challenge::hash_id(challenge_id).as_ref(),
+            admin.as_ref(),
+        ],
+        &ID,
+    )
+    .0
+}
+
+/// Address of an existing challenge. Unlike [`challenge`], this also covers
+/// legacy challenges seeded by the raw id.
+pub fn challenge_of(challenge: &Challenge) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        &ID,
+    )
+    .0
+}
+
+pub fn participant(challenge: &Pubkey, user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"participant", challenge.as_ref(), user.as_ref()], &ID).0
+}
+
+/// Associated token account holding the stakes of a challenge, duel or
+/// commitment.
+pub fn escrow_vault(owner: &Pubkey, token_mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(owner, token_mint)
+}
+
+pub fn series(series_id: &str, admin: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"series",
+            Challenge::hash_id(series_id).as_ref(),
+            admin.as_ref(),
+        ],
+        &ID,
+    )
+    .0
+}
+
+pub fn series_of(series: &ChallengeSeries) -> Pubkey {
+    self::series(&series.series_id, &series.admin)
+}
+
+pub fn oracle_registry(authority: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle_registry", authority.as_ref()], &ID).0
+}
+
+pub fn pending_oracle(challenge: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"pending_oracle", challenge.as_ref()], &ID).0
+}
+
+pub fn allowlist(challenge: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"allowlist", challenge.as_ref()], &ID).0
+}
+
+pub fn referral_accrual(challenge: &Pubkey, referrer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"referral", challenge.as_ref(), referrer.as_ref()], &ID).0
+}
+
+pub fn team_config(challenge: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"team_config", challenge.as_ref()], &ID).0
+}
+
+pub fn team(challenge: &Pubkey, index: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"team", challenge.as_ref(), index.to_le_bytes().as_ref()],
+        &ID,
+    )
+    .0
+}
+
+pub fn duel(challenger: &Pubkey, duel_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"duel", challenger.as_ref(), duel_id.to_le_bytes().as_ref()],
+        &ID,
+    )
+    .0
+}
+
+pub fn duel_of(duel: &Duel) -> Pubkey {
+    self::duel(&duel.challenger, duel.duel_id)
+}
+
+pub fn commitment(user: &Pubkey, commitment_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"commitment",
+            user.as_ref(),
+            commitment_id.to_le_bytes().as_ref(),
+        ],
+        &ID,
+    )
+    .0
+}
+
+pub fn commitment_of(commitment: &Commitment) -> Pubkey {
+    self::commitment(&commitment.user, commitment.commitment_id)
+}
+
+pub fn protocol_config() -> Pubkey {
+    Pubkey::find_program_address(&[b"protocol"], &ID).0
+}
+
+pub fn beneficiary(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"beneficiary", wallet.as_ref()], &ID).0
+}
+
+/// The upgradeable loader's data account for the program, needed by
+/// `initialize_protocol`.
+pub fn program_data() -> Pubkey {
+    Pubkey::find_program_address(&[ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_address_matches_stored_seeds() {
+        let admin = Pubkey::new_unique();
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"challenge",
+                Challenge::hash_id("walk-30").as_ref(),
+                admin.as_ref(),
+            ],
+            &ID,
+        );
+        assert_eq!(challenge("walk-30", &admin), address);
+
+        let mut account = crate::accounts::tests::sample_challenge();
+        account.initialize(
+            "walk-30".to_string(),
+            admin,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            1,
+            1,
+            0,
+            0,
+            0,
+            Pubkey::new_unique(),
+            bump,
+        );
+        assert_eq!(challenge_of(&account), address);
+
+        // Legacy challenges are seeded by the raw id instead
+        account.hashed_seeds = false;
+        assert_ne!(challenge_of(&account), address);
+    }
+
+    #[test]
+    fn escrow_is_the_owner_ata() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (expected, _) = Pubkey::find_program_address(
+            &[
+                owner.as_ref(),
+                anchor_spl::token::ID.as_ref(),
+                mint.as_ref(),
+            ],
+            &anchor_spl::associated_token::ID,
+        );
+        assert_eq!(escrow_vault(&owner, &mint), expected);
+    }
+
+    #[test]
+    fn seeded_ids_round_trip() {
+        let user = Pubkey::new_unique();
+        assert_ne!(duel(&user, 1), duel(&user, 2));
+        assert_ne!(commitment(&user, 1), duel(&user, 1));
+        assert_ne!(team(&user, 0), team(&user, 1));
+    }
+}
@@ -0,0 +1,120 @@
+//! Pieces shared by the off-chain tools: where the Solana CLI keeps its
+//! keypair, the cluster's clock, and submission dates as the backend
+//! exports them and the challenge days they fall on.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::sysvar;
+use chrono::{DateTime, NaiveDate};
+use serde::Deserialize;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// The Solana CLI's default keypair, `~/.config/solana/id.json`, or `None`
+/// when `HOME` is not set.
+pub fn default_keypair() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/solana/id.json"))
+}
+
+/// The cluster's clock, which is what the program checks time against,
+/// rather than this machine's.
+pub fn cluster_time(rpc: &RpcClient) -> Result<i64, Box<ClientError>> {
+    let account = rpc.get_account(&sysvar::clock::ID)?;
+    let clock: Clock = bincode::deserialize(&account.data).map_err(|e| {
+        ClientError::from(ClientErrorKind::Custom(format!(
+            "decoding the clock sysvar: {e}"
+        )))
+    })?;
+    Ok(clock.unix_timestamp)
+}
+
+/// When a submission was made: unix seconds, or text holding them, an
+/// RFC 3339 timestamp, or a bare date, which is taken as midnight UTC.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SubmissionDate {
+    Unix(i64),
+    Text(String),
+}
+
+impl SubmissionDate {
+    /// Unix seconds, or `None` if the text is none of the accepted forms.
+    pub fn unix(&self) -> Option<i64> {
+        let text = match self {
+            SubmissionDate::Unix(ts) => return Some(*ts),
+            SubmissionDate::Text(text) => text.trim(),
+        };
+        if let Ok(ts) = text.parse() {
+            return Some(ts);
+        }
+        if let Ok(ts) = DateTime::parse_from_rfc3339(text) {
+            return Some(ts.timestamp());
+        }
+        let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+        Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+    }
+}
+
+impl fmt::Display for SubmissionDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubmissionDate::Unix(ts) => write!(f, "{ts}"),
+            SubmissionDate::Text(text) => write!(f, "{text:?}"),
+        }
+    }
+}
+
+/// The zero-based day `ts` falls on in a challenge of `total_days` days
+/// starting at `start_ts`, or `None` if it is before the start or after
+/// the last day.
+pub fn challenge_day(start_ts: i64, total_days: u32, ts: i64) -> Option<u32> {
+    let day = (ts - start_ts).div_euclid(DAY);
+    (0..i64::from(total_days))
+        .contains(&day)
+        .then_some(day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> SubmissionDate {
+        SubmissionDate::Text(value.to_string())
+    }
+
+    #[test]
+    fn reads_every_submission_date_form() {
+        let midnight = 1_735_689_600;
+        assert_eq!(SubmissionDate::Unix(midnight).unix(), Some(midnight));
+        assert_eq!(text(" 1735689600 ").unix(), Some(midnight));
+        assert_eq!(text("2025-01-01").unix(), Some(midnight));
+        assert_eq!(
+            text("2025-01-01T09:30:00.000Z").unix(),
+            Some(midnight + 9 * 3600 + 1800)
+        );
+        assert_eq!(
+            text("2025-01-01T09:30:00+02:00").unix(),
+            Some(midnight + 7 * 3600 + 1800)
+        );
+        assert_eq!(text("yesterday").unix(), None);
+        assert_eq!(text("yesterday").to_string(), "\"yesterday\"");
+
+        let parsed: Vec<SubmissionDate> = serde_json::from_str(r#"[1, "2025-01-01"]"#).unwrap();
+        assert_eq!(parsed, [SubmissionDate::Unix(1), text("2025-01-01")]);
+    }
+
+    #[test]
+    fn places_timestamps_on_challenge_days() {
+        let start = 1_735_689_600;
+        assert_eq!(challenge_day(start, 10, start), Some(0));
+        assert_eq!(challenge_day(start, 10, start + DAY - 1), Some(0));
+        assert_eq!(challenge_day(start, 10, start + 9 * DAY), Some(9));
+        assert_eq!(challenge_day(start, 10, start + 10 * DAY), None);
+        assert_eq!(challenge_day(start, 10, start - 1), None);
+    }
+}
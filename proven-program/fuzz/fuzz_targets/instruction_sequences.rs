@@ -0,0 +1,513 @@
+//! Drives arbitrary sequences of challenge instructions, from arbitrary
+//! signers at arbitrary times, against the program running in-process,
+//! and checks after every step that:
+//!
+//! - only the challenge's oracle moves `proof_days`, one day per proof;
+//! - a participant is paid, by payout or refund, at most once;
+//! - `status` only moves Created → Started → Ended → Settled, Created →
+//!   Ended when `settle_challenge` runs before any proof, or Created →
+//!   Cancelled, and the challenge closes only once settled or cancelled.
+//!
+//! Failed transactions are expected; only a broken invariant is a crash.
+
+#![no_main]
+
+use std::cell::RefCell;
+
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::AccountDeserialize;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account;
+use anchor_spl::token::spl_token;
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use proven_stake::{instruction, Challenge, ChallengeStatus, Participant, ID};
+use proven_stake_client::{instructions, pda};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::clock::Clock;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::entrypoint::ProgramResult;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use tokio::runtime::{Builder, Runtime};
+
+const USERS: usize = 4;
+const MAX_ACTIONS: usize = 48;
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+/// Tokens minted to every actor up front; stakes never come close.
+const FUNDING: u64 = 1 << 50;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    stake: u32,
+    total_days: u8,
+    threshold_bps: u16,
+    platform_fee_bps: u16,
+    actions: Vec<Action>,
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum Actor {
+    Admin,
+    Oracle,
+    Stranger,
+    User(u8),
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum Action {
+    /// Moves the clock forward.
+    Wait {
+        hours: u8,
+    },
+    Join {
+        user: u8,
+    },
+    RecordProof {
+        signer: Actor,
+        user: u8,
+    },
+    SettleChallenge {
+        signer: Actor,
+    },
+    SettleParticipant {
+        signer: Actor,
+        user: u8,
+    },
+    Finalize {
+        signer: Actor,
+    },
+    /// `claimant` claims a payout against `owner`'s participant account.
+    Claim {
+        claimant: Actor,
+        owner: u8,
+    },
+    /// `claimant` claims a refund against `owner`'s participant account.
+    Refund {
+        claimant: Actor,
+        owner: u8,
+    },
+    Cancel {
+        signer: Actor,
+    },
+    WithdrawFees {
+        signer: Actor,
+    },
+    Amend {
+        signer: Actor,
+        total_days: u8,
+        threshold_bps: u16,
+    },
+    UpdateOracle {
+        signer: Actor,
+        oracle: Actor,
+    },
+    CloseParticipant {
+        signer: Actor,
+        user: u8,
+    },
+    CloseChallenge {
+        signer: Actor,
+    },
+}
+
+fn user_index(user: u8) -> usize {
+    user as usize % USERS
+}
+
+/// `entry` ties its account slice to the accounts' own lifetime, which
+/// the `processor!` signature can't express; leaking the slice satisfies
+/// it.
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    proven_stake::entry(program_id, accounts, data)
+}
+
+/// A bank with the program, one mint and funded actors, shared by every
+/// input: each new bank leaks its service threads.
+struct Bank {
+    ctx: ProgramTestContext,
+    mint: Pubkey,
+    nonce: u64,
+    admin: Keypair,
+    oracle: Keypair,
+    stranger: Keypair,
+    users: Vec<Keypair>,
+    challenges: u64,
+}
+
+impl Bank {
+    async fn new() -> Self {
+        let program_test = ProgramTest::new("proven_stake", ID, processor!(process_instruction));
+        let mut bank = Self {
+            ctx: program_test.start_with_context().await,
+            mint: Pubkey::default(),
+            nonce: 0,
+            admin: Keypair::new(),
+            oracle: Keypair::new(),
+            stranger: Keypair::new(),
+            users: (0..USERS).map(|_| Keypair::new()).collect(),
+            challenges: 0,
+        };
+
+        let mint = Keypair::new();
+        let payer = bank.ctx.payer.pubkey();
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                Rent::default().minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                6,
+            )
+            .unwrap(),
+        ];
+        bank.send(&ixs, &[&mint]).await.unwrap();
+        bank.mint = mint.pubkey();
+
+        let mut actors = vec![
+            bank.admin.pubkey(),
+            bank.oracle.pubkey(),
+            bank.stranger.pubkey(),
+        ];
+        actors.extend(bank.users.iter().map(|u| u.pubkey()));
+        for actor in actors {
+            let ata = get_associated_token_address(&actor, &bank.mint);
+            let ixs = [
+                system_instruction::transfer(&payer, &actor, 1_000_000_000),
+                create_associated_token_account(&payer, &actor, &bank.mint, &spl_token::ID),
+                spl_token::instruction::mint_to(
+                    &spl_token::ID,
+                    &bank.mint,
+                    &ata,
+                    &payer,
+                    &[],
+                    FUNDING,
+                )
+                .unwrap(),
+            ];
+            bank.send(&ixs, &[]).await.unwrap();
+        }
+        bank
+    }
+
+    fn key(&self, actor: Actor) -> &Keypair {
+        match actor {
+            Actor::Admin => &self.admin,
+            Actor::Oracle => &self.oracle,
+            Actor::Stranger => &self.stranger,
+            Actor::User(user) => &self.users[user_index(user)],
+        }
+    }
+
+    async fn send(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        // Unique per transaction, so repeated instructions aren't deduplicated
+        self.nonce += 1;
+        let mut all = vec![ComputeBudgetInstruction::set_compute_unit_price(self.nonce)];
+        all.extend_from_slice(ixs);
+
+        let blockhash = self.ctx.banks_client.get_latest_blockhash().await?;
+        let mut keypairs = vec![&self.ctx.payer];
+        keypairs.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(
+            &all,
+            Some(&self.ctx.payer.pubkey()),
+            &keypairs,
+            blockhash,
+        );
+        self.ctx.banks_client.process_transaction(tx).await
+    }
+
+    async fn wait(&mut self, seconds: i64) {
+        let mut clock: Clock = self.ctx.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.ctx.set_sysvar(&clock);
+    }
+
+    async fn account<T: AccountDeserialize>(&mut self, address: Pubkey) -> Option<T> {
+        let account = self.ctx.banks_client.get_account(address).await.unwrap()?;
+        T::try_deserialize(&mut account.data.as_slice()).ok()
+    }
+
+    async fn balance(&mut self, owner: &Pubkey) -> u64 {
+        let address = get_associated_token_address(owner, &self.mint);
+        let account = self.ctx.banks_client.get_account(address).await.unwrap();
+        spl_token::state::Account::unpack(&account.unwrap().data)
+            .unwrap()
+            .amount
+    }
+
+    /// A new challenge for `input`, starting a day from now.
+    async fn create(&mut self, input: &Input) -> Option<Pubkey> {
+        self.challenges += 1;
+        let clock: Clock = self.ctx.banks_client.get_sysvar().await.unwrap();
+        let terms = instruction::CreateChallenge {
+            challenge_id: format!("fuzz-{}", self.challenges),
+            stake_amount: 1 + input.stake as u64 % 1_000_000,
+            total_days: 1 + input.total_days as u32 % 3,
+            threshold_bps: input.threshold_bps % 10_001,
+            platform_fee_bps: input.platform_fee_bps % 1_001,
+            start_ts: clock.unix_timestamp + DAY,
+            oracle_signer: self.oracle.pubkey(),
+            treasury_fee_bps: 0,
+            charity_fee_bps: 0,
+            charity: None,
+        };
+        let address = pda::challenge(&terms.challenge_id, &self.admin.pubkey());
+        let ix = instructions::create_challenge(self.admin.pubkey(), self.mint, terms);
+        let admin = self.admin.insecure_clone();
+        self.send(&[ix], &[&admin]).await.ok().map(|()| address)
+    }
+
+    async fn snapshot(&mut self, challenge: Pubkey) -> Snapshot {
+        let mut snapshot = Snapshot {
+            challenge: self.account(challenge).await,
+            proof_days: [None; USERS],
+            balances: [0; USERS],
+        };
+        for i in 0..USERS {
+            let user = self.users[i].pubkey();
+            let participant: Option<Participant> =
+                self.account(pda::participant(&challenge, &user)).await;
+            snapshot.proof_days[i] = participant.map(|p| p.proof_days);
+            snapshot.balances[i] = self.balance(&user).await;
+        }
+        snapshot
+    }
+
+    /// The transaction for `action` against `c`, with its signer, or
+    /// `None` if it needs an account that doesn't exist.
+    async fn build(&mut self, c: &Challenge, action: Action) -> Option<(Instruction, Keypair)> {
+        let address = pda::challenge_of(c);
+        // Admin instructions are built against a copy naming the signer
+        // as authority, so the program sees whoever signed
+        let as_authority = |signer: &Keypair| {
+            let mut forged = c.clone();
+            forged.authority = signer.pubkey();
+            forged
+        };
+        let users: Vec<Pubkey> = self.users.iter().map(|u| u.pubkey()).collect();
+        let redirect = |mut ix: Instruction, from: &Pubkey, owner: u8| {
+            let from = pda::participant(&address, from);
+            let to = pda::participant(&address, &users[user_index(owner)]);
+            for meta in ix.accounts.iter_mut().filter(|m| m.pubkey == from) {
+                meta.pubkey = to;
+            }
+            ix
+        };
+
+        let (ix, signer) = match action {
+            Action::Wait { .. } => return None,
+            Action::Join { user } => {
+                let user = self.key(Actor::User(user));
+                let ix = instructions::join_challenge(c, user.pubkey(), vec![], None);
+                (ix, user)
+            }
+            Action::RecordProof { signer, user } => {
+                let signer = self.key(signer);
+                let user = self.key(Actor::User(user)).pubkey();
+                (instructions::record_proof(c, signer.pubkey(), user), signer)
+            }
+            Action::SettleChallenge { signer } => {
+                let signer = self.key(signer);
+                (instructions::settle_challenge(c, signer.pubkey()), signer)
+            }
+            Action::SettleParticipant { signer, user } => {
+                let user = self.key(Actor::User(user)).pubkey();
+                let p: Participant = self.account(pda::participant(&address, &user)).await?;
+                let signer = self.key(signer);
+                (
+                    instructions::settle_participant(c, signer.pubkey(), &p),
+                    signer,
+                )
+            }
+            Action::Finalize { signer } => {
+                let signer = self.key(signer);
+                (
+                    instructions::finalize_settlement(c, signer.pubkey()),
+                    signer,
+                )
+            }
+            Action::Claim { claimant, owner } => {
+                let claimant = self.key(claimant);
+                let ix = instructions::claim_payout(c, claimant.pubkey());
+                (redirect(ix, &claimant.pubkey(), owner), claimant)
+            }
+            Action::Refund { claimant, owner } => {
+                let claimant = self.key(claimant);
+                let ix = instructions::claim_refund(c, claimant.pubkey());
+                (redirect(ix, &claimant.pubkey(), owner), claimant)
+            }
+            Action::Cancel { signer } => {
+                let signer = self.key(signer);
+                (instructions::cancel_challenge(c, signer.pubkey()), signer)
+            }
+            Action::WithdrawFees { signer } => {
+                let signer = self.key(signer);
+                (instructions::withdraw_fees(&as_authority(signer)), signer)
+            }
+            Action::Amend {
+                signer,
+                total_days,
+                threshold_bps,
+            } => {
+                let signer = self.key(signer);
+                let ix = instructions::amend_challenge(
+                    &as_authority(signer),
+                    1 + total_days as u32 % 4,
+                    threshold_bps % 10_001,
+                );
+                (ix, signer)
+            }
+            Action::UpdateOracle { signer, oracle } => {
+                let oracle = self.key(oracle).pubkey();
+                let signer = self.key(signer);
+                (
+                    instructions::update_oracle(&as_authority(signer), oracle),
+                    signer,
+                )
+            }
+            Action::CloseParticipant { signer, user } => {
+                let user = self.key(Actor::User(user)).pubkey();
+                let signer = self.key(signer);
+                (
+                    instructions::close_participant(c, signer.pubkey(), user),
+                    signer,
+                )
+            }
+            Action::CloseChallenge { signer } => {
+                let signer = self.key(signer);
+                (instructions::close_challenge(&as_authority(signer)), signer)
+            }
+        };
+        Some((ix, signer.insecure_clone()))
+    }
+}
+
+/// State the invariants are checked against, read before and after each
+/// step.
+struct Snapshot {
+    challenge: Option<Challenge>,
+    proof_days: [Option<u32>; USERS],
+    balances: [u64; USERS],
+}
+
+fn transition_allowed(from: &ChallengeStatus, to: Option<&ChallengeStatus>) -> bool {
+    use ChallengeStatus::*;
+    match (from, to) {
+        (from, None) => matches!(from, Settled | Cancelled),
+        (from, Some(to)) if from == to => true,
+        (Created, Some(Started | Ended | Cancelled)) | (Started, Some(Ended)) => true,
+        (Ended, Some(Settled)) => true,
+        _ => false,
+    }
+}
+
+fn status_name(status: Option<&ChallengeStatus>) -> &'static str {
+    match status {
+        Some(ChallengeStatus::Created) => "Created",
+        Some(ChallengeStatus::Started) => "Started",
+        Some(ChallengeStatus::Ended) => "Ended",
+        Some(ChallengeStatus::Settled) => "Settled",
+        Some(ChallengeStatus::Cancelled) => "Cancelled",
+        None => "closed",
+    }
+}
+
+async fn play(bank: &mut Bank, input: Input) {
+    let Some(challenge) = bank.create(&input).await else {
+        return;
+    };
+    let mut paid = [false; USERS];
+    let mut before = bank.snapshot(challenge).await;
+
+    for &action in input.actions.iter().take(MAX_ACTIONS) {
+        let Some(c) = before.challenge.clone() else {
+            break;
+        };
+        let ok = match action {
+            Action::Wait { hours } => {
+                bank.wait((hours as i64 + 1) * HOUR).await;
+                false
+            }
+            _ => match bank.build(&c, action).await {
+                Some((ix, signer)) => bank.send(&[ix], &[&signer]).await.is_ok(),
+                None => false,
+            },
+        };
+        let after = bank.snapshot(challenge).await;
+
+        let to = after.challenge.as_ref().map(|c| &c.status);
+        assert!(
+            transition_allowed(&c.status, to),
+            "{action:?} moved {} to {}",
+            status_name(Some(&c.status)),
+            status_name(to)
+        );
+
+        for i in 0..USERS {
+            let (Some(old), Some(new)) = (before.proof_days[i], after.proof_days[i]) else {
+                continue;
+            };
+            if old == new {
+                continue;
+            }
+            let proved = match action {
+                Action::RecordProof { signer, user } => {
+                    ok && user_index(user) == i && bank.key(signer).pubkey() == c.oracle_signer
+                }
+                _ => false,
+            };
+            assert!(
+                proved && new == old + 1,
+                "{action:?} moved user {i} from {old} to {new} proof days"
+            );
+        }
+
+        let balances = before.balances.iter().zip(&after.balances);
+        for (i, (old, new)) in balances.enumerate() {
+            if new > old {
+                assert!(!paid[i], "{action:?} paid user {i} a second time");
+                paid[i] = true;
+            }
+        }
+        before = after;
+    }
+}
+
+thread_local! {
+    static BANK: RefCell<Option<(Runtime, Bank)>> = const { RefCell::new(None) };
+}
+
+fuzz_target!(|input: Input| {
+    BANK.with(|bank| {
+        let mut bank = bank.borrow_mut();
+        let (runtime, bank) = bank.get_or_insert_with(|| {
+            let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+            let bank = runtime.block_on(Bank::new());
+            (runtime, bank)
+        });
+        runtime.block_on(play(bank, input));
+    });
+});
@@ -0,0 +1,238 @@
+//! Where transactions come from: a JSON RPC node, or `getTransaction`
+//! responses saved to disk.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use proven_stake_client::PROGRAM_ID;
+use serde_json::Value;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiTransactionEncoding,
+};
+
+/// The parts of a confirmed transaction the indexer needs.
+#[derive(Clone, Debug)]
+pub struct IndexedTx {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// Failed transactions are rolled back, events and all.
+    pub failed: bool,
+    pub logs: Vec<String>,
+}
+
+impl IndexedTx {
+    pub fn from_encoded(tx: EncodedConfirmedTransactionWithStatusMeta) -> Result<Self> {
+        let signature = match &tx.transaction.transaction {
+            EncodedTransaction::Json(ui) => ui.signatures.first().cloned(),
+            EncodedTransaction::Accounts(ui) => ui.signatures.first().cloned(),
+            encoded => encoded
+                .decode()
+                .and_then(|decoded| decoded.signatures.first().map(ToString::to_string)),
+        }
+        .ok_or_else(|| anyhow!("transaction at slot {} has no signature", tx.slot))?;
+        let meta = tx
+            .transaction
+            .meta
+            .ok_or_else(|| anyhow!("transaction {signature} has no status meta"))?;
+        let logs: Option<Vec<String>> = meta.log_messages.into();
+
+        Ok(Self {
+            signature,
+            slot: tx.slot,
+            block_time: tx.block_time,
+            failed: meta.err.is_some(),
+            logs: logs.unwrap_or_default(),
+        })
+    }
+
+    /// Parses one saved `getTransaction` result, either bare or still
+    /// wrapped in its JSON-RPC response.
+    pub fn from_json(value: Value) -> Result<Self> {
+        let value = match value {
+            Value::Object(mut response) if response.contains_key("result") => {
+                response.remove("result").unwrap()
+            }
+            value => value,
+        };
+        if value.is_null() {
+            bail!("transaction not found");
+        }
+        Self::from_encoded(serde_json::from_value(value)?)
+    }
+}
+
+/// Reads fixtures from `paths`, descending one level into directories
+/// for their `.json` files. A file holds one transaction or an array of
+/// them. Results are in slot order.
+pub fn fixtures(paths: &[PathBuf]) -> Result<Vec<IndexedTx>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in
+                fs::read_dir(path).with_context(|| format!("reading {}", path.display()))?
+            {
+                let entry = entry?.path();
+                if entry.extension().is_some_and(|ext| ext == "json") {
+                    entries.push(entry);
+                }
+            }
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut txs = Vec::new();
+    for file in &files {
+        txs.extend(read_fixture(file).with_context(|| format!("loading {}", file.display()))?);
+    }
+    // Stable, so same-slot transactions keep their file order
+    txs.sort_by_key(|tx| tx.slot);
+    Ok(txs)
+}
+
+fn read_fixture(path: &Path) -> Result<Vec<IndexedTx>> {
+    let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    match value {
+        Value::Array(values) => values.into_iter().map(IndexedTx::from_json).collect(),
+        value => Ok(vec![IndexedTx::from_json(value)?]),
+    }
+}
+
+/// Fetches every program transaction newer than `until` (all of them when
+/// `None`), oldest first, along with the newest signature seen to resume
+/// from next time. Signatures that failed are skipped without fetching the
+/// transaction.
+pub fn rpc(rpc: &RpcClient, until: Option<&str>) -> Result<(Vec<IndexedTx>, Option<String>)> {
+    let until = until.map(Signature::from_str).transpose()?;
+    let mut signatures = Vec::new();
+    let mut before = None;
+    loop {
+        let page = rpc
+            .get_signatures_for_address_with_config(
+                &PROGRAM_ID,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until,
+                    limit: None,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .context("listing program signatures")?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        before = Some(Signature::from_str(&last.signature)?);
+        signatures.extend(page);
+    }
+
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    // Pages run newest to oldest
+    let mut txs = Vec::new();
+    for status in signatures
+        .iter()
+        .rev()
+        .filter(|status| status.err.is_none())
+    {
+        let signature = Signature::from_str(&status.signature)?;
+        let tx = rpc
+            .get_transaction_with_config(&signature, config)
+            .with_context(|| format!("fetching {signature}"))?;
+        txs.push(IndexedTx::from_encoded(tx)?);
+    }
+    Ok((
+        txs,
+        signatures.first().map(|status| status.signature.clone()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::{env, slice};
+
+    /// A `getTransaction` result in `json` encoding, trimmed to what the
+    /// indexer and the decoder need.
+    fn response(signature: &str, slot: u64, err: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": {
+                "slot": slot,
+                "blockTime": 1_800_000_000,
+                "version": "legacy",
+                "transaction": {
+                    "signatures": [signature],
+                    "message": {
+                        "header": {
+                            "numRequiredSignatures": 1,
+                            "numReadonlySignedAccounts": 0,
+                            "numReadonlyUnsignedAccounts": 1
+                        },
+                        "accountKeys": [PROGRAM_ID.to_string()],
+                        "recentBlockhash": "11111111111111111111111111111111",
+                        "instructions": []
+                    }
+                },
+                "meta": {
+                    "err": err,
+                    "status": if err.is_null() { json!({"Ok": null}) } else { json!({"Err": err}) },
+                    "fee": 5000,
+                    "preBalances": [],
+                    "postBalances": [],
+                    "logMessages": [format!("Program {PROGRAM_ID} invoke [1]")]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn parses_rpc_responses_and_bare_results() {
+        let tx = IndexedTx::from_json(response("sig", 7, Value::Null)).unwrap();
+        assert_eq!((tx.signature.as_str(), tx.slot), ("sig", 7));
+        assert_eq!(tx.block_time, Some(1_800_000_000));
+        assert!(!tx.failed);
+        assert_eq!(tx.logs.len(), 1);
+
+        let bare = response("sig", 7, Value::Null)["result"].clone();
+        assert_eq!(IndexedTx::from_json(bare).unwrap().signature, "sig");
+
+        let failed = response("sig", 7, json!({"InstructionError": [0, {"Custom": 6000}]}));
+        assert!(IndexedTx::from_json(failed).unwrap().failed);
+
+        assert!(IndexedTx::from_json(json!({"jsonrpc": "2.0", "result": null})).is_err());
+    }
+
+    #[test]
+    fn loads_fixture_directories_in_slot_order() {
+        let dir = env::temp_dir().join(format!("proven-indexer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let batch = json!([response("c", 9, Value::Null), response("a", 3, Value::Null)]);
+        fs::write(dir.join("batch.json"), batch.to_string()).unwrap();
+        fs::write(
+            dir.join("single.json"),
+            response("b", 5, Value::Null).to_string(),
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a fixture").unwrap();
+
+        let txs = fixtures(slice::from_ref(&dir)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let order: Vec<_> = txs.iter().map(|tx| tx.signature.as_str()).collect();
+        assert_eq!(order, ["a", "b", "c"]);
+    }
+}
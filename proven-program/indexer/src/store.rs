@@ -0,0 +1,656 @@
+//! The SQLite side: schema, and folding each transaction's events into
+//! challenge, participant and cash flow rows.
+//!
+//! Every event is kept in `events`, payload included. Only challenges
+//! whose creation was indexed are materialized; duels, commitments and
+//! challenges created before the indexed history stay in `events` alone,
+//! to be decoded with `ProvenEvent::decode`. Cash flows are signed
+//! from the escrow's point of view, so summing them per challenge gives
+//! what its escrow should hold.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use proven_stake_client::events::{decode_logs, ProvenEvent};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::source::IndexedTx;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS cursor (
+    source TEXT PRIMARY KEY,
+    signature TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER
+);
+CREATE TABLE IF NOT EXISTS events (
+    signature TEXT NOT NULL REFERENCES transactions (signature),
+    idx INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- Discriminator and Borsh encoding, as logged
+    data BLOB NOT NULL,
+    PRIMARY KEY (signature, idx)
+);
+CREATE TABLE IF NOT EXISTS challenges (
+    address TEXT PRIMARY KEY,
+    admin TEXT NOT NULL,
+    stake_amount INTEGER NOT NULL,
+    total_days INTEGER NOT NULL,
+    start_ts INTEGER NOT NULL,
+    -- created, ended, settled or cancelled; starting isn't an event
+    status TEXT NOT NULL,
+    participant_count INTEGER NOT NULL DEFAULT 0,
+    required_days INTEGER,
+    winner_count INTEGER,
+    loser_count INTEGER,
+    bonus_per_winner INTEGER,
+    created_slot INTEGER NOT NULL,
+    closed INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS participants (
+    challenge TEXT NOT NULL REFERENCES challenges (address),
+    user TEXT NOT NULL,
+    stake_deposited INTEGER NOT NULL,
+    proof_days INTEGER NOT NULL DEFAULT 0,
+    team TEXT,
+    -- NULL until settled
+    is_winner INTEGER,
+    payout INTEGER NOT NULL DEFAULT 0,
+    refund INTEGER NOT NULL DEFAULT 0,
+    closed INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (challenge, user)
+);
+CREATE TABLE IF NOT EXISTS cash_flows (
+    signature TEXT NOT NULL REFERENCES transactions (signature),
+    slot INTEGER NOT NULL,
+    challenge TEXT NOT NULL REFERENCES challenges (address),
+    wallet TEXT NOT NULL,
+    -- deposit, payout, refund, fee, fee_share, forfeit, referral,
+    -- rollover_in or rollover_out
+    kind TEXT NOT NULL,
+    -- Positive into the escrow, negative out of it
+    amount INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS cash_flows_challenge ON cash_flows (challenge);
+";
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("opening {}", path.display()))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    #[cfg(test)]
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Newest signature indexed from `source`, to resume from.
+    pub fn cursor(&self, source: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT signature FROM cursor WHERE source = ?1",
+                [source],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_cursor(&self, source: &str, signature: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO cursor (source, signature) VALUES (?1, ?2)
+             ON CONFLICT (source) DO UPDATE SET signature = excluded.signature",
+            [source, signature],
+        )?;
+        Ok(())
+    }
+
+    /// Applies `tx` atomically. Returns `false` without touching anything
+    /// when it failed on chain or was already indexed.
+    pub fn apply(&mut self, tx: &IndexedTx) -> Result<bool> {
+        if tx.failed {
+            return Ok(false);
+        }
+        let db = self.conn.transaction()?;
+        let inserted = db.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time) VALUES (?1, ?2, ?3)",
+            params![tx.signature, tx.slot as i64, tx.block_time],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        for (idx, event) in decode_logs(&tx.logs).into_iter().enumerate() {
+            let event =
+                event.with_context(|| format!("decoding event {idx} of {}", tx.signature))?;
+            db.execute(
+                "INSERT INTO events (signature, idx, name, data) VALUES (?1, ?2, ?3, ?4)",
+                params![tx.signature, idx as i64, event.name(), event.data()],
+            )?;
+            Fold {
+                db: &db,
+                signature: &tx.signature,
+                slot: tx.slot as i64,
+            }
+            .event(&event)
+            .with_context(|| format!("applying {} from {}", event.name(), tx.signature))?;
+        }
+        db.commit()?;
+        Ok(true)
+    }
+}
+
+/// Folds one transaction's events into the materialized tables.
+struct Fold<'a> {
+    db: &'a Transaction<'a>,
+    signature: &'a str,
+    slot: i64,
+}
+
+impl Fold<'_> {
+    fn event(&self, event: &ProvenEvent) -> Result<()> {
+        match event {
+            ProvenEvent::ChallengeCreated(e) => {
+                self.db.execute(
+                    "INSERT OR IGNORE INTO challenges
+                     (address, admin, stake_amount, total_days, start_ts, status, created_slot)
+                     VALUES (?1, ?2, ?3, ?4, ?5, 'created', ?6)",
+                    params![
+                        key(&e.challenge_id),
+                        key(&e.admin),
+                        amount(e.stake_amount)?,
+                        e.total_days,
+                        e.start_ts,
+                        self.slot
+                    ],
+                )?;
+            }
+            ProvenEvent::ChallengeJoined(e) => {
+                self.join(&e.challenge_id, &e.user, e.stake_amount)?;
+                self.flow(&e.challenge_id, &e.user, "deposit", e.stake_amount, 1)?;
+            }
+            ProvenEvent::TeamJoined(e) => {
+                self.db.execute(
+                    "UPDATE participants SET team = ?3 WHERE challenge = ?1 AND user = ?2",
+                    params![key(&e.challenge_id), key(&e.user), key(&e.team)],
+                )?;
+            }
+            ProvenEvent::StakeRolledOver(e) => {
+                // The next stake moves between escrows, the rest is paid out
+                self.db.execute(
+                    "UPDATE participants SET payout = payout + ?3 WHERE challenge = ?1 AND user = ?2",
+                    params![
+                        key(&e.from_challenge),
+                        key(&e.user),
+                        amount(e.payout_amount)?
+                    ],
+                )?;
+                let excess = e.payout_amount.saturating_sub(e.rolled_amount);
+                self.flow(&e.from_challenge, &e.user, "payout", excess, -1)?;
+                self.flow(
+                    &e.from_challenge,
+                    &e.user,
+                    "rollover_out",
+                    e.rolled_amount,
+                    -1,
+                )?;
+                self.join(&e.to_challenge, &e.user, e.rolled_amount)?;
+                self.flow(&e.to_challenge, &e.user, "rollover_in", e.rolled_amount, 1)?;
+            }
+            ProvenEvent::ProofRecorded(e) => {
+                self.db.execute(
+                    "UPDATE participants SET proof_days = ?3 WHERE challenge = ?1 AND user = ?2",
+                    params![key(&e.challenge_id), key(&e.user), e.proof_days],
+                )?;
+            }
+            ProvenEvent::ChallengeAmended(e) => {
+                self.db.execute(
+                    "UPDATE challenges SET total_days = ?2 WHERE address = ?1",
+                    params![key(&e.challenge_id), e.new_total_days],
+                )?;
+            }
+            ProvenEvent::AdminTransferred(e) => {
+                self.db.execute(
+                    "UPDATE challenges SET admin = ?2 WHERE address = ?1",
+                    params![key(&e.challenge_id), key(&e.new_admin)],
+                )?;
+            }
+            ProvenEvent::ChallengeSettlementStarted(e) => {
+                self.db.execute(
+                    "UPDATE challenges SET status = 'ended', required_days = ?2 WHERE address = ?1",
+                    params![key(&e.challenge_id), e.required_days],
+                )?;
+            }
+            ProvenEvent::ParticipantSettled(e) => {
+                self.db.execute(
+                    "UPDATE participants SET is_winner = ?3, proof_days = ?4
+                     WHERE challenge = ?1 AND user = ?2",
+                    params![
+                        key(&e.challenge_id),
+                        key(&e.user),
+                        e.is_winner,
+                        e.proof_days
+                    ],
+                )?;
+            }
+            ProvenEvent::TeamSettled(e) => {
+                self.db.execute(
+                    "UPDATE participants SET is_winner = ?2 WHERE challenge = ?1 AND team = ?3",
+                    params![key(&e.challenge_id), e.is_winner, key(&e.team)],
+                )?;
+            }
+            ProvenEvent::ChallengeSettled(e) => {
+                self.db.execute(
+                    "UPDATE challenges
+                     SET status = 'settled', winner_count = ?2, loser_count = ?3,
+                         bonus_per_winner = ?4
+                     WHERE address = ?1",
+                    params![
+                        key(&e.challenge_id),
+                        e.winner_count,
+                        e.loser_count,
+                        amount(e.bonus_per_winner)?
+                    ],
+                )?;
+            }
+            ProvenEvent::ChallengeCancelled(e) => {
+                self.db.execute(
+                    "UPDATE challenges SET status = 'cancelled' WHERE address = ?1",
+                    [key(&e.challenge_id)],
+                )?;
+            }
+            ProvenEvent::PayoutClaimed(e) => {
+                self.db.execute(
+                    "UPDATE participants SET payout = payout + ?3 WHERE challenge = ?1 AND user = ?2",
+                    params![key(&e.challenge_id), key(&e.user), amount(e.amount)?],
+                )?;
+                self.flow(&e.challenge_id, &e.user, "payout", e.amount, -1)?;
+            }
+            ProvenEvent::RefundClaimed(e) => {
+                self.db.execute(
+                    "UPDATE participants SET refund = refund + ?3 WHERE challenge = ?1 AND user = ?2",
+                    params![key(&e.challenge_id), key(&e.user), amount(e.amount)?],
+                )?;
+                self.flow(&e.challenge_id, &e.user, "refund", e.amount, -1)?;
+            }
+            ProvenEvent::FeeWithdrawn(e) => {
+                self.flow(&e.challenge_id, &e.admin, "fee", e.amount, -1)?;
+            }
+            ProvenEvent::FeeShareWithdrawn(e) => {
+                self.flow(&e.challenge_id, &e.recipient, "fee_share", e.amount, -1)?;
+            }
+            ProvenEvent::StakesForfeited(e) => {
+                self.flow(&e.challenge_id, &e.beneficiary, "forfeit", e.amount, -1)?;
+            }
+            ProvenEvent::ReferralRewardClaimed(e) => {
+                self.flow(&e.challenge_id, &e.referrer, "referral", e.amount, -1)?;
+            }
+            ProvenEvent::ParticipantClosed(e) => {
+                self.db.execute(
+                    "UPDATE participants SET closed = 1 WHERE challenge = ?1 AND user = ?2",
+                    params![key(&e.challenge_id), key(&e.user)],
+                )?;
+            }
+            ProvenEvent::ChallengeClosed(e) => {
+                self.db.execute(
+                    "UPDATE challenges SET closed = 1 WHERE address = ?1",
+                    [key(&e.challenge_id)],
+                )?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Adds a participant to a known challenge.
+    fn join(&self, challenge: &Pubkey, user: &Pubkey, stake: u64) -> Result<()> {
+        self.db.execute(
+            "INSERT INTO participants (challenge, user, stake_deposited)
+             SELECT address, ?2, ?3 FROM challenges WHERE address = ?1",
+            params![key(challenge), key(user), amount(stake)?],
+        )?;
+        self.db.execute(
+            "UPDATE challenges SET participant_count = participant_count + 1 WHERE address = ?1",
+            [key(challenge)],
+        )?;
+        Ok(())
+    }
+
+    /// Records `value` moving into (`sign` 1) or out of (-1) a known
+    /// challenge's escrow.
+    fn flow(
+        &self,
+        challenge: &Pubkey,
+        wallet: &Pubkey,
+        kind: &str,
+        value: u64,
+        sign: i64,
+    ) -> Result<()> {
+        if value == 0 {
+            return Ok(());
+        }
+        self.db.execute(
+            "INSERT INTO cash_flows (signature, slot, challenge, wallet, kind, amount)
+             SELECT ?1, ?2, address, ?4, ?5, ?6 FROM challenges WHERE address = ?3",
+            params![
+                self.signature,
+                self.slot,
+                key(challenge),
+                key(wallet),
+                kind,
+                sign * amount(value)?
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn key(key: &Pubkey) -> String {
+    key.to_string()
+}
+
+/// SQLite integers are signed, so amounts past `i64::MAX` are refused
+/// rather than wrapped.
+fn amount(value: u64) -> Result<i64> {
+    i64::try_from(value).with_context(|| format!("amount {value} does not fit SQLite"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+    use proven_stake_client::program::*;
+    use proven_stake_client::PROGRAM_ID;
+    use solana_sdk::bs58;
+    use std::slice;
+
+    /// A successful transaction emitting `events`, in slot order.
+    fn tx(slot: u64, events: &[Vec<u8>]) -> IndexedTx {
+        let mut logs = vec![format!("Program {PROGRAM_ID} invoke [1]")];
+        logs.extend(
+            events
+                .iter()
+                .map(|data| format!("Program data: {}", base64(data))),
+        );
+        logs.push(format!("Program {PROGRAM_ID} success"));
+        IndexedTx {
+            signature: bs58::encode(slot.to_le_bytes()).into_string(),
+            slot,
+            block_time: None,
+            failed: false,
+            logs,
+        }
+    }
+
+    fn base64(data: &[u8]) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD.encode(data)
+    }
+
+    fn created(challenge: Pubkey, admin: Pubkey) -> Vec<u8> {
+        ChallengeCreated {
+            challenge_id: challenge,
+            admin,
+            stake_amount: 1_000,
+            total_days: 10,
+            start_ts: 1_800_000_000,
+        }
+        .data()
+    }
+
+    fn joined(challenge: Pubkey, user: Pubkey) -> Vec<u8> {
+        ChallengeJoined {
+            challenge_id: challenge,
+            user,
+            stake_amount: 1_000,
+        }
+        .data()
+    }
+
+    fn query<T: rusqlite::types::FromSql>(store: &Store, sql: &str) -> T {
+        store
+            .connection()
+            .query_row(sql, [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn escrow(store: &Store, challenge: &Pubkey) -> i64 {
+        store
+            .connection()
+            .query_row(
+                "SELECT COALESCE(SUM(amount), 0) FROM cash_flows WHERE challenge = ?1",
+                [challenge.to_string()],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn folds_a_challenge_from_creation_to_close() {
+        let mut store = Store::in_memory().unwrap();
+        let (challenge, admin) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let txs = [
+            tx(1, &[created(challenge, admin)]),
+            tx(2, &[joined(challenge, alice), joined(challenge, bob)]),
+            tx(
+                3,
+                &[ProofRecorded {
+                    challenge_id: challenge,
+                    user: alice,
+                    proof_days: 9,
+                }
+                .data()],
+            ),
+            tx(
+                4,
+                &[
+                    ChallengeSettlementStarted {
+                        challenge_id: challenge,
+                        required_days: 8,
+                    }
+                    .data(),
+                    ParticipantSettled {
+                        challenge_id: challenge,
+                        user: alice,
+                        is_winner: true,
+                        proof_days: 9,
+                        required_days: 8,
+                    }
+                    .data(),
+                    ParticipantSettled {
+                        challenge_id: challenge,
+                        user: bob,
+                        is_winner: false,
+                        proof_days: 2,
+                        required_days: 8,
+                    }
+                    .data(),
+                    ChallengeSettled {
+                        challenge_id: challenge,
+                        winner_count: 1,
+                        loser_count: 1,
+                        bonus_per_winner: 950,
+                    }
+                    .data(),
+                ],
+            ),
+            tx(
+                5,
+                &[
+                    PayoutClaimed {
+                        challenge_id: challenge,
+                        user: alice,
+                        amount: 1_950,
+                    }
+                    .data(),
+                    FeeWithdrawn {
+                        challenge_id: challenge,
+                        admin,
+                        amount: 50,
+                    }
+                    .data(),
+                ],
+            ),
+            tx(
+                6,
+                &[
+                    ParticipantClosed {
+                        challenge_id: challenge,
+                        user: bob,
+                        closed_by: bob,
+                    }
+                    .data(),
+                    ChallengeClosed {
+                        challenge_id: challenge,
+                        admin,
+                    }
+                    .data(),
+                ],
+            ),
+        ];
+        for tx in &txs {
+            assert!(store.apply(tx).unwrap());
+        }
+
+        assert_eq!(
+            query::<String>(&store, "SELECT status FROM challenges"),
+            "settled"
+        );
+        assert_eq!(
+            query::<i64>(&store, "SELECT participant_count FROM challenges"),
+            2
+        );
+        assert_eq!(query::<i64>(&store, "SELECT closed FROM challenges"), 1);
+        let (days, won, payout): (i64, bool, i64) = store
+            .connection()
+            .query_row(
+                "SELECT proof_days, is_winner, payout FROM participants WHERE user = ?1",
+                [alice.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((days, won, payout), (9, true, 1_950));
+        assert_eq!(
+            query::<i64>(&store, "SELECT COUNT(*) FROM participants WHERE closed = 1"),
+            1
+        );
+        // Two deposits out as one payout and the fee
+        assert_eq!(query::<i64>(&store, "SELECT COUNT(*) FROM cash_flows"), 4);
+        assert_eq!(escrow(&store, &challenge), 0);
+        assert_eq!(query::<i64>(&store, "SELECT COUNT(*) FROM events"), 12);
+    }
+
+    #[test]
+    fn replays_and_failed_transactions_change_nothing() {
+        let mut store = Store::in_memory().unwrap();
+        let challenge = Pubkey::new_unique();
+        let create = tx(1, &[created(challenge, Pubkey::new_unique())]);
+        let join = tx(2, &[joined(challenge, Pubkey::new_unique())]);
+        assert!(store.apply(&create).unwrap());
+        assert!(store.apply(&join).unwrap());
+        assert!(!store.apply(&join).unwrap());
+
+        let mut failed = tx(3, &[joined(challenge, Pubkey::new_unique())]);
+        failed.failed = true;
+        assert!(!store.apply(&failed).unwrap());
+
+        assert_eq!(
+            query::<i64>(&store, "SELECT participant_count FROM challenges"),
+            1
+        );
+        assert_eq!(escrow(&store, &challenge), 1_000);
+        assert_eq!(query::<i64>(&store, "SELECT COUNT(*) FROM transactions"), 2);
+    }
+
+    #[test]
+    fn unknown_challenges_are_only_logged() {
+        let mut store = Store::in_memory().unwrap();
+        let challenge = Pubkey::new_unique();
+        let event = joined(challenge, Pubkey::new_unique());
+        assert!(store.apply(&tx(1, slice::from_ref(&event))).unwrap());
+
+        assert_eq!(query::<i64>(&store, "SELECT COUNT(*) FROM participants"), 0);
+        assert_eq!(query::<i64>(&store, "SELECT COUNT(*) FROM cash_flows"), 0);
+        assert_eq!(
+            query::<String>(&store, "SELECT name FROM events"),
+            "ChallengeJoined"
+        );
+        // Still recoverable in full from the log
+        assert_eq!(query::<Vec<u8>>(&store, "SELECT data FROM events"), event);
+    }
+
+    #[test]
+    fn rollovers_move_the_stake_between_escrows() {
+        let mut store = Store::in_memory().unwrap();
+        let (first, next) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (admin, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        store
+            .apply(&tx(
+                1,
+                &[
+                    created(first, admin),
+                    created(next, admin),
+                    joined(first, user),
+                ],
+            ))
+            .unwrap();
+        let rolled = StakeRolledOver {
+            from_challenge: first,
+            to_challenge: next,
+            user,
+            payout_amount: 1_000,
+            rolled_amount: 1_000,
+        };
+        store.apply(&tx(2, &[rolled.data()])).unwrap();
+
+        assert_eq!(escrow(&store, &first), 0);
+        assert_eq!(escrow(&store, &next), 1_000);
+        assert_eq!(
+            query::<i64>(
+                &store,
+                "SELECT COUNT(*) FROM participants WHERE payout = 1000"
+            ),
+            1
+        );
+        // A payout that exactly covers the next stake leaves no payout leg
+        assert_eq!(
+            query::<i64>(
+                &store,
+                "SELECT COUNT(*) FROM cash_flows WHERE kind = 'payout'"
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn cursor_is_kept_per_source() {
+        let store = Store::in_memory().unwrap();
+        assert_eq!(store.cursor("a").unwrap(), None);
+        store.set_cursor("a", "1").unwrap();
+        store.set_cursor("a", "2").unwrap();
+        store.set_cursor("b", "3").unwrap();
+        assert_eq!(store.cursor("a").unwrap().as_deref(), Some("2"));
+        assert_eq!(store.cursor("b").unwrap().as_deref(), Some("3"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Approval messages, as the backend sends them.
+//!
+//! A message names the challenge, the participant's wallet, and either the
+//! challenge day directly or the submission's date, which is placed on a
+//! day once the challenge's start is known. Either way the day must fall
+//! within the challenge. A body holds one message or an array of them.
+
+use anyhow::{anyhow, bail, Result};
+use proven_stake_client::tooling::{self, SubmissionDate};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum When {
+    /// Zero-based challenge day.
+    Day(u32),
+    /// Unix timestamp of the submission.
+    At(i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Approval {
+    pub challenge: Pubkey,
+    pub user: Pubkey,
+    pub when: When,
+}
+
+impl Approval {
+    /// The challenge day this approval counts for, or `None` when it falls
+    /// outside a challenge of `total_days` days starting at `start_ts`.
+    pub fn day(&self, start_ts: i64, total_days: u32) -> Option<u32> {
+        match self.when {
+            When::Day(day) => (day < total_days).then_some(day),
+            When::At(ts) => tooling::challenge_day(start_ts, total_days, ts),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Message {
+    challenge: String,
+    #[serde(alias = "walletAddress", alias = "user")]
+    wallet: String,
+    #[serde(default)]
+    day: Option<u32>,
+    #[serde(default)]
+    submission_date: Option<SubmissionDate>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Body {
+    One(Message),
+    Many(Vec<Message>),
+}
+
+fn pubkey(field: &str, value: &str) -> Result<Pubkey> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid {field} {value:?}"))
+}
+
+impl Message {
+    fn approval(self) -> Result<Approval> {
+        let when = match (self.day, self.submission_date) {
+            (Some(day), _) => When::Day(day),
+            (None, Some(date)) => When::At(
+                date.unix()
+                    .ok_or_else(|| anyhow!("invalid submissionDate {date}"))?,
+            ),
+            (None, None) => bail!("approval needs a day or a submissionDate"),
+        };
+        Ok(Approval {
+            challenge: pubkey("challenge", &self.challenge)?,
+            user: pubkey("wallet", &self.wallet)?,
+            when,
+        })
+    }
+}
+
+/// Parses a message body: one approval, or an array of them.
+pub fn parse(body: &str) -> Result<Vec<Approval>> {
+    match serde_json::from_str(body)? {
+        Body::One(message) => Ok(vec![message.approval()?]),
+        Body::Many(messages) => messages.into_iter().map(Message::approval).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_messages_and_arrays() {
+        let (challenge, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let one = format!(r#"{{"challenge": "{challenge}", "wallet": "{user}", "day": 3}}"#);
+        assert_eq!(
+            parse(&one).unwrap(),
+            [Approval {
+                challenge,
+                user,
+                when: When::Day(3)
+            }]
+        );
+
+        let many = format!(
+            r#"[{{"challenge": "{challenge}", "walletAddress": "{user}",
+                  "submissionDate": "2025-01-02T09:30:00.000Z"}},
+                {{"challenge": "{challenge}", "user": "{user}", "submissionDate": 1735689600}},
+                {{"challenge": "{challenge}", "user": "{user}", "submissionDate": "2025-01-01"}}]"#
+        );
+        let whens: Vec<_> = parse(&many).unwrap().iter().map(|a| a.when).collect();
+        assert_eq!(
+            whens,
+            [
+                When::At(1_735_810_200),
+                When::At(1_735_689_600),
+                When::At(1_735_689_600)
+            ]
+        );
+
+        assert!(parse(&format!(
+            r#"{{"challenge": "{challenge}", "wallet": "{user}"}}"#
+        ))
+        .is_err());
+        assert!(parse(&format!(
+            r#"{{"challenge": "nope", "wallet": "{user}", "day": 0}}"#
+        ))
+        .is_err());
+        assert!(parse("not json").is_err());
+    }
+
+    #[test]
+    fn places_submissions_on_challenge_days() {
+        const DAY: i64 = 24 * 60 * 60;
+        let start = 1_735_689_600;
+        let at = |when| Approval {
+            challenge: Pubkey::default(),
+            user: Pubkey::default(),
+            when,
+        };
+        assert_eq!(at(When::At(start)).day(start, 30), Some(0));
+        assert_eq!(at(When::At(start + DAY - 1)).day(start, 30), Some(0));
+        assert_eq!(at(When::At(start + 2 * DAY)).day(start, 30), Some(2));
+        assert_eq!(at(When::At(start - 1)).day(start, 30), None);
+        assert_eq!(at(When::At(start + 30 * DAY)).day(start, 30), None);
+        assert_eq!(at(When::Day(7)).day(start, 30), Some(7));
+        assert_eq!(at(When::Day(29)).day(start, 30), Some(29));
+        assert_eq!(at(When::Day(45)).day(start, 30), None);
+    }
+}
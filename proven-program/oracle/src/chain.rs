@@ -0,0 +1,362 @@
+//! Sending journaled approvals as batched `record_proof` transactions.
+//!
+//! A batch holds approvals for one challenge, so its accounts overlap, and
+//! is split further if it still wouldn't fit in a packet. It is signed
+//! against a fresh blockhash and journaled as sent before it is submitted,
+//! then polled until it confirms, fails, or outlives its blockhash; only the
+//! last is retried, with a new blockhash. A batch the program rejects is
+//! split so one bad approval can't hold up the rest.
+
+use std::collections::{hash_map, HashMap};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use proven_stake_client::program::{Challenge, ChallengeStatus};
+use proven_stake_client::{accounts, instructions, tooling};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_transaction_status::TransactionStatus;
+
+use crate::journal::{Entry, Journal};
+
+/// Most `record_proof` instructions for one challenge that fit in a
+/// transaction, registry included.
+pub const MAX_BATCH: usize = 20;
+
+const POLL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Landed,
+    Failed(TransactionError),
+    /// Its blockhash expired before it landed, so it never will.
+    Expired,
+    InFlight,
+}
+
+/// Where a transaction stands, from its status and a block height read
+/// *before* the status, so a transaction missing from the status cannot
+/// have landed by the height compared.
+pub fn outcome(status: Option<&TransactionStatus>, block_height: u64, last_valid: u64) -> Outcome {
+    match status {
+        Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => {
+            match &status.err {
+                Some(err) => Outcome::Failed(err.clone()),
+                None => Outcome::Landed,
+            }
+        }
+        None if block_height > last_valid => Outcome::Expired,
+        _ => Outcome::InFlight,
+    }
+}
+
+/// An unsigned transaction recording `entries`, paid for by `oracle`.
+fn batch(
+    challenges: &HashMap<Pubkey, Challenge>,
+    oracle: Pubkey,
+    entries: &[Entry],
+) -> Transaction {
+    let ixs: Vec<_> = entries
+        .iter()
+        .map(|entry| instructions::record_proof(&challenges[&entry.challenge], oracle, entry.user))
+        .collect();
+    Transaction::new_with_payer(&ixs, Some(&oracle))
+}
+
+/// Whether `tx` fits in the one packet a transaction is sent in. Signing
+/// only fills in the signature slots, so the size is known beforehand.
+fn fits(tx: &Transaction) -> bool {
+    bincode::serialized_size(tx).is_ok_and(|size| size <= PACKET_DATA_SIZE as u64)
+}
+
+enum Delivery {
+    Recorded,
+    Rejected(TransactionError),
+}
+
+pub struct Chain {
+    rpc: RpcClient,
+    oracle: Keypair,
+    attempts: u32,
+}
+
+impl Chain {
+    pub fn new(url: &str, oracle: Keypair, attempts: u32) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()),
+            oracle,
+            attempts,
+        }
+    }
+
+    /// `None` once the challenge has been closed.
+    fn challenge(&self, address: &Pubkey) -> Result<Option<Challenge>> {
+        let Some(account) = self
+            .rpc
+            .get_account_with_commitment(address, CommitmentConfig::confirmed())
+            .with_context(|| format!("fetching challenge {address}"))?
+            .value
+        else {
+            return Ok(None);
+        };
+        accounts::decode_challenge(&account.data)
+            .map(Some)
+            .map_err(|e| anyhow!("decoding challenge {address}: {e}"))
+    }
+
+    /// The cluster's clock, which is what `record_proof` checks against.
+    fn now(&self) -> Result<i64> {
+        Ok(tooling::cluster_time(&self.rpc)?)
+    }
+
+    fn outcome(&self, signature: &str, last_valid: u64) -> Result<Outcome> {
+        let block_height = self.rpc.get_block_height()?;
+        let status = self
+            .rpc
+            .get_signature_statuses_with_history(&[signature.parse::<Signature>()?])?
+            .value
+            .pop()
+            .flatten();
+        Ok(outcome(status.as_ref(), block_height, last_valid))
+    }
+
+    /// Settles transactions left in flight, by an earlier run or an earlier
+    /// pass: landed ones are recorded, and ones that failed or expired go
+    /// back to pending. Returns how many are still undecided.
+    pub fn resolve(&self, journal: &Journal) -> Result<usize> {
+        let mut undecided = 0;
+        for sent in journal.in_flight()? {
+            match self.outcome(&sent.signature, sent.last_valid_block_height)? {
+                Outcome::Landed => journal.recorded(&sent.signature)?,
+                Outcome::Failed(_) | Outcome::Expired => journal.release(&sent.signature)?,
+                Outcome::InFlight => undecided += 1,
+            }
+        }
+        Ok(undecided)
+    }
+
+    /// Sends up to `batch` pending approvals per transaction until none are
+    /// left that the program would take now. Returns how many were recorded.
+    pub fn flush(&self, journal: &mut Journal, batch: usize) -> Result<usize> {
+        let pending = journal.pending(usize::MAX)?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        // Screen against each challenge once, as `record_proof` would
+        let now = self.now()?;
+        let mut challenges = HashMap::new();
+        let mut ready = Vec::new();
+        for entry in pending {
+            if let hash_map::Entry::Vacant(slot) = challenges.entry(entry.challenge) {
+                slot.insert(self.challenge(&entry.challenge)?);
+            }
+            let Some(challenge) = &challenges[&entry.challenge] else {
+                journal.reject(&entry, "challenge has been closed")?;
+                continue;
+            };
+            if challenge.status != ChallengeStatus::Created
+                && challenge.status != ChallengeStatus::Started
+            {
+                journal.reject(&entry, "challenge no longer accepts proofs")?;
+            } else if now > challenge.end_ts {
+                journal.reject(&entry, "challenge window has closed")?;
+            } else if now >= challenge.start_ts {
+                ready.push(entry);
+            }
+        }
+
+        let challenges = challenges
+            .into_iter()
+            .filter_map(|(address, challenge)| Some((address, challenge?)))
+            .collect();
+        // Stable, so each challenge's approvals stay oldest first
+        ready.sort_by_key(|entry| entry.challenge);
+        let mut recorded = 0;
+        for group in ready.chunk_by(|a, b| a.challenge == b.challenge) {
+            for chunk in group.chunks(batch) {
+                recorded += self.deliver_or_split(journal, &challenges, chunk)?;
+            }
+        }
+        Ok(recorded)
+    }
+
+    fn deliver_or_split(
+        &self,
+        journal: &mut Journal,
+        challenges: &HashMap<Pubkey, Challenge>,
+        entries: &[Entry],
+    ) -> Result<usize> {
+        let tx = batch(challenges, self.oracle.pubkey(), entries);
+        if !fits(&tx) && entries.len() > 1 {
+            let (left, right) = entries.split_at(entries.len() / 2);
+            return Ok(self.deliver_or_split(journal, challenges, left)?
+                + self.deliver_or_split(journal, challenges, right)?);
+        }
+        match self.deliver(journal, tx, entries)? {
+            Delivery::Recorded => Ok(entries.len()),
+            Delivery::Rejected(err) if entries.len() == 1 => {
+                eprintln!(
+                    "rejected day {} for {} in {}: {err}",
+                    entries[0].day, entries[0].user, entries[0].challenge
+                );
+                journal.reject(&entries[0], &err.to_string())?;
+                Ok(0)
+            }
+            Delivery::Rejected(_) => {
+                let (left, right) = entries.split_at(entries.len() / 2);
+                Ok(self.deliver_or_split(journal, challenges, left)?
+                    + self.deliver_or_split(journal, challenges, right)?)
+            }
+        }
+    }
+
+    /// Sends `tx`, carrying `entries`, until it lands, fails, or has
+    /// expired `attempts` times.
+    fn deliver(
+        &self,
+        journal: &mut Journal,
+        mut tx: Transaction,
+        entries: &[Entry],
+    ) -> Result<Delivery> {
+        for attempt in 1..=self.attempts {
+            let (blockhash, last_valid) = self
+                .rpc
+                .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())?;
+            tx.try_sign(&[&self.oracle], blockhash)?;
+            let signature = tx.signatures[0].to_string();
+            journal.sent(entries, &signature, last_valid)?;
+
+            if let Err(err) = self.rpc.send_transaction(&tx) {
+                match err.get_transaction_error() {
+                    // Preflight simulation failed, so it was never forwarded
+                    Some(err @ TransactionError::InstructionError(..)) => {
+                        journal.release(&signature)?;
+                        return Ok(Delivery::Rejected(err));
+                    }
+                    Some(_) => {
+                        journal.release(&signature)?;
+                        continue;
+                    }
+                    // It may have reached the leader anyway; wait it out
+                    None => eprintln!("sending {signature} (attempt {attempt}): {err}"),
+                }
+            }
+
+            loop {
+                match self.outcome(&signature, last_valid)? {
+                    Outcome::Landed => {
+                        journal.recorded(&signature)?;
+                        return Ok(Delivery::Recorded);
+                    }
+                    Outcome::Failed(err) => {
+                        journal.release(&signature)?;
+                        return Ok(Delivery::Rejected(err));
+                    }
+                    Outcome::Expired => {
+                        journal.release(&signature)?;
+                        break;
+                    }
+                    Outcome::InFlight => thread::sleep(POLL),
+                }
+            }
+        }
+        bail!(
+            "{} approvals unconfirmed after {} attempts",
+            entries.len(),
+            self.attempts
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{AnchorDeserialize, Space};
+    use solana_sdk::instruction::InstructionError;
+    use solana_transaction_status::TransactionConfirmationStatus;
+
+    fn status(
+        confirmation: TransactionConfirmationStatus,
+        err: Option<TransactionError>,
+    ) -> TransactionStatus {
+        TransactionStatus {
+            slot: 1,
+            confirmations: Some(1),
+            status: err.clone().map_or(Ok(()), Err),
+            err,
+            confirmation_status: Some(confirmation),
+        }
+    }
+
+    fn challenge(registry: Option<Pubkey>) -> Challenge {
+        let mut challenge = Challenge::deserialize(&mut &[0u8; Challenge::INIT_SPACE][..]).unwrap();
+        challenge.admin = Pubkey::new_unique();
+        challenge.oracle_registry = registry;
+        challenge
+    }
+
+    fn entries(challenge: Pubkey, count: usize) -> Vec<Entry> {
+        (0..count)
+            .map(|id| Entry {
+                id: id as i64,
+                challenge,
+                user: Pubkey::new_unique(),
+                day: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_full_batch_for_one_challenge_fits_in_a_packet() {
+        let oracle = Pubkey::new_unique();
+        let (linked, direct) = (challenge(Some(Pubkey::new_unique())), challenge(None));
+        let (a, b) = (
+            proven_stake_client::pda::challenge_of(&linked),
+            proven_stake_client::pda::challenge_of(&direct),
+        );
+        let challenges = HashMap::from([(a, linked), (b, direct)]);
+
+        assert!(fits(&batch(&challenges, oracle, &entries(a, MAX_BATCH))));
+        assert!(fits(&batch(&challenges, oracle, &entries(b, MAX_BATCH))));
+        // Every participant is another key, so there is little slack
+        assert!(!fits(&batch(
+            &challenges,
+            oracle,
+            &entries(a, MAX_BATCH + 2)
+        )));
+
+        // Spread over challenges, the same count doesn't fit
+        let spread: HashMap<_, _> = (0..MAX_BATCH)
+            .map(|_| (Pubkey::new_unique(), challenge(None)))
+            .collect();
+        let mixed: Vec<_> = spread.keys().flat_map(|&c| entries(c, 1)).collect();
+        assert!(!fits(&batch(&spread, oracle, &mixed)));
+    }
+
+    #[test]
+    fn only_confirmed_or_expired_transactions_are_decided() {
+        use TransactionConfirmationStatus::*;
+
+        let landed = status(Confirmed, None);
+        assert_eq!(outcome(Some(&landed), 200, 100), Outcome::Landed);
+        let finalized = status(Finalized, None);
+        assert_eq!(outcome(Some(&finalized), 50, 100), Outcome::Landed);
+
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(6000));
+        let failed = status(Confirmed, Some(err.clone()));
+        assert_eq!(outcome(Some(&failed), 50, 100), Outcome::Failed(err));
+
+        // Processed can still be rolled back
+        let processed = status(Processed, None);
+        assert_eq!(outcome(Some(&processed), 200, 100), Outcome::InFlight);
+
+        assert_eq!(outcome(None, 100, 100), Outcome::InFlight);
+        assert_eq!(outcome(None, 101, 100), Outcome::Expired);
+    }
+}
@@ -0,0 +1,229 @@
+//! Getting approvals into the journal, from an inbox file the backend
+//! appends to or from HTTP.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use proven_stake_client::{accounts, PROGRAM_ID};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tiny_http::{Method, Response, Server};
+
+use crate::approval::{self, Approval};
+use crate::journal::Journal;
+
+/// What became of a batch of approvals.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Accepted {
+    pub added: usize,
+    pub duplicates: usize,
+    /// Approvals for a day outside their challenge: submitted before it
+    /// started or after its last day, or naming a day past the last.
+    pub outside: usize,
+    /// Approvals naming an account that isn't a challenge.
+    pub unknown: usize,
+}
+
+impl Accepted {
+    fn add(&mut self, other: Accepted) {
+        self.added += other.added;
+        self.duplicates += other.duplicates;
+        self.outside += other.outside;
+        self.unknown += other.unknown;
+    }
+}
+
+/// Places approvals on challenge days and journals them.
+pub struct Intake {
+    journal: Journal,
+    rpc: RpcClient,
+    /// A challenge's start and length never move, so they are fetched once.
+    windows: HashMap<Pubkey, (i64, u32)>,
+}
+
+impl Intake {
+    pub fn new(journal: Journal, url: &str) -> Self {
+        Self {
+            journal,
+            rpc: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()),
+            windows: HashMap::new(),
+        }
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// When `challenge` started and how many days it runs, or `None` if it
+    /// isn't a challenge.
+    fn window(&mut self, challenge: &Pubkey) -> Result<Option<(i64, u32)>> {
+        if let Some(window) = self.windows.get(challenge) {
+            return Ok(Some(*window));
+        }
+        let account = self
+            .rpc
+            .get_account_with_commitment(challenge, CommitmentConfig::confirmed())
+            .with_context(|| format!("fetching challenge {challenge}"))?
+            .value;
+        let Some(window) = account
+            .filter(|account| account.owner == PROGRAM_ID)
+            .and_then(|account| accounts::decode_challenge(&account.data).ok())
+            .map(|challenge| (challenge.start_ts, challenge.total_days))
+        else {
+            return Ok(None);
+        };
+        self.windows.insert(*challenge, window);
+        Ok(Some(window))
+    }
+
+    /// Journals `approvals`, moving `cursor` with them. Nothing is written
+    /// if the node can't be reached.
+    pub fn accept(
+        &mut self,
+        approvals: &[Approval],
+        cursor: Option<(&str, u64)>,
+    ) -> Result<Accepted> {
+        let mut days = Vec::with_capacity(approvals.len());
+        let mut accepted = Accepted::default();
+        for approval in approvals {
+            let Some((start, total_days)) = self.window(&approval.challenge)? else {
+                accepted.unknown += 1;
+                continue;
+            };
+            match approval.day(start, total_days) {
+                Some(day) => days.push((approval.challenge, approval.user, day)),
+                None => accepted.outside += 1,
+            }
+        }
+        accepted.added = self.journal.accept(&days, cursor)?;
+        accepted.duplicates = days.len() - accepted.added;
+        Ok(accepted)
+    }
+}
+
+/// Complete lines appended to `path` since byte `from`, each with the
+/// position just past it. A trailing line without its newline is left for
+/// the next read, as the backend may still be writing it.
+pub fn tail(path: &Path, from: u64) -> Result<Vec<(String, u64)>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("opening {}", path.display())),
+    };
+    let len = file.metadata()?.len();
+    // Truncated or replaced: start over; the journal drops repeats
+    let from = if len < from { 0 } else { from };
+    file.seek(SeekFrom::Start(from))?;
+
+    let mut lines = Vec::new();
+    let mut position = from;
+    let mut reader = BufReader::new(file.take(len - from));
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        position += read as u64;
+        lines.push((line.trim().to_string(), position));
+    }
+    Ok(lines)
+}
+
+/// Journals what has been appended to the inbox at `path` since the last
+/// call. A line that doesn't parse is logged and skipped; if the node
+/// can't be reached the read stops there, to be retried.
+pub fn drain_inbox(intake: &mut Intake, path: &Path) -> Result<Accepted> {
+    let source = format!("inbox:{}", path.display());
+    let mut total = Accepted::default();
+    for (line, position) in tail(path, intake.journal().position(&source)?)? {
+        let approvals = if line.is_empty() {
+            Vec::new()
+        } else {
+            approval::parse(&line).unwrap_or_else(|err| {
+                eprintln!("skipping inbox line {line:?}: {err:#}");
+                Vec::new()
+            })
+        };
+        total.add(intake.accept(&approvals, Some((&source, position)))?);
+    }
+    Ok(total)
+}
+
+/// Serves `POST /approvals` on `addr` from a thread of its own, with its own
+/// journal connection. A request is answered once its approvals are
+/// journaled, so a 200 means they will be recorded; a 503 means nothing was
+/// and the request should be retried.
+pub fn serve(addr: &str, journal_path: PathBuf, url: String) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow!("listening on {addr}: {e}"))?;
+    let mut intake = Intake::new(Journal::open(&journal_path)?, &url);
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let (status, body) = if request.url() != "/approvals" {
+                (404, "not found\n".to_string())
+            } else if *request.method() != Method::Post {
+                (405, "use POST\n".to_string())
+            } else {
+                let mut body = String::new();
+                match request
+                    .as_reader()
+                    .read_to_string(&mut body)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| approval::parse(&body))
+                {
+                    Err(err) => (400, format!("{err:#}\n")),
+                    Ok(approvals) => match intake.accept(&approvals, None) {
+                        Ok(accepted) => (
+                            200,
+                            serde_json::json!({
+                                "added": accepted.added,
+                                "duplicates": accepted.duplicates,
+                                "outside": accepted.outside,
+                                "unknown": accepted.unknown,
+                            })
+                            .to_string(),
+                        ),
+                        Err(err) => (503, format!("{err:#}\n")),
+                    },
+                }
+            };
+            let _ = request.respond(Response::from_string(body).with_status_code(status));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, io::Write};
+
+    #[test]
+    fn tails_complete_lines_only() {
+        let path = env::temp_dir().join(format!("proven-oracle-inbox-{}", std::process::id()));
+        fs::write(&path, "first\nsecond\nthi").unwrap();
+
+        let lines = tail(&path, 0).unwrap();
+        assert_eq!(
+            lines,
+            [("first".to_string(), 6), ("second".to_string(), 13)]
+        );
+        assert!(tail(&path, 13).unwrap().is_empty());
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"rd\n").unwrap();
+        assert_eq!(tail(&path, 13).unwrap(), [("third".to_string(), 19)]);
+
+        // Rotated under us
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(tail(&path, 19).unwrap(), [("new".to_string(), 4)]);
+
+        fs::remove_file(&path).unwrap();
+        assert!(tail(&path, 4).unwrap().is_empty());
+    }
+}
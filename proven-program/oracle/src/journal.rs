@@ -0,0 +1,293 @@
+//! The local journal: every approval accepted, and what became of it.
+//!
+//! Approvals are unique per (challenge, user, day), so a repeat is dropped
+//! however it arrives. Each moves from `pending` to `sent` when a
+//! transaction carrying it is signed, with the signature and the last block
+//! height its blockhash is valid for written *before* it is submitted.
+//! After a restart, a `sent` approval is only returned to `pending` once
+//! its transaction has failed or can no longer land, so nothing is recorded
+//! twice.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use solana_sdk::pubkey::Pubkey;
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+CREATE TABLE IF NOT EXISTS cursor (
+    source TEXT PRIMARY KEY,
+    position INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS approvals (
+    id INTEGER PRIMARY KEY,
+    challenge TEXT NOT NULL,
+    user TEXT NOT NULL,
+    day INTEGER NOT NULL,
+    -- pending, sent, recorded or rejected
+    state TEXT NOT NULL DEFAULT 'pending',
+    -- Set while sent, and kept once recorded
+    signature TEXT,
+    last_valid_block_height INTEGER,
+    error TEXT,
+    received_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    UNIQUE (challenge, user, day)
+);
+CREATE INDEX IF NOT EXISTS approvals_state ON approvals (state);
+";
+
+/// An approval waiting for a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub id: i64,
+    pub challenge: Pubkey,
+    pub user: Pubkey,
+    pub day: u32,
+}
+
+/// A signed transaction whose fate is still unknown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InFlight {
+    pub signature: String,
+    pub last_valid_block_height: u64,
+}
+
+pub struct Journal {
+    conn: Connection,
+}
+
+impl Journal {
+    /// Opens the journal at `path`. The HTTP intake holds its own
+    /// connection to the same file, so writers wait on each other briefly
+    /// rather than failing.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("opening {}", path.display()))?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// How far `source` has been read.
+    pub fn position(&self, source: &str) -> Result<u64> {
+        let position: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT position FROM cursor WHERE source = ?1",
+                [source],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(position.unwrap_or(0) as u64)
+    }
+
+    /// Adds `approvals` as (challenge, user, day), returning how many were
+    /// new. With `cursor`, also moves that source's position, in the same
+    /// transaction, so nothing read is lost or re-read.
+    pub fn accept(
+        &mut self,
+        approvals: &[(Pubkey, Pubkey, u32)],
+        cursor: Option<(&str, u64)>,
+    ) -> Result<usize> {
+        let db = self.conn.transaction()?;
+        let mut added = 0;
+        for (challenge, user, day) in approvals {
+            added += db.execute(
+                "INSERT OR IGNORE INTO approvals (challenge, user, day) VALUES (?1, ?2, ?3)",
+                params![challenge.to_string(), user.to_string(), day],
+            )?;
+        }
+        if let Some((source, position)) = cursor {
+            db.execute(
+                "INSERT INTO cursor (source, position) VALUES (?1, ?2)
+                 ON CONFLICT (source) DO UPDATE SET position = excluded.position",
+                params![source, position as i64],
+            )?;
+        }
+        db.commit()?;
+        Ok(added)
+    }
+
+    /// Up to `limit` pending approvals, oldest first.
+    pub fn pending(&self, limit: usize) -> Result<Vec<Entry>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, challenge, user, day FROM approvals
+             WHERE state = 'pending' ORDER BY id LIMIT ?1",
+        )?;
+        let rows = statement.query_map([limit as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, u32>(3)?,
+            ))
+        })?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (id, challenge, user, day) = row?;
+            entries.push(Entry {
+                id,
+                challenge: challenge.parse().context("journaled challenge")?,
+                user: user.parse().context("journaled user")?,
+                day,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Transactions signed but not yet known to have landed or failed.
+    pub fn in_flight(&self) -> Result<Vec<InFlight>> {
+        let mut statement = self.conn.prepare(
+            "SELECT DISTINCT signature, last_valid_block_height FROM approvals
+             WHERE state = 'sent' ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(InFlight {
+                signature: row.get(0)?,
+                last_valid_block_height: row.get::<_, i64>(1)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Ties `entries` to a transaction that is about to be submitted.
+    pub fn sent(&mut self, entries: &[Entry], signature: &str, last_valid: u64) -> Result<()> {
+        let db = self.conn.transaction()?;
+        for entry in entries {
+            db.execute(
+                "UPDATE approvals SET state = 'sent', signature = ?2, last_valid_block_height = ?3
+                 WHERE id = ?1 AND state = 'pending'",
+                params![entry.id, signature, last_valid as i64],
+            )?;
+        }
+        db.commit()?;
+        Ok(())
+    }
+
+    /// `signature` landed.
+    pub fn recorded(&self, signature: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE approvals SET state = 'recorded' WHERE state = 'sent' AND signature = ?1",
+            [signature],
+        )?;
+        Ok(())
+    }
+
+    /// `signature` failed or expired; its approvals go back to pending.
+    pub fn release(&self, signature: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE approvals SET state = 'pending', signature = NULL,
+                 last_valid_block_height = NULL
+             WHERE state = 'sent' AND signature = ?1",
+            [signature],
+        )?;
+        Ok(())
+    }
+
+    /// Gives up on `entry`, which the program will never accept.
+    pub fn reject(&self, entry: &Entry, error: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE approvals SET state = 'rejected', error = ?2 WHERE id = ?1",
+            params![entry.id, error],
+        )?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn state(&self, entry: &Entry) -> String {
+        self.conn
+            .query_row(
+                "SELECT state FROM approvals WHERE id = ?1",
+                [entry.id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_each_day_once() {
+        let mut journal = Journal::in_memory().unwrap();
+        let (challenge, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let approvals = [
+            (challenge, user, 0),
+            (challenge, user, 1),
+            (challenge, user, 0),
+        ];
+        assert_eq!(journal.accept(&approvals, None).unwrap(), 2);
+        assert_eq!(journal.accept(&approvals[1..], None).unwrap(), 0);
+
+        let pending = journal.pending(10).unwrap();
+        let days: Vec<_> = pending.iter().map(|entry| entry.day).collect();
+        assert_eq!(days, [0, 1]);
+        assert_eq!(pending[0].challenge, challenge);
+        assert_eq!(pending[0].user, user);
+        assert_eq!(journal.pending(1).unwrap().len(), 1);
+
+        // Rejected and recorded approvals still count as seen
+        journal.reject(&pending[0], "NotJoined").unwrap();
+        assert_eq!(journal.accept(&approvals[..1], None).unwrap(), 0);
+    }
+
+    #[test]
+    fn cursor_moves_with_the_approvals() {
+        let mut journal = Journal::in_memory().unwrap();
+        assert_eq!(journal.position("inbox").unwrap(), 0);
+        let approval = (Pubkey::new_unique(), Pubkey::new_unique(), 4);
+        journal.accept(&[approval], Some(("inbox", 120))).unwrap();
+        journal.accept(&[], Some(("inbox", 180))).unwrap();
+        assert_eq!(journal.position("inbox").unwrap(), 180);
+        assert_eq!(journal.position("other").unwrap(), 0);
+    }
+
+    #[test]
+    fn sent_approvals_wait_for_their_transaction() {
+        let mut journal = Journal::in_memory().unwrap();
+        let challenge = Pubkey::new_unique();
+        let approvals: Vec<_> = (0..3)
+            .map(|day| (challenge, Pubkey::new_unique(), day))
+            .collect();
+        journal.accept(&approvals, None).unwrap();
+        let pending = journal.pending(10).unwrap();
+
+        journal.sent(&pending[..2], "first", 100).unwrap();
+        assert_eq!(journal.pending(10).unwrap(), pending[2..]);
+        assert_eq!(
+            journal.in_flight().unwrap(),
+            [InFlight {
+                signature: "first".to_string(),
+                last_valid_block_height: 100
+            }]
+        );
+
+        // Expired: back in the queue, and re-sent under a new signature
+        journal.release("first").unwrap();
+        assert_eq!(journal.pending(10).unwrap(), pending);
+        journal.sent(&pending, "second", 160).unwrap();
+        journal.recorded("first").unwrap();
+        assert_eq!(journal.state(&pending[0]), "sent");
+
+        journal.recorded("second").unwrap();
+        assert!(journal.in_flight().unwrap().is_empty());
+        assert!(pending
+            .iter()
+            .all(|entry| journal.state(entry) == "recorded"));
+        // A late release of a settled transaction changes nothing
+        journal.release("second").unwrap();
+        assert!(journal.pending(10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+//! `proven-oracle`: records approved proofs on chain in batches.
+//!
+//! The backend hands over approvals by appending JSON lines to an inbox
+//! file or by posting them to `/approvals`. Each is placed on its challenge
+//! day and journaled in SQLite, where a repeat of the same (challenge, user,
+//! day) is dropped. Pending approvals are then sent as `record_proof`
+//! instructions, several per transaction, signed by the oracle keypair. The
+//! journal tracks every transaction from signing to confirmation, so a
+//! restart picks up where the last run stopped without recording a day
+//! twice.
+
+mod approval;
+mod chain;
+mod intake;
+mod journal;
+
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use proven_stake_client::tooling;
+use solana_sdk::signature::{read_keypair_file, Signer};
+
+use crate::chain::{Chain, MAX_BATCH};
+use crate::intake::{Accepted, Intake};
+use crate::journal::Journal;
+
+#[derive(Parser)]
+#[command(
+    name = "proven-oracle",
+    version,
+    about = "Batch approved proofs into record_proof transactions"
+)]
+struct Cli {
+    /// JSON RPC endpoint; defaults to a local validator.
+    #[arg(
+        long,
+        short = 'u',
+        env = "PROVEN_RPC_URL",
+        default_value = "http://127.0.0.1:8899"
+    )]
+    url: String,
+    /// Oracle keypair, which signs and pays; defaults to the Solana CLI
+    /// keypair.
+    #[arg(long, short = 'k', env = "PROVEN_ORACLE_KEYPAIR")]
+    keypair: Option<PathBuf>,
+    /// SQLite journal of approvals and transactions; created if missing.
+    #[arg(
+        long,
+        env = "PROVEN_ORACLE_JOURNAL",
+        default_value = "proven-oracle.sqlite"
+    )]
+    journal: PathBuf,
+    /// File the backend appends approvals to, one JSON message per line.
+    #[arg(long)]
+    inbox: Option<PathBuf>,
+    /// Address to accept `POST /approvals` on, e.g. `127.0.0.1:8787`.
+    #[arg(long)]
+    http: Option<String>,
+    /// Most `record_proof` instructions per transaction.
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u64).range(1..=MAX_BATCH as u64)
+    )]
+    batch: u64,
+    /// Fresh blockhashes to try before leaving a batch for the next pass.
+    #[arg(long, default_value_t = 3)]
+    attempts: u32,
+    /// Seconds between passes over the inbox and the journal.
+    #[arg(long, default_value_t = 2)]
+    interval: u64,
+    /// Make one pass and exit, rather than running as a daemon.
+    #[arg(long)]
+    once: bool,
+}
+
+fn log(source: &str, accepted: &Accepted) {
+    if *accepted == Accepted::default() {
+        return;
+    }
+    println!(
+        "{source}: {} new, {} duplicate, {} outside their challenge, {} for unknown challenges",
+        accepted.added, accepted.duplicates, accepted.outside, accepted.unknown
+    );
+}
+
+/// Reads the inbox, settles transactions still in flight, then sends
+/// whatever is pending.
+fn pass(
+    intake: Option<(&mut Intake, &Path)>,
+    chain: &Chain,
+    journal: &mut Journal,
+    batch: usize,
+) -> Result<()> {
+    if let Some((intake, inbox)) = intake {
+        log("inbox", &intake::drain_inbox(intake, inbox)?);
+    }
+    let undecided = chain.resolve(journal)?;
+    let recorded = chain.flush(journal, batch)?;
+    if recorded > 0 {
+        println!("recorded {recorded} proofs");
+    }
+    if undecided > 0 {
+        println!("{undecided} transactions awaiting confirmation");
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let keypair = match cli.keypair {
+        Some(path) => path,
+        None => tooling::default_keypair().ok_or_else(|| anyhow!("HOME is not set"))?,
+    };
+    let oracle = read_keypair_file(&keypair)
+        .map_err(|e| anyhow!("reading keypair {}: {e}", keypair.display()))?;
+    println!("oracle {}", oracle.pubkey());
+
+    let mut journal = Journal::open(&cli.journal)?;
+    let chain = Chain::new(&cli.url, oracle, cli.attempts);
+    let mut intake = match &cli.inbox {
+        Some(_) => Some(Intake::new(Journal::open(&cli.journal)?, &cli.url)),
+        None => None,
+    };
+    if let Some(addr) = &cli.http {
+        intake::serve(addr, cli.journal.clone(), cli.url.clone())?;
+        println!("listening on http://{addr}/approvals");
+    }
+
+    loop {
+        let inbox = intake.as_mut().zip(cli.inbox.as_deref());
+        let result = pass(inbox, &chain, &mut journal, cli.batch as usize);
+        if cli.once {
+            return result;
+        }
+        // Whatever failed is still journaled; try again next pass
+        if let Err(err) = result {
+            eprintln!("{err:#}");
+        }
+        thread::sleep(Duration::from_secs(cli.interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+}
@@ -1,8 +1,19 @@
 #![allow(unexpected_cfgs)]
+#![allow(clippy::too_many_arguments)]
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::hash::{hash, hashv};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+
+pub mod settlement;
 
 declare_id!("2axKJmSNPkdAysQXjz7y2R2Tho58WbzLYgYcAsMgfMKc");
 
@@ -19,44 +30,39 @@ pub mod proven_stake {
         platform_fee_bps: u16,
         start_ts: i64,
         oracle_signer: Pubkey,
+        treasury_fee_bps: u16,
+        charity_fee_bps: u16,
+        charity: Option<Pubkey>,
     ) -> Result<()> {
-        require!(stake_amount > 0, ProvenError::InvalidAmount);
-        require!(total_days > 0, ProvenError::InvalidDuration);
-        require!(threshold_bps <= 10000, ProvenError::InvalidThreshold);
-        require!(platform_fee_bps <= 1000, ProvenError::InvalidFee);
+        Challenge::validate_terms(stake_amount, total_days, threshold_bps, platform_fee_bps)?;
+        Challenge::validate_fee_split(treasury_fee_bps, charity_fee_bps, charity)?;
         require!(
             start_ts > Clock::get()?.unix_timestamp,
             ProvenError::InvalidStartTime
         );
         require!(!challenge_id.is_empty(), ProvenError::ChallengeIdEmpty);
         require!(
-            challenge_id.as_bytes().len() <= Challenge::MAX_ID_LENGTH,
+            challenge_id.len() <= Challenge::MAX_ID_LENGTH,
             ProvenError::ChallengeIdTooLong
         );
 
         let challenge = &mut ctx.accounts.challenge;
-        challenge.challenge_id = challenge_id.clone();
-        challenge.admin = ctx.accounts.admin.key();
-        challenge.token_mint = ctx.accounts.token_mint.key();
-        challenge.escrow_vault = ctx.accounts.escrow_vault.key();
-        challenge.stake_amount = stake_amount;
-        challenge.total_days = total_days;
-        challenge.threshold_bps = threshold_bps;
-        challenge.platform_fee_bps = platform_fee_bps;
-        challenge.status = ChallengeStatus::Created;
-        challenge.start_ts = start_ts;
-        challenge.end_ts = start_ts + (total_days as i64 * 24 * 60 * 60);
-        challenge.participant_count = 0;
-        challenge.active_participants = 0;
-        challenge.winner_count = 0;
-        challenge.loser_count = 0;
-        challenge.bonus_per_winner = 0;
-        challenge.fee_amount = 0;
-        challenge.remainder = 0;
-        challenge.payouts_claimed_count = 0;
-        challenge.remainder_claimed = 0;
-        challenge.oracle_signer = oracle_signer;
-        challenge.bump = ctx.bumps.challenge;
+        challenge.initialize(
+            challenge_id,
+            ctx.accounts.admin.key(),
+            ctx.accounts.token_mint.key(),
+            ctx.accounts.escrow_vault.key(),
+            stake_amount,
+            total_days,
+            threshold_bps,
+            platform_fee_bps,
+            start_ts,
+            oracle_signer,
+            ctx.bumps.challenge,
+        );
+        challenge.treasury_fee_bps = treasury_fee_bps;
+        challenge.charity_fee_bps = charity_fee_bps;
+        challenge.charity = charity;
 
         emit!(ChallengeCreated {
             challenge_id: challenge.key(),
@@ -69,23 +75,36 @@ pub mod proven_stake {
         Ok(())
     }
 
-    pub fn join_challenge(ctx: Context<JoinChallenge>, challenge_id: String) -> Result<()> {
+    pub fn join_challenge(
+        ctx: Context<JoinChallenge>,
+        proof: Vec<[u8; 32]>,
+        referrer: Option<Pubkey>,
+    ) -> Result<()> {
         let challenge = &mut ctx.accounts.challenge;
         let participant = &mut ctx.accounts.participant;
         let clock = Clock::get()?;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             challenge.status == ChallengeStatus::Created,
             ProvenError::InvalidChallengeStatus
         );
+        require!(!challenge.team_mode, ProvenError::TeamChallenge);
         require!(
             clock.unix_timestamp < challenge.start_ts,
             ProvenError::ChallengeStarted
         );
+        challenge.check_allowlist(
+            ctx.accounts.allowlist.as_ref(),
+            &ctx.accounts.user.key(),
+            &proof,
+        )?;
+        challenge.check_voucher(
+            challenge.key(),
+            ctx.accounts.instructions.as_ref(),
+            ctx.accounts.oracle_registry.as_ref(),
+            &ctx.accounts.user.key(),
+            clock.unix_timestamp,
+        )?;
 
         // Transfer USDC from user to escrow vault
         let cpi_accounts = Transfer {
@@ -98,16 +117,41 @@ pub mod proven_stake {
         token::transfer(cpi_ctx, challenge.stake_amount)?;
 
         // Initialize participant
-        participant.user = ctx.accounts.user.key();
-        participant.challenge = challenge.key();
-        participant.joined = true;
-        participant.stake_deposited = challenge.stake_amount;
-        participant.proof_days = 0;
-        participant.is_winner = false;
-        participant.is_settled = false;
-        participant.payout_claimed = false;
-        participant.refund_claimed = false;
-        participant.bump = ctx.bumps.participant;
+        participant.initialize(
+            ctx.accounts.user.key(),
+            challenge.key(),
+            challenge.stake_amount,
+            ctx.bumps.participant,
+        );
+
+        if let Some(referrer) = referrer {
+            require_keys_neq!(
+                referrer,
+                ctx.accounts.user.key(),
+                ProvenError::InvalidReferrer
+            );
+            let accrual = ctx
+                .accounts
+                .referral_accrual
+                .as_mut()
+                .ok_or(ProvenError::ReferralAccrualMissing)?;
+            require_keys_eq!(
+                accrual.referrer,
+                referrer,
+                ProvenError::ReferralAccrualMissing
+            );
+            accrual.referred_count = accrual
+                .referred_count
+                .checked_add(1)
+                .ok_or(ProvenError::MathOverflow)?;
+            participant.referrer = Some(referrer);
+
+            emit!(ReferralRecorded {
+                challenge_id: challenge.key(),
+                user: ctx.accounts.user.key(),
+                referrer,
+            });
+        }
 
         // Update challenge stats
         challenge.participant_count += 1;
@@ -125,15 +169,11 @@ pub mod proven_stake {
         Ok(())
     }
 
-    pub fn record_proof(ctx: Context<RecordProof>, challenge_id: String) -> Result<()> {
+    pub fn record_proof(ctx: Context<RecordProof>) -> Result<()> {
         let challenge = &mut ctx.accounts.challenge;
         let participant = &mut ctx.accounts.participant;
         let clock = Clock::get()?;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             challenge.status == ChallengeStatus::Created
                 || challenge.status == ChallengeStatus::Started,
@@ -148,10 +188,10 @@ pub mod proven_stake {
             ProvenError::ChallengeEnded
         );
         require!(participant.joined, ProvenError::NotJoined);
-        require!(
-            ctx.accounts.oracle.key() == challenge.oracle_signer,
-            ProvenError::InvalidOracle
-        );
+        challenge.verify_oracle(
+            ctx.accounts.oracle.key(),
+            ctx.accounts.oracle_registry.as_ref(),
+        )?;
 
         if challenge.status == ChallengeStatus::Created {
             challenge.status = ChallengeStatus::Started;
@@ -169,14 +209,10 @@ pub mod proven_stake {
         Ok(())
     }
 
-    pub fn settle_challenge(ctx: Context<SettleChallenge>, challenge_id: String) -> Result<()> {
+    pub fn settle_challenge(ctx: Context<SettleChallenge>) -> Result<()> {
         let challenge = &mut ctx.accounts.challenge;
         let clock = Clock::get()?;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             challenge.status == ChallengeStatus::Created
                 || challenge.status == ChallengeStatus::Started,
@@ -186,52 +222,73 @@ pub mod proven_stake {
             clock.unix_timestamp > challenge.end_ts,
             ProvenError::ChallengeNotEnded
         );
-        require!(
-            ctx.accounts.oracle.key() == challenge.oracle_signer,
-            ProvenError::InvalidOracle
-        );
+        challenge.verify_oracle(
+            ctx.accounts.oracle.key(),
+            ctx.accounts.oracle_registry.as_ref(),
+        )?;
 
         // Calculate threshold days required
-        let required_days = (challenge.total_days as u64 * challenge.threshold_bps as u64) / 10000;
+        let required_days =
+            settlement::required_days(challenge.total_days, challenge.threshold_bps);
 
         challenge.status = ChallengeStatus::Ended;
 
         emit!(ChallengeSettlementStarted {
             challenge_id: challenge.key(),
-            required_days: required_days as u32,
+            required_days,
         });
 
         Ok(())
     }
 
-    pub fn settle_participant(ctx: Context<SettleParticipant>, challenge_id: String) -> Result<()> {
+    pub fn settle_participant(ctx: Context<SettleParticipant>) -> Result<()> {
         let challenge = &mut ctx.accounts.challenge;
         let participant = &mut ctx.accounts.participant;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             challenge.status == ChallengeStatus::Ended,
             ProvenError::InvalidChallengeStatus
         );
-        require!(
-            ctx.accounts.oracle.key() == challenge.oracle_signer,
-            ProvenError::InvalidOracle
-        );
+        challenge.verify_oracle(
+            ctx.accounts.oracle.key(),
+            ctx.accounts.oracle_registry.as_ref(),
+        )?;
         require!(!participant.is_settled, ProvenError::AlreadySettled);
+        require!(!challenge.team_mode, ProvenError::TeamChallenge);
 
         // Calculate threshold days required
-        let required_days = (challenge.total_days as u64 * challenge.threshold_bps as u64) / 10000;
+        let required_days =
+            settlement::required_days(challenge.total_days, challenge.threshold_bps);
 
-        if participant.proof_days as u64 >= required_days {
+        if participant.proof_days >= required_days {
             // User is a winner
             participant.is_winner = true;
             challenge.winner_count += 1;
         } else {
             // User is a loser
             challenge.loser_count += 1;
+
+            if let Some(referrer) = participant.referrer {
+                let accrual = ctx
+                    .accounts
+                    .referral_accrual
+                    .as_mut()
+                    .ok_or(ProvenError::ReferralAccrualMissing)?;
+                require_keys_eq!(
+                    accrual.referrer,
+                    referrer,
+                    ProvenError::ReferralAccrualMissing
+                );
+                let reward = challenge.referral_reward(participant.stake_deposited);
+                accrual.accrued = accrual
+                    .accrued
+                    .checked_add(reward)
+                    .ok_or(ProvenError::MathOverflow)?;
+                challenge.referral_owed = challenge
+                    .referral_owed
+                    .checked_add(reward)
+                    .ok_or(ProvenError::MathOverflow)?;
+            }
         }
 
         participant.is_settled = true;
@@ -241,54 +298,81 @@ pub mod proven_stake {
             user: participant.user,
             is_winner: participant.is_winner,
             proof_days: participant.proof_days,
-            required_days: required_days as u32,
+            required_days,
         });
 
         Ok(())
     }
 
-    pub fn finalize_settlement(
-        ctx: Context<FinalizeSettlement>,
-        challenge_id: String,
-    ) -> Result<()> {
+    pub fn finalize_settlement(ctx: Context<FinalizeSettlement>) -> Result<()> {
         let challenge = &mut ctx.accounts.challenge;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             challenge.status == ChallengeStatus::Ended,
             ProvenError::InvalidChallengeStatus
         );
-        require!(
-            ctx.accounts.oracle.key() == challenge.oracle_signer,
-            ProvenError::InvalidOracle
-        );
+        challenge.verify_oracle(
+            ctx.accounts.oracle.key(),
+            ctx.accounts.oracle_registry.as_ref(),
+        )?;
         require!(
             challenge.winner_count + challenge.loser_count == challenge.participant_count,
             ProvenError::SettlementIncomplete
         );
 
-        // Calculate platform fees and distribution
-        let losers_stakes = challenge.loser_count as u64 * challenge.stake_amount;
-        let platform_fee = (losers_stakes * challenge.platform_fee_bps as u64) / 10000;
-        let distributable_amount = losers_stakes - platform_fee;
+        // Team challenges split the pool across winning teams here; each
+        // team splits its share among its members on the first claim
+        let winning_shares = match ctx.accounts.team_config.as_ref() {
+            Some(team_config) if challenge.team_mode => team_config.winning_teams,
+            _ => challenge.winner_count,
+        };
+        let distribution = settlement::distribute(
+            challenge.stake_amount,
+            challenge.loser_count,
+            winning_shares,
+            challenge.platform_fee_bps,
+        )
+        .ok_or(ProvenError::MathOverflow)?;
 
-        // Store platform fee and reset counters
-        challenge.fee_amount = platform_fee;
+        // Split the fee between host, treasury and charity and reset
+        // counters; referral rewards stay in escrow until referrers claim them
+        let net_fee = distribution
+            .platform_fee
+            .checked_sub(challenge.referral_owed)
+            .ok_or(ProvenError::MathOverflow)?;
+        challenge.split_fee(net_fee);
         challenge.payouts_claimed_count = 0;
         challenge.remainder_claimed = 0;
 
-        if challenge.winner_count > 0 {
-            // Distribute remaining stakes equally among winners
-            challenge.bonus_per_winner = distributable_amount / challenge.winner_count as u64;
-            challenge.remainder = distributable_amount % challenge.winner_count as u64;
+        let mut forfeited = 0;
+        if challenge.forfeit_beneficiary.is_some() {
+            // Winners only get their stake back; the loser pool goes to
+            // the beneficiary below
+            challenge.bonus_per_winner = 0;
+            challenge.remainder = 0;
+            forfeited = distribution.distributable;
+        } else if challenge.team_mode {
+            let team_config = ctx
+                .accounts
+                .team_config
+                .as_mut()
+                .ok_or(ProvenError::TeamConfigMissing)?;
+            // Teams no one joined have nothing to settle
+            require!(
+                team_config.teams_settled + team_config.empty_teams == team_config.team_count,
+                ProvenError::SettlementIncomplete
+            );
+
+            challenge.bonus_per_winner = 0;
+            team_config.bonus_per_team = distribution.bonus_per_share;
+            challenge.remainder = distribution.remainder;
         } else {
+            challenge.bonus_per_winner = distribution.bonus_per_share;
+            challenge.remainder = distribution.remainder;
+        }
+        if winning_shares == 0 {
             // No winners - all distributable stakes remain in escrow (edge case)
-            challenge.bonus_per_winner = 0;
-            challenge.remainder = distributable_amount;
-            challenge.remainder_claimed = distributable_amount;
+            challenge.remainder_claimed = challenge.remainder;
         }
 
         challenge.status = ChallengeStatus::Settled;
@@ -300,56 +384,152 @@ pub mod proven_stake {
             bonus_per_winner: challenge.bonus_per_winner,
         });
 
+        if let Some(beneficiary) = challenge.forfeit_beneficiary {
+            let token_mint = challenge.token_mint;
+            let challenge_key = challenge.key();
+            let beneficiary_token_account = ctx
+                .accounts
+                .beneficiary_token_account
+                .as_ref()
+                .ok_or(ProvenError::BeneficiaryMissing)?;
+            require!(
+                beneficiary_token_account.owner == beneficiary
+                    && beneficiary_token_account.mint == token_mint,
+                ProvenError::BeneficiaryMissing
+            );
+            let escrow_vault = ctx
+                .accounts
+                .escrow_vault
+                .as_ref()
+                .ok_or(ProvenError::BeneficiaryMissing)?;
+            let token_program = ctx
+                .accounts
+                .token_program
+                .as_ref()
+                .ok_or(ProvenError::BeneficiaryMissing)?;
+
+            if forfeited > 0 {
+                // Store values needed for CPI
+                let id_seed = ctx.accounts.challenge.id_seed().to_vec();
+                let admin = ctx.accounts.challenge.admin;
+                let bump = ctx.accounts.challenge.bump;
+
+                let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
+                let signer = &[&seeds[..]];
+
+                let cpi_accounts = Transfer {
+                    from: escrow_vault.to_account_info(),
+                    to: beneficiary_token_account.to_account_info(),
+                    authority: ctx.accounts.challenge.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    cpi_accounts,
+                    signer,
+                );
+                token::transfer(cpi_ctx, forfeited)?;
+            }
+
+            emit!(StakesForfeited {
+                challenge_id: challenge_key,
+                beneficiary,
+                amount: forfeited,
+            });
+        }
+
         Ok(())
     }
 
-    pub fn claim_payout(ctx: Context<ClaimPayout>, challenge_id: String) -> Result<()> {
+    /// Projects what `finalize_settlement` would book if the challenge were
+    /// settled now, without writing anything. Every `Participant` account
+    /// must be passed in `remaining_accounts`; participants not yet settled
+    /// are judged on their proof days so far. Meant to be simulated, with
+    /// the result read back from the return data.
+    pub fn preview_settlement<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PreviewSettlement<'info>>,
+    ) -> Result<SettlementPreview> {
         let challenge = &ctx.accounts.challenge;
-        let participant = &mut ctx.accounts.participant;
 
         require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
-        require!(
-            challenge.status == ChallengeStatus::Settled,
-            ProvenError::ChallengeNotSettled
-        );
-        require!(participant.is_settled, ProvenError::NotSettled);
-        require!(participant.is_winner, ProvenError::NotWinner);
-        require!(
-            !participant.payout_claimed,
-            ProvenError::PayoutAlreadyClaimed
+            challenge.status == ChallengeStatus::Created
+                || challenge.status == ChallengeStatus::Started
+                || challenge.status == ChallengeStatus::Ended,
+            ProvenError::InvalidChallengeStatus
         );
+        require!(!challenge.team_mode, ProvenError::TeamChallenge);
         require!(
-            challenge.payouts_claimed_count < challenge.winner_count,
-            ProvenError::AllPayoutsClaimed
+            ctx.remaining_accounts.len() == challenge.participant_count as usize,
+            ProvenError::ParticipantsIncomplete
         );
 
-        // Calculate total payout (original stake + bonus)
-        let mut bonus = challenge.bonus_per_winner;
-        let mut remainder_increment = 0;
-        if challenge.remainder_claimed < challenge.remainder {
-            bonus = bonus.checked_add(1).ok_or(ProvenError::MathOverflow)?;
-            remainder_increment = 1;
+        let required_days =
+            settlement::required_days(challenge.total_days, challenge.threshold_bps);
+
+        let mut seen = Vec::with_capacity(ctx.remaining_accounts.len());
+        let (mut winner_count, mut loser_count) = (0u32, 0u32);
+        for account_info in ctx.remaining_accounts.iter() {
+            let participant = Account::<Participant>::try_from(account_info)?;
+            require_keys_eq!(
+                participant.challenge,
+                challenge.key(),
+                ProvenError::ParticipantsIncomplete
+            );
+            require!(
+                !seen.contains(&participant.key()),
+                ProvenError::ParticipantsIncomplete
+            );
+            seen.push(participant.key());
+
+            let is_winner = if participant.is_settled {
+                participant.is_winner
+            } else {
+                participant.proof_days >= required_days
+            };
+            if is_winner {
+                winner_count += 1;
+            } else {
+                loser_count += 1;
+            }
         }
-        let payout_amount = challenge
-            .stake_amount
-            .checked_add(bonus)
-            .ok_or(ProvenError::MathOverflow)?;
+
+        let distribution = settlement::distribute(
+            challenge.stake_amount,
+            loser_count,
+            winner_count,
+            challenge.platform_fee_bps,
+        )
+        .ok_or(ProvenError::MathOverflow)?;
+        let mut preview = SettlementPreview {
+            winner_count,
+            loser_count,
+            platform_fee: distribution.platform_fee,
+            bonus_per_winner: distribution.bonus_per_share,
+            remainder: distribution.remainder,
+            forfeited: 0,
+        };
+        if challenge.forfeit_beneficiary.is_some() {
+            preview.bonus_per_winner = 0;
+            preview.remainder = 0;
+            preview.forfeited = distribution.distributable;
+        }
+
+        Ok(preview)
+    }
+
+    pub fn claim_payout(ctx: Context<ClaimPayout>) -> Result<()> {
+        let payout_amount = ctx
+            .accounts
+            .challenge
+            .take_winner_payout(&mut ctx.accounts.participant)?;
+        let challenge = &ctx.accounts.challenge;
 
         // Store values needed for CPI
-        let challenge_id = challenge.challenge_id.clone();
+        let id_seed = challenge.id_seed().to_vec();
         let admin = challenge.admin;
         let bump = challenge.bump;
 
         // Transfer tokens from escrow vault to user
-        let seeds = &[
-            b"challenge",
-            challenge_id.as_bytes(),
-            admin.as_ref(),
-            &[bump],
-        ];
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
         let signer = &[&seeds[..]];
 
         let cpi_accounts = Transfer {
@@ -361,39 +541,21 @@ pub mod proven_stake {
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::transfer(cpi_ctx, payout_amount)?;
 
-        participant.payout_claimed = true;
-        ctx.accounts.challenge.payouts_claimed_count = ctx
-            .accounts
-            .challenge
-            .payouts_claimed_count
-            .checked_add(1)
-            .ok_or(ProvenError::MathOverflow)?;
-        ctx.accounts.challenge.remainder_claimed = ctx
-            .accounts
-            .challenge
-            .remainder_claimed
-            .checked_add(remainder_increment)
-            .ok_or(ProvenError::MathOverflow)?;
-
         emit!(PayoutClaimed {
             challenge_id: ctx.accounts.challenge.key(),
-            user: participant.user,
+            user: ctx.accounts.participant.user,
             amount: payout_amount,
         });
 
         Ok(())
     }
 
-    pub fn cancel_challenge(ctx: Context<CancelChallenge>, challenge_id: String) -> Result<()> {
+    pub fn cancel_challenge(ctx: Context<CancelChallenge>) -> Result<()> {
         let challenge = &mut ctx.accounts.challenge;
         let clock = Clock::get()?;
 
         require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
-        require!(
-            challenge.admin == ctx.accounts.admin.key(),
+            challenge.authority == ctx.accounts.admin.key(),
             ProvenError::Unauthorized
         );
         require!(
@@ -409,20 +571,16 @@ pub mod proven_stake {
 
         emit!(ChallengeCancelled {
             challenge_id: challenge.key(),
-            admin: challenge.admin,
+            admin: challenge.authority,
         });
 
         Ok(())
     }
 
-    pub fn claim_refund(ctx: Context<ClaimRefund>, challenge_id: String) -> Result<()> {
+    pub fn claim_refund(ctx: Context<ClaimRefund>) -> Result<()> {
         let challenge = &ctx.accounts.challenge;
         let participant = &mut ctx.accounts.participant;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             challenge.status == ChallengeStatus::Cancelled,
             ProvenError::NotCancelled
@@ -431,17 +589,12 @@ pub mod proven_stake {
         require!(!participant.refund_claimed, ProvenError::AlreadyClaimed);
 
         // Store values needed for CPI
-        let challenge_id = challenge.challenge_id.clone();
+        let id_seed = challenge.id_seed().to_vec();
         let admin = challenge.admin;
         let bump = challenge.bump;
 
         // Transfer stake back to user
-        let seeds = &[
-            b"challenge",
-            challenge_id.as_bytes(),
-            admin.as_ref(),
-            &[bump],
-        ];
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
         let signer = &[&seeds[..]];
 
         let cpi_accounts = Transfer {
@@ -464,13 +617,9 @@ pub mod proven_stake {
         Ok(())
     }
 
-    pub fn withdraw_fees(ctx: Context<WithdrawFees>, challenge_id: String) -> Result<()> {
-        require!(
-            ctx.accounts.challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
         require!(
-            ctx.accounts.challenge.admin == ctx.accounts.admin.key(),
+            ctx.accounts.challenge.authority == ctx.accounts.admin.key(),
             ProvenError::Unauthorized
         );
         require!(
@@ -481,18 +630,14 @@ pub mod proven_stake {
         require!(ctx.accounts.challenge.fee_amount > 0, ProvenError::NoFees);
 
         let fee_amount = ctx.accounts.challenge.fee_amount;
-        let challenge_id_str = ctx.accounts.challenge.challenge_id.clone();
+        let id_seed = ctx.accounts.challenge.id_seed().to_vec();
         let admin = ctx.accounts.challenge.admin;
+        let authority = ctx.accounts.challenge.authority;
         let bump = ctx.accounts.challenge.bump;
         let challenge_key = ctx.accounts.challenge.key();
 
         // Transfer fees from escrow vault to admin
-        let seeds = &[
-            b"challenge",
-            challenge_id_str.as_bytes(),
-            admin.as_ref(),
-            &[bump],
-        ];
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
         let signer = &[&seeds[..]];
 
         let cpi_accounts = Transfer {
@@ -508,29 +653,113 @@ pub mod proven_stake {
 
         emit!(FeeWithdrawn {
             challenge_id: challenge_key,
-            admin: admin,
+            admin: authority,
             amount: fee_amount,
         });
 
         Ok(())
     }
 
-    pub fn close_participant(ctx: Context<CloseParticipant>, challenge_id: String) -> Result<()> {
+    pub fn withdraw_treasury_fees(ctx: Context<WithdrawTreasuryFees>) -> Result<()> {
+        require!(
+            ctx.accounts.protocol_config.authority == ctx.accounts.authority.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            ctx.accounts.challenge.status == ChallengeStatus::Settled
+                || ctx.accounts.challenge.status == ChallengeStatus::Cancelled,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            ctx.accounts.challenge.treasury_fee_amount > 0,
+            ProvenError::NoFees
+        );
+
+        let amount = ctx.accounts.challenge.treasury_fee_amount;
+        let id_seed = ctx.accounts.challenge.id_seed().to_vec();
+        let admin = ctx.accounts.challenge.admin;
+        let bump = ctx.accounts.challenge.bump;
+        let challenge_key = ctx.accounts.challenge.key();
+
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.treasury_token_account.to_account_info(),
+            authority: ctx.accounts.challenge.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        ctx.accounts.challenge.treasury_fee_amount = 0;
+
+        emit!(FeeShareWithdrawn {
+            challenge_id: challenge_key,
+            recipient: ctx.accounts.protocol_config.treasury,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_charity_fees(ctx: Context<WithdrawCharityFees>) -> Result<()> {
+        require!(
+            ctx.accounts.challenge.charity == Some(ctx.accounts.charity.key()),
+            ProvenError::Unauthorized
+        );
+        require!(
+            ctx.accounts.challenge.status == ChallengeStatus::Settled
+                || ctx.accounts.challenge.status == ChallengeStatus::Cancelled,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            ctx.accounts.challenge.charity_fee_amount > 0,
+            ProvenError::NoFees
+        );
+
+        let amount = ctx.accounts.challenge.charity_fee_amount;
+        let id_seed = ctx.accounts.challenge.id_seed().to_vec();
+        let admin = ctx.accounts.challenge.admin;
+        let bump = ctx.accounts.challenge.bump;
+        let challenge_key = ctx.accounts.challenge.key();
+
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.charity_token_account.to_account_info(),
+            authority: ctx.accounts.challenge.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        ctx.accounts.challenge.charity_fee_amount = 0;
+
+        emit!(FeeShareWithdrawn {
+            challenge_id: challenge_key,
+            recipient: ctx.accounts.charity.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_participant(ctx: Context<CloseParticipant>) -> Result<()> {
         let authority = &ctx.accounts.authority;
         let challenge = &mut ctx.accounts.challenge;
         let participant = &ctx.accounts.participant;
         let destination = &ctx.accounts.destination;
 
-        require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
-        );
         require!(
             participant.user == destination.key(),
             ProvenError::Unauthorized
         );
         require!(
-            authority.key() == participant.user || authority.key() == challenge.admin,
+            authority.key() == participant.user || authority.key() == challenge.authority,
             ProvenError::Unauthorized
         );
 
@@ -561,15 +790,14 @@ pub mod proven_stake {
         Ok(())
     }
 
-    pub fn close_challenge(ctx: Context<CloseChallenge>, challenge_id: String) -> Result<()> {
+    pub fn close_challenge(ctx: Context<CloseChallenge>) -> Result<()> {
         let admin = &ctx.accounts.admin;
         let challenge = &mut ctx.accounts.challenge;
 
         require!(
-            challenge.challenge_id == challenge_id,
-            ProvenError::ChallengeIdMismatch
+            challenge.authority == admin.key(),
+            ProvenError::Unauthorized
         );
-        require!(challenge.admin == admin.key(), ProvenError::Unauthorized);
 
         match challenge.status {
             ChallengeStatus::Settled => {
@@ -581,10 +809,14 @@ pub mod proven_stake {
                     challenge.remainder_claimed == challenge.remainder,
                     ProvenError::PendingRemainderDistribution
                 );
-                require!(challenge.fee_amount == 0, ProvenError::FeesUncollected);
+                require!(challenge.fees_collected(), ProvenError::FeesUncollected);
+                require!(
+                    challenge.referral_owed == 0,
+                    ProvenError::ReferralsUnclaimed
+                );
             }
             ChallengeStatus::Cancelled => {
-                require!(challenge.fee_amount == 0, ProvenError::FeesUncollected);
+                require!(challenge.fees_collected(), ProvenError::FeesUncollected);
             }
             _ => return err!(ProvenError::ChallengeStillActive),
         }
@@ -596,438 +828,4367 @@ pub mod proven_stake {
 
         emit!(ChallengeClosed {
             challenge_id: challenge.key(),
-            admin: challenge.admin,
+            admin: challenge.authority,
         });
 
         Ok(())
     }
-}
-
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct CreateChallenge<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    #[account(
-        init,
-        payer = admin,
-        space = 8 + Challenge::LEN,
-        seeds = [b"challenge", challenge_id.as_bytes(), admin.key().as_ref()],
-        bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    pub token_mint: Account<'info, Mint>,
-    #[account(
-        init,
-        payer = admin,
-        associated_token::mint = token_mint,
-        associated_token::authority = challenge,
-    )]
-    pub escrow_vault: Account<'info, TokenAccount>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-}
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct JoinChallenge<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    #[account(
-        init,
-        payer = user,
-        space = 8 + Participant::LEN,
-        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
-        bump,
-    )]
-    pub participant: Account<'info, Participant>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = user,
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = challenge,
-    )]
-    pub escrow_vault: Account<'info, TokenAccount>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-}
+    pub fn amend_challenge(
+        ctx: Context<AmendChallenge>,
+        total_days: u32,
+        threshold_bps: u16,
+    ) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct RecordProof<'info> {
-    pub oracle: Signer<'info>,
-    #[account(
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created
+                || challenge.status == ChallengeStatus::Started,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp <= challenge.end_ts,
+            ProvenError::ChallengeEnded
+        );
+        require!(total_days > 0, ProvenError::InvalidDuration);
+        require!(threshold_bps <= 10000, ProvenError::InvalidThreshold);
+
+        // Once started, participants may only be given more time, by a bounded
+        // amount, and no more days to prove
+        if clock.unix_timestamp >= challenge.start_ts {
+            require!(
+                total_days >= challenge.total_days
+                    && total_days - challenge.total_days <= Challenge::MAX_EXTENSION_DAYS
+                    && settlement::required_days(total_days, threshold_bps)
+                        <= settlement::required_days(challenge.total_days, challenge.threshold_bps),
+                ProvenError::InvalidAmendment
+            );
+        }
+
+        let old_total_days = challenge.total_days;
+        let old_threshold_bps = challenge.threshold_bps;
+        let old_end_ts = challenge.end_ts;
+
+        // required_days is derived at settlement, so it follows the new values
+        challenge.total_days = total_days;
+        challenge.threshold_bps = threshold_bps;
+        challenge.end_ts = challenge.start_ts + (total_days as i64 * 24 * 60 * 60);
+
+        emit!(ChallengeAmended {
+            challenge_id: challenge.key(),
+            old_total_days,
+            new_total_days: total_days,
+            old_threshold_bps,
+            new_threshold_bps: threshold_bps,
+            old_end_ts,
+            new_end_ts: challenge.end_ts,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_oracle(ctx: Context<UpdateOracle>, new_oracle: Pubkey) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status != ChallengeStatus::Settled
+                && challenge.status != ChallengeStatus::Cancelled,
+            ProvenError::InvalidChallengeStatus
+        );
+        let delay = challenge
+            .oracle_update_delay(ctx.accounts.oracle_registry.as_ref(), clock.unix_timestamp)?;
+        require!(delay == 0, ProvenError::OracleUpdateTimelocked);
+
+        let old_oracle = challenge.oracle_signer;
+
+        // A direct oracle replaces any registry link
+        challenge.oracle_signer = new_oracle;
+        challenge.oracle_registry = None;
+
+        emit!(OracleUpdated {
+            challenge_id: challenge.key(),
+            old_oracle,
+            new_oracle,
+        });
+
+        Ok(())
+    }
+
+    pub fn link_oracle_registry(ctx: Context<LinkOracleRegistry>) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status != ChallengeStatus::Settled
+                && challenge.status != ChallengeStatus::Cancelled,
+            ProvenError::InvalidChallengeStatus
+        );
+        let delay = challenge
+            .oracle_update_delay(ctx.accounts.current_registry.as_ref(), clock.unix_timestamp)?;
+        require!(delay == 0, ProvenError::OracleUpdateTimelocked);
+
+        challenge.oracle_registry = Some(ctx.accounts.oracle_registry.key());
+
+        emit!(OracleRegistryLinked {
+            challenge_id: challenge.key(),
+            registry: ctx.accounts.oracle_registry.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Queues `new_oracle` as the challenge's direct signer or, with
+    /// `new_registry`, a link to that registry. Once the challenge has
+    /// started, a change away from a linked registry waits out that
+    /// registry's `update_delay`, like a rotation of the registry itself.
+    pub fn propose_oracle_update(
+        ctx: Context<ProposeOracleUpdate>,
+        new_oracle: Pubkey,
+    ) -> Result<()> {
+        let challenge = &ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status != ChallengeStatus::Settled
+                && challenge.status != ChallengeStatus::Cancelled,
+            ProvenError::InvalidChallengeStatus
+        );
+        let delay = challenge
+            .oracle_update_delay(ctx.accounts.oracle_registry.as_ref(), clock.unix_timestamp)?;
+        let eta = clock
+            .unix_timestamp
+            .checked_add(delay)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        let pending = &mut ctx.accounts.pending_oracle;
+        pending.challenge = challenge.key();
+        pending.oracle_signer = new_oracle;
+        pending.oracle_registry = ctx.accounts.new_registry.as_ref().map(|r| r.key());
+        pending.eta = eta;
+        pending.bump = ctx.bumps.pending_oracle;
+
+        emit!(OracleUpdateQueued {
+            challenge_id: challenge.key(),
+            new_oracle,
+            new_registry: pending.oracle_registry,
+            eta,
+        });
+
+        Ok(())
+    }
+
+    pub fn apply_oracle_update(ctx: Context<ApplyOracleUpdate>) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let pending = &ctx.accounts.pending_oracle;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status != ChallengeStatus::Settled
+                && challenge.status != ChallengeStatus::Cancelled,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp >= pending.eta,
+            ProvenError::RotationTimelocked
+        );
+
+        let old_oracle = challenge.oracle_signer;
+        challenge.oracle_signer = pending.oracle_signer;
+        challenge.oracle_registry = pending.oracle_registry;
+
+        emit!(OracleUpdated {
+            challenge_id: challenge.key(),
+            old_oracle,
+            new_oracle: pending.oracle_signer,
+        });
+        if let Some(registry) = pending.oracle_registry {
+            emit!(OracleRegistryLinked {
+                challenge_id: challenge.key(),
+                registry,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn cancel_oracle_update(ctx: Context<CancelOracleUpdate>) -> Result<()> {
+        require!(
+            ctx.accounts.challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        Ok(())
+    }
+
+    pub fn propose_admin(ctx: Context<ProposeAdmin>, new_admin: Pubkey) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+
+        challenge.pending_authority = Some(new_admin);
+
+        emit!(AdminProposed {
+            challenge_id: challenge.key(),
+            current_admin: challenge.authority,
+            proposed_admin: new_admin,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+
+        require!(
+            challenge.pending_authority == Some(ctx.accounts.new_admin.key()),
+            ProvenError::NotPendingAdmin
+        );
+
+        let previous_admin = challenge.authority;
+        challenge.authority = ctx.accounts.new_admin.key();
+        challenge.pending_authority = None;
+
+        emit!(AdminTransferred {
+            challenge_id: challenge.key(),
+            previous_admin,
+            new_admin: challenge.authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn migrate_challenge(ctx: Context<MigrateChallenge>) -> Result<()> {
+        let info = ctx.accounts.challenge.to_account_info();
+        let challenge = Challenge::from_legacy_data(&info.try_borrow_data()?)?;
+
+        resize_account(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + Challenge::INIT_SPACE,
+        )?;
+        challenge.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            version: challenge.version,
+        });
+
+        Ok(())
+    }
+
+    pub fn migrate_participant(ctx: Context<MigrateParticipant>) -> Result<()> {
+        let info = ctx.accounts.participant.to_account_info();
+        let participant = Participant::from_legacy_data(&info.try_borrow_data()?)?;
+
+        resize_account(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + Participant::INIT_SPACE,
+        )?;
+        participant.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            version: participant.version,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_series(
+        ctx: Context<CreateSeries>,
+        series_id: String,
+        stake_amount: u64,
+        total_days: u32,
+        threshold_bps: u16,
+        platform_fee_bps: u16,
+        first_start_ts: i64,
+        interval_secs: i64,
+        oracle_signer: Pubkey,
+    ) -> Result<()> {
+        Challenge::validate_terms(stake_amount, total_days, threshold_bps, platform_fee_bps)?;
+        require!(
+            first_start_ts > Clock::get()?.unix_timestamp,
+            ProvenError::InvalidStartTime
+        );
+        require!(
+            interval_secs >= total_days as i64 * 24 * 60 * 60,
+            ProvenError::InvalidInterval
+        );
+        require!(!series_id.is_empty(), ProvenError::ChallengeIdEmpty);
+        require!(
+            series_id.len() <= ChallengeSeries::MAX_ID_LENGTH,
+            ProvenError::ChallengeIdTooLong
+        );
+
+        let series = &mut ctx.accounts.series;
+        series.version = ChallengeSeries::VERSION;
+        series.series_id = series_id;
+        series.admin = ctx.accounts.admin.key();
+        series.token_mint = ctx.accounts.token_mint.key();
+        series.oracle_signer = oracle_signer;
+        series.stake_amount = stake_amount;
+        series.total_days = total_days;
+        series.threshold_bps = threshold_bps;
+        series.platform_fee_bps = platform_fee_bps;
+        series.interval_secs = interval_secs;
+        series.next_edition = 1;
+        series.next_start_ts = first_start_ts;
+        series.bump = ctx.bumps.series;
+
+        emit!(SeriesCreated {
+            series: series.key(),
+            admin: series.admin,
+            stake_amount,
+            total_days,
+            first_start_ts,
+            interval_secs,
+        });
+
+        Ok(())
+    }
+
+    pub fn spawn_next(ctx: Context<SpawnNext>) -> Result<()> {
+        let series = &mut ctx.accounts.series;
+        let clock = Clock::get()?;
+
+        // Editions are spawned at most one interval ahead of their start
+        require!(
+            clock.unix_timestamp >= series.next_start_ts - series.interval_secs,
+            ProvenError::SpawnTooEarly
+        );
+
+        // A late spawn keeps the edition number but moves to the next slot
+        // still in the future, so start times stay on the series cadence
+        let mut start_ts = series.next_start_ts;
+        if start_ts <= clock.unix_timestamp {
+            let missed = (clock.unix_timestamp - start_ts) / series.interval_secs + 1;
+            start_ts = missed
+                .checked_mul(series.interval_secs)
+                .and_then(|offset| start_ts.checked_add(offset))
+                .ok_or(ProvenError::MathOverflow)?;
+        }
+
+        let edition = series.next_edition;
+        let challenge = &mut ctx.accounts.challenge;
+        challenge.initialize(
+            series.edition_id(edition),
+            series.admin,
+            series.token_mint,
+            ctx.accounts.escrow_vault.key(),
+            series.stake_amount,
+            series.total_days,
+            series.threshold_bps,
+            series.platform_fee_bps,
+            start_ts,
+            series.oracle_signer,
+            ctx.bumps.challenge,
+        );
+        challenge.series = Some(series.key());
+        challenge.edition = edition;
+
+        series.next_edition = edition.checked_add(1).ok_or(ProvenError::MathOverflow)?;
+        series.next_start_ts = start_ts
+            .checked_add(series.interval_secs)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        emit!(ChallengeCreated {
+            challenge_id: challenge.key(),
+            admin: challenge.admin,
+            stake_amount: challenge.stake_amount,
+            total_days: challenge.total_days,
+            start_ts,
+        });
+        emit!(SeriesEditionSpawned {
+            series: series.key(),
+            challenge_id: challenge.key(),
+            edition,
+            start_ts,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_auto_roll(ctx: Context<SetAutoRoll>, enabled: bool) -> Result<()> {
+        let challenge = &ctx.accounts.challenge;
+        let participant = &mut ctx.accounts.participant;
+
+        require!(challenge.series.is_some(), ProvenError::NotSeriesChallenge);
+        require!(
+            !participant.payout_claimed && !participant.refund_claimed,
+            ProvenError::AlreadyClaimed
+        );
+
+        participant.auto_roll = enabled;
+
+        emit!(AutoRollUpdated {
+            challenge_id: challenge.key(),
+            user: participant.user,
+            enabled,
+        });
+
+        Ok(())
+    }
+
+    pub fn roll_over(ctx: Context<RollOver>) -> Result<()> {
+        let series_key = ctx.accounts.series.key();
+        let clock = Clock::get()?;
+
+        let challenge = &ctx.accounts.challenge;
+        let next_challenge = &ctx.accounts.next_challenge;
+        require!(
+            challenge.series == Some(series_key) && next_challenge.series == Some(series_key),
+            ProvenError::SeriesMismatch
+        );
+        require!(
+            challenge.edition.checked_add(1) == Some(next_challenge.edition),
+            ProvenError::NotNextEdition
+        );
+        require!(
+            next_challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < next_challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+        require!(
+            ctx.accounts.participant.auto_roll,
+            ProvenError::AutoRollDisabled
+        );
+        // A rolled participant has no team, so a team edition could never settle
+        require!(!next_challenge.team_mode, ProvenError::TeamChallenge);
+        // Rolling skips the allowlist proof and voucher that gate a join
+        require!(
+            !next_challenge.allowlisted && !next_challenge.voucher_required,
+            ProvenError::GatedEdition
+        );
+
+        let payout_amount = ctx
+            .accounts
+            .challenge
+            .take_winner_payout(&mut ctx.accounts.participant)?;
+        let stake_amount = ctx.accounts.next_challenge.stake_amount;
+        let excess_amount = payout_amount
+            .checked_sub(stake_amount)
+            .ok_or(ProvenError::InsufficientRollover)?;
+
+        // Store values needed for CPI
+        let challenge = &ctx.accounts.challenge;
+        let id_seed = challenge.id_seed().to_vec();
+        let admin = challenge.admin;
+        let bump = challenge.bump;
+
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        // Move the next edition's stake between escrows
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.next_escrow_vault.to_account_info(),
+            authority: ctx.accounts.challenge.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, stake_amount)?;
+
+        // Pay out whatever exceeds the next stake
+        if excess_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.escrow_vault.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.challenge.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, excess_amount)?;
+        }
+
+        let next_challenge = &mut ctx.accounts.next_challenge;
+        let next_participant = &mut ctx.accounts.next_participant;
+        next_participant.initialize(
+            ctx.accounts.user.key(),
+            next_challenge.key(),
+            stake_amount,
+            ctx.bumps.next_participant,
+        );
+        next_participant.auto_roll = true;
+
+        next_challenge.participant_count = next_challenge
+            .participant_count
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+        next_challenge.active_participants = next_challenge
+            .active_participants
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        emit!(StakeRolledOver {
+            from_challenge: ctx.accounts.challenge.key(),
+            to_challenge: next_challenge.key(),
+            user: ctx.accounts.user.key(),
+            payout_amount,
+            rolled_amount: stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Restricts joining to wallets in the Merkle tree with the given root.
+    pub fn create_allowlist(ctx: Context<CreateAllowlist>, root: [u8; 32]) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+
+        challenge.allowlisted = true;
+
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.version = Allowlist::VERSION;
+        allowlist.challenge = challenge.key();
+        allowlist.root = root;
+        allowlist.bump = ctx.bumps.allowlist;
+
+        emit!(AllowlistUpdated {
+            challenge_id: challenge.key(),
+            root,
+        });
+
+        Ok(())
+    }
+
+    /// Replaces the allowlist root. Wallets that already joined stay in.
+    pub fn update_allowlist_root(ctx: Context<UpdateAllowlistRoot>, root: [u8; 32]) -> Result<()> {
+        let challenge = &ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+
+        ctx.accounts.allowlist.root = root;
+
+        emit!(AllowlistUpdated {
+            challenge_id: challenge.key(),
+            root,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the share of each referred loser's fee contribution paid to
+    /// their referrer.
+    pub fn set_referral_bps(ctx: Context<SetReferralBps>, referral_bps: u16) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+        require!(referral_bps <= 10000, ProvenError::InvalidFee);
+
+        challenge.referral_bps = referral_bps;
+
+        emit!(ReferralRateUpdated {
+            challenge_id: challenge.key(),
+            referral_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Opens the accrual a referrer's rewards on this challenge collect in.
+    /// Anyone may pay for it; it must exist before referred users join.
+    pub fn open_referral_accrual(
+        ctx: Context<OpenReferralAccrual>,
+        referrer: Pubkey,
+    ) -> Result<()> {
+        let accrual = &mut ctx.accounts.referral_accrual;
+        accrual.version = ReferralAccrual::VERSION;
+        accrual.challenge = ctx.accounts.challenge.key();
+        accrual.referrer = referrer;
+        accrual.referred_count = 0;
+        accrual.accrued = 0;
+        accrual.claimed = false;
+        accrual.bump = ctx.bumps.referral_accrual;
+
+        Ok(())
+    }
+
+    pub fn claim_referral(ctx: Context<ClaimReferral>) -> Result<()> {
+        let accrual = &mut ctx.accounts.referral_accrual;
+
+        require!(
+            ctx.accounts.challenge.status == ChallengeStatus::Settled,
+            ProvenError::ChallengeNotSettled
+        );
+        require!(!accrual.claimed, ProvenError::PayoutAlreadyClaimed);
+        require!(accrual.accrued > 0, ProvenError::NoReferralRewards);
+
+        let amount = accrual.accrued;
+        accrual.claimed = true;
+
+        let challenge = &mut ctx.accounts.challenge;
+        challenge.referral_owed = challenge
+            .referral_owed
+            .checked_sub(amount)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        // Store values needed for CPI
+        let id_seed = challenge.id_seed().to_vec();
+        let admin = challenge.admin;
+        let bump = challenge.bump;
+
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.referrer_token_account.to_account_info(),
+            authority: ctx.accounts.challenge.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        emit!(ReferralRewardClaimed {
+            challenge_id: ctx.accounts.challenge.key(),
+            referrer: ctx.accounts.referrer.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Requires joins to carry a `JoinVoucher` signed by the challenge
+    /// authority or oracle.
+    pub fn set_voucher_required(ctx: Context<SetVoucherRequired>, required: bool) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+
+        challenge.voucher_required = required;
+
+        emit!(VoucherRequirementUpdated {
+            challenge_id: challenge.key(),
+            required,
+        });
+
+        Ok(())
+    }
+
+    /// Sends the loser pool, less fees, to an approved beneficiary instead
+    /// of splitting it among winners.
+    pub fn enable_forfeiture(ctx: Context<EnableForfeiture>) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let beneficiary = &ctx.accounts.beneficiary;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+        require!(!challenge.team_mode, ProvenError::TeamChallenge);
+        require!(beneficiary.active, ProvenError::BeneficiaryInactive);
+
+        challenge.forfeit_beneficiary = Some(beneficiary.wallet);
+
+        emit!(ForfeitureEnabled {
+            challenge_id: challenge.key(),
+            beneficiary: beneficiary.wallet,
+        });
+
+        Ok(())
+    }
+
+    pub fn enable_teams(ctx: Context<EnableTeams>, scoring: TeamScoring) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.authority == ctx.accounts.admin.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+        require!(
+            challenge.participant_count == 0,
+            ProvenError::ParticipantsRemaining
+        );
+        require!(
+            challenge.forfeit_beneficiary.is_none(),
+            ProvenError::ForfeitureChallenge
+        );
+
+        challenge.team_mode = true;
+
+        let team_config = &mut ctx.accounts.team_config;
+        team_config.version = TeamConfig::VERSION;
+        team_config.challenge = challenge.key();
+        team_config.scoring = scoring.clone();
+        team_config.team_count = 0;
+        team_config.teams_settled = 0;
+        team_config.empty_teams = 0;
+        team_config.winning_teams = 0;
+        team_config.bonus_per_team = 0;
+        team_config.bump = ctx.bumps.team_config;
+
+        emit!(TeamsEnabled {
+            challenge_id: challenge.key(),
+            scoring,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_team(ctx: Context<CreateTeam>, name: String) -> Result<()> {
+        let challenge = &ctx.accounts.challenge;
+        let team_config = &mut ctx.accounts.team_config;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+        require!(
+            !name.is_empty() && name.len() <= Team::MAX_NAME_LENGTH,
+            ProvenError::InvalidTeamName
+        );
+
+        let team = &mut ctx.accounts.team;
+        team.version = Team::VERSION;
+        team.challenge = challenge.key();
+        team.index = team_config.team_count;
+        team.captain = ctx.accounts.captain.key();
+        team.name = name;
+        team.member_count = 0;
+        team.score = 0;
+        team.is_settled = false;
+        team.is_winner = false;
+        team.share_taken = false;
+        team.bonus_per_member = 0;
+        team.remainder = 0;
+        team.remainder_claimed = 0;
+        team.bump = ctx.bumps.team;
+
+        team_config.team_count = team_config
+            .team_count
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+        team_config.empty_teams = team_config
+            .empty_teams
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        emit!(TeamCreated {
+            challenge_id: challenge.key(),
+            team: team.key(),
+            captain: team.captain,
+            index: team.index,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the rent of a team no one joined to its captain. It still
+    /// counts towards `team_count`, so later teams keep their indices.
+    pub fn close_team(ctx: Context<CloseTeam>) -> Result<()> {
+        let team = &ctx.accounts.team;
+        require!(team.member_count == 0, ProvenError::TeamNotEmpty);
+
+        emit!(TeamClosed {
+            challenge_id: ctx.accounts.challenge.key(),
+            team: team.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn join_team(ctx: Context<JoinTeam>, proof: Vec<[u8; 32]>) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let team = &mut ctx.accounts.team;
+        let participant = &mut ctx.accounts.participant;
+        let clock = Clock::get()?;
+
+        require!(
+            challenge.status == ChallengeStatus::Created,
+            ProvenError::InvalidChallengeStatus
+        );
+        require!(
+            clock.unix_timestamp < challenge.start_ts,
+            ProvenError::ChallengeStarted
+        );
+        challenge.check_allowlist(
+            ctx.accounts.allowlist.as_deref(),
+            &ctx.accounts.user.key(),
+            &proof,
+        )?;
+        challenge.check_voucher(
+            challenge.key(),
+            ctx.accounts.instructions.as_ref(),
+            ctx.accounts.oracle_registry.as_deref(),
+            &ctx.accounts.user.key(),
+            clock.unix_timestamp,
+        )?;
+        require!(team.member_count < Team::MAX_MEMBERS, ProvenError::TeamFull);
+
+        // Transfer USDC from user to escrow vault
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.escrow_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, challenge.stake_amount)?;
+
+        participant.initialize(
+            ctx.accounts.user.key(),
+            challenge.key(),
+            challenge.stake_amount,
+            ctx.bumps.participant,
+        );
+        participant.team = Some(team.key());
+
+        if team.member_count == 0 {
+            // Saturating: configs from before `empty_teams` never counted them
+            let team_config = &mut ctx.accounts.team_config;
+            team_config.empty_teams = team_config.empty_teams.saturating_sub(1);
+        }
+        team.member_count += 1;
+        challenge.participant_count = challenge
+            .participant_count
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+        challenge.active_participants = challenge
+            .active_participants
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        emit!(ChallengeJoined {
+            challenge_id: challenge.key(),
+            user: ctx.accounts.user.key(),
+            stake_amount: challenge.stake_amount,
+        });
+        emit!(TeamJoined {
+            challenge_id: challenge.key(),
+            team: team.key(),
+            user: ctx.accounts.user.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Settles a team and all of its members at once. Every member's
+    /// `Participant` account must be passed, writable, in `remaining_accounts`.
+    pub fn settle_team<'info>(ctx: Context<'_, '_, 'info, 'info, SettleTeam<'info>>) -> Result<()> {
+        let challenge = &mut ctx.accounts.challenge;
+        let team_config = &mut ctx.accounts.team_config;
+        let team = &mut ctx.accounts.team;
+
+        require!(
+            challenge.status == ChallengeStatus::Ended,
+            ProvenError::InvalidChallengeStatus
+        );
+        challenge.verify_oracle(
+            ctx.accounts.oracle.key(),
+            ctx.accounts.oracle_registry.as_ref(),
+        )?;
+        require!(!team.is_settled, ProvenError::AlreadySettled);
+        require!(
+            ctx.remaining_accounts.len() == team.member_count as usize,
+            ProvenError::TeamMembersIncomplete
+        );
+
+        let required_days =
+            settlement::required_days(challenge.total_days, challenge.threshold_bps);
+
+        let mut members = Vec::with_capacity(ctx.remaining_accounts.len());
+        for account_info in ctx.remaining_accounts.iter() {
+            let member = Account::<Participant>::try_from(account_info)?;
+            require_keys_eq!(member.challenge, challenge.key(), ProvenError::TeamMismatch);
+            require!(member.team == Some(team.key()), ProvenError::TeamMismatch);
+            require!(
+                members
+                    .iter()
+                    .all(|seen: &Account<Participant>| seen.key() != member.key()),
+                ProvenError::TeamMembersIncomplete
+            );
+            members.push(member);
+        }
+
+        team.score = team_config
+            .scoring
+            .score(members.iter().map(|m| m.proof_days));
+        team.is_winner = !members.is_empty() && team.score >= required_days;
+        team.is_settled = true;
+
+        for member in members.iter_mut() {
+            require!(!member.is_settled, ProvenError::AlreadySettled);
+            member.is_winner = team.is_winner;
+            member.is_settled = true;
+            member.exit(&crate::ID)?;
+        }
+
+        if team.is_winner {
+            challenge.winner_count += team.member_count;
+            team_config.winning_teams += 1;
+        } else {
+            challenge.loser_count += team.member_count;
+        }
+        if team.member_count == 0 {
+            team_config.empty_teams = team_config.empty_teams.saturating_sub(1);
+        }
+        team_config.teams_settled += 1;
+
+        emit!(TeamSettled {
+            challenge_id: challenge.key(),
+            team: team.key(),
+            score: team.score,
+            required_days,
+            is_winner: team.is_winner,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_team_payout(ctx: Context<ClaimTeamPayout>) -> Result<()> {
+        require!(
+            ctx.accounts.participant.team == Some(ctx.accounts.team.key()),
+            ProvenError::TeamMismatch
+        );
+
+        let payout_amount = ctx.accounts.challenge.take_team_payout(
+            &ctx.accounts.team_config,
+            &mut ctx.accounts.team,
+            &mut ctx.accounts.participant,
+        )?;
+        let challenge = &ctx.accounts.challenge;
+
+        // Store values needed for CPI
+        let id_seed = challenge.id_seed().to_vec();
+        let admin = challenge.admin;
+        let bump = challenge.bump;
+
+        // Transfer tokens from escrow vault to user
+        let seeds = &[b"challenge", id_seed.as_slice(), admin.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.user_token_account.to_account_info(),
+            authority: ctx.accounts.challenge.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, payout_amount)?;
+
+        emit!(PayoutClaimed {
+            challenge_id: ctx.accounts.challenge.key(),
+            user: ctx.accounts.participant.user,
+            amount: payout_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_duel(
+        ctx: Context<CreateDuel>,
+        duel_id: u64,
+        opponent: Pubkey,
+        stake_amount: u64,
+        total_days: u32,
+        threshold_bps: u16,
+        start_ts: i64,
+        oracle_signer: Pubkey,
+    ) -> Result<()> {
+        Challenge::validate_terms(stake_amount, total_days, threshold_bps, 0)?;
+        require!(
+            start_ts > Clock::get()?.unix_timestamp,
+            ProvenError::InvalidStartTime
+        );
+        require_keys_neq!(
+            opponent,
+            ctx.accounts.challenger.key(),
+            ProvenError::InvalidOpponent
+        );
+
+        // Transfer the challenger's stake into the duel escrow
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.challenger_token_account.to_account_info(),
+            to: ctx.accounts.escrow_vault.to_account_info(),
+            authority: ctx.accounts.challenger.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, stake_amount)?;
+
+        let duel = &mut ctx.accounts.duel;
+        duel.version = Duel::VERSION;
+        duel.duel_id = duel_id;
+        duel.challenger = ctx.accounts.challenger.key();
+        duel.opponent = opponent;
+        duel.token_mint = ctx.accounts.token_mint.key();
+        duel.oracle_signer = oracle_signer;
+        duel.stake_amount = stake_amount;
+        duel.total_days = total_days;
+        duel.required_days = settlement::required_days(total_days, threshold_bps);
+        duel.start_ts = start_ts;
+        duel.end_ts = start_ts + (total_days as i64 * 24 * 60 * 60);
+        duel.status = DuelStatus::Pending;
+        duel.challenger_proof_days = 0;
+        duel.opponent_proof_days = 0;
+        duel.challenger_payout = 0;
+        duel.opponent_payout = 0;
+        duel.challenger_claimed = false;
+        duel.opponent_claimed = false;
+        duel.bump = ctx.bumps.duel;
+
+        emit!(DuelCreated {
+            duel: duel.key(),
+            challenger: duel.challenger,
+            opponent,
+            stake_amount,
+            total_days,
+            start_ts,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_duel(ctx: Context<AcceptDuel>) -> Result<()> {
+        let duel = &mut ctx.accounts.duel;
+        let clock = Clock::get()?;
+
+        require!(
+            duel.status == DuelStatus::Pending,
+            ProvenError::InvalidDuelStatus
+        );
+        require_keys_eq!(
+            duel.opponent,
+            ctx.accounts.opponent.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            clock.unix_timestamp < duel.start_ts,
+            ProvenError::ChallengeStarted
+        );
+
+        // Transfer the opponent's matching stake into the duel escrow
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.opponent_token_account.to_account_info(),
+            to: ctx.accounts.escrow_vault.to_account_info(),
+            authority: ctx.accounts.opponent.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, duel.stake_amount)?;
+
+        duel.status = DuelStatus::Active;
+
+        emit!(DuelAccepted {
+            duel: duel.key(),
+            opponent: duel.opponent,
+        });
+
+        Ok(())
+    }
+
+    pub fn record_duel_proof(ctx: Context<RecordDuelProof>, user: Pubkey) -> Result<()> {
+        let duel = &mut ctx.accounts.duel;
+        let clock = Clock::get()?;
+
+        require!(
+            duel.status == DuelStatus::Active,
+            ProvenError::InvalidDuelStatus
+        );
+        require!(
+            clock.unix_timestamp >= duel.start_ts,
+            ProvenError::ChallengeNotStarted
+        );
+        require!(
+            clock.unix_timestamp <= duel.end_ts,
+            ProvenError::ChallengeEnded
+        );
+        require_keys_eq!(
+            ctx.accounts.oracle.key(),
+            duel.oracle_signer,
+            ProvenError::InvalidOracle
+        );
+
+        let proof_days = if user == duel.challenger {
+            duel.challenger_proof_days += 1;
+            duel.challenger_proof_days
+        } else if user == duel.opponent {
+            duel.opponent_proof_days += 1;
+            duel.opponent_proof_days
+        } else {
+            return err!(ProvenError::NotDuelParticipant);
+        };
+
+        emit!(DuelProofRecorded {
+            duel: duel.key(),
+            user,
+            proof_days,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless once the duel has ended; the outcome follows from
+    /// the oracle-recorded proof days alone.
+    pub fn settle_duel(ctx: Context<SettleDuel>) -> Result<()> {
+        let duel = &mut ctx.accounts.duel;
+        let clock = Clock::get()?;
+
+        require!(
+            duel.status == DuelStatus::Active,
+            ProvenError::InvalidDuelStatus
+        );
+        require!(
+            clock.unix_timestamp > duel.end_ts,
+            ProvenError::ChallengeNotEnded
+        );
+
+        let challenger_won = duel.challenger_proof_days >= duel.required_days;
+        let opponent_won = duel.opponent_proof_days >= duel.required_days;
+        let pot = duel
+            .stake_amount
+            .checked_mul(2)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        // A sole completer takes the pot; if both or neither complete,
+        // each side gets their own stake back
+        match (challenger_won, opponent_won) {
+            (true, false) => {
+                duel.challenger_payout = pot;
+                duel.opponent_payout = 0;
+            }
+            (false, true) => {
+                duel.challenger_payout = 0;
+                duel.opponent_payout = pot;
+            }
+            _ => {
+                duel.challenger_payout = duel.stake_amount;
+                duel.opponent_payout = duel.stake_amount;
+            }
+        }
+        duel.challenger_claimed = duel.challenger_payout == 0;
+        duel.opponent_claimed = duel.opponent_payout == 0;
+        duel.status = DuelStatus::Settled;
+
+        emit!(DuelSettled {
+            duel: duel.key(),
+            challenger_won,
+            opponent_won,
+            challenger_payout: duel.challenger_payout,
+            opponent_payout: duel.opponent_payout,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_duel(ctx: Context<ClaimDuel>) -> Result<()> {
+        let duel = &mut ctx.accounts.duel;
+        let user = ctx.accounts.user.key();
+
+        require!(
+            duel.status == DuelStatus::Settled,
+            ProvenError::ChallengeNotSettled
+        );
+
+        let amount = if user == duel.challenger {
+            require!(!duel.challenger_claimed, ProvenError::PayoutAlreadyClaimed);
+            duel.challenger_claimed = true;
+            duel.challenger_payout
+        } else if user == duel.opponent {
+            require!(!duel.opponent_claimed, ProvenError::PayoutAlreadyClaimed);
+            duel.opponent_claimed = true;
+            duel.opponent_payout
+        } else {
+            return err!(ProvenError::NotDuelParticipant);
+        };
+
+        // Store values needed for CPI
+        let challenger = duel.challenger;
+        let duel_id = duel.duel_id.to_le_bytes();
+        let bump = duel.bump;
+
+        let seeds = &[b"duel", challenger.as_ref(), duel_id.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.user_token_account.to_account_info(),
+            authority: ctx.accounts.duel.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        emit!(PayoutClaimed {
+            challenge_id: ctx.accounts.duel.key(),
+            user,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws an unaccepted duel, refunding the challenger's stake.
+    pub fn cancel_duel(ctx: Context<CancelDuel>) -> Result<()> {
+        let duel = &mut ctx.accounts.duel;
+
+        require!(
+            duel.status == DuelStatus::Pending,
+            ProvenError::InvalidDuelStatus
+        );
+
+        duel.status = DuelStatus::Cancelled;
+        duel.challenger_claimed = true;
+        duel.opponent_claimed = true;
+
+        // Store values needed for CPI
+        let challenger = duel.challenger;
+        let duel_id = duel.duel_id.to_le_bytes();
+        let bump = duel.bump;
+        let stake_amount = duel.stake_amount;
+
+        let seeds = &[b"duel", challenger.as_ref(), duel_id.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.challenger_token_account.to_account_info(),
+            authority: ctx.accounts.duel.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, stake_amount)?;
+
+        emit!(DuelCancelled {
+            duel: ctx.accounts.duel.key(),
+            challenger,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a finished duel and its escrow, returning all rent to the
+    /// challenger who paid it.
+    pub fn close_duel(ctx: Context<CloseDuel>) -> Result<()> {
+        let duel = &ctx.accounts.duel;
+
+        require!(
+            duel.status == DuelStatus::Settled || duel.status == DuelStatus::Cancelled,
+            ProvenError::ChallengeStillActive
+        );
+        require!(
+            duel.challenger_claimed && duel.opponent_claimed,
+            ProvenError::PendingWinnerPayouts
+        );
+
+        // Store values needed for CPI
+        let challenger = duel.challenger;
+        let duel_id = duel.duel_id.to_le_bytes();
+        let bump = duel.bump;
+
+        let seeds = &[b"duel", challenger.as_ref(), duel_id.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        // Stakes are all paid out, but dust anyone sent would block closing
+        let leftover = ctx.accounts.escrow_vault.amount;
+        if leftover > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.escrow_vault.to_account_info(),
+                to: ctx.accounts.challenger_token_account.to_account_info(),
+                authority: ctx.accounts.duel.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, leftover)?;
+        }
+
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.escrow_vault.to_account_info(),
+            destination: ctx.accounts.challenger.to_account_info(),
+            authority: ctx.accounts.duel.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+
+        emit!(DuelClosed {
+            duel: ctx.accounts.duel.key(),
+            challenger,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_commitment(
+        ctx: Context<CreateCommitment>,
+        commitment_id: u64,
+        stake_amount: u64,
+        total_days: u32,
+        threshold_bps: u16,
+        start_ts: i64,
+        referee: Pubkey,
+        recipient: Pubkey,
+    ) -> Result<()> {
+        Challenge::validate_terms(stake_amount, total_days, threshold_bps, 0)?;
+        require!(
+            start_ts > Clock::get()?.unix_timestamp,
+            ProvenError::InvalidStartTime
+        );
+        require_keys_neq!(
+            recipient,
+            ctx.accounts.user.key(),
+            ProvenError::InvalidRecipient
+        );
+        require_keys_neq!(
+            referee,
+            ctx.accounts.user.key(),
+            ProvenError::InvalidReferee
+        );
+
+        // Transfer the stake into the commitment escrow
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.escrow_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, stake_amount)?;
+
+        let commitment = &mut ctx.accounts.commitment;
+        commitment.version = Commitment::VERSION;
+        commitment.commitment_id = commitment_id;
+        commitment.user = ctx.accounts.user.key();
+        commitment.referee = referee;
+        commitment.recipient = recipient;
+        commitment.token_mint = ctx.accounts.token_mint.key();
+        commitment.stake_amount = stake_amount;
+        commitment.total_days = total_days;
+        commitment.required_days = settlement::required_days(total_days, threshold_bps);
+        commitment.start_ts = start_ts;
+        commitment.end_ts = start_ts + (total_days as i64 * 24 * 60 * 60);
+        commitment.proof_days = 0;
+        commitment.bump = ctx.bumps.commitment;
+
+        emit!(CommitmentCreated {
+            commitment: commitment.key(),
+            user: commitment.user,
+            referee,
+            recipient,
+            stake_amount,
+            total_days,
+            start_ts,
+        });
+
+        Ok(())
+    }
+
+    pub fn record_commitment_proof(ctx: Context<RecordCommitmentProof>) -> Result<()> {
+        let commitment = &mut ctx.accounts.commitment;
+        let clock = Clock::get()?;
+
+        require_keys_eq!(
+            ctx.accounts.referee.key(),
+            commitment.referee,
+            ProvenError::InvalidOracle
+        );
+        require!(
+            clock.unix_timestamp >= commitment.start_ts,
+            ProvenError::ChallengeNotStarted
+        );
+        require!(
+            clock.unix_timestamp <= commitment.end_ts,
+            ProvenError::ChallengeEnded
+        );
+
+        commitment.proof_days += 1;
+
+        emit!(CommitmentProofRecorded {
+            commitment: commitment.key(),
+            user: commitment.user,
+            proof_days: commitment.proof_days,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless: returns the stake once the required days are
+    /// proven, or sends it to the recipient after a failed run. Closes the
+    /// commitment and its escrow, returning rent to the user.
+    pub fn settle_commitment(ctx: Context<SettleCommitment>) -> Result<()> {
+        let commitment = &ctx.accounts.commitment;
+        let clock = Clock::get()?;
+
+        let succeeded = commitment.proof_days >= commitment.required_days;
+        require!(
+            succeeded || clock.unix_timestamp > commitment.end_ts,
+            ProvenError::ChallengeNotEnded
+        );
+        let payee = if succeeded {
+            commitment.user
+        } else {
+            commitment.recipient
+        };
+        require_keys_eq!(
+            ctx.accounts.destination_token_account.owner,
+            payee,
+            ProvenError::Unauthorized
+        );
+
+        // Store values needed for CPI
+        let user = commitment.user;
+        let commitment_id = commitment.commitment_id.to_le_bytes();
+        let bump = commitment.bump;
+        // The whole balance, so dust sent to the escrow can't block closing it
+        let amount = ctx.accounts.escrow_vault.amount;
+
+        let seeds = &[
+            b"commitment",
+            user.as_ref(),
+            commitment_id.as_ref(),
+            &[bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.escrow_vault.to_account_info(),
+            to: ctx.accounts.destination_token_account.to_account_info(),
+            authority: ctx.accounts.commitment.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.escrow_vault.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.commitment.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+
+        emit!(CommitmentSettled {
+            commitment: ctx.accounts.commitment.key(),
+            user,
+            succeeded,
+            payee,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// One-time setup of protocol-wide settings, restricted to the
+    /// program's upgrade authority.
+    pub fn initialize_protocol(ctx: Context<InitializeProtocol>, treasury: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.version = ProtocolConfig::VERSION;
+        config.authority = ctx.accounts.authority.key();
+        config.treasury = treasury;
+        config.bump = ctx.bumps.protocol_config;
+
+        emit!(ProtocolTreasuryUpdated { treasury });
+
+        Ok(())
+    }
+
+    pub fn set_protocol_treasury(
+        ctx: Context<SetProtocolTreasury>,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        require!(
+            config.authority == ctx.accounts.authority.key(),
+            ProvenError::Unauthorized
+        );
+
+        config.treasury = treasury;
+
+        emit!(ProtocolTreasuryUpdated { treasury });
+
+        Ok(())
+    }
+
+    pub fn register_beneficiary(
+        ctx: Context<RegisterBeneficiary>,
+        wallet: Pubkey,
+        name: String,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.protocol_config.authority == ctx.accounts.authority.key(),
+            ProvenError::Unauthorized
+        );
+        require!(
+            !name.is_empty() && name.len() <= Beneficiary::MAX_NAME_LENGTH,
+            ProvenError::InvalidBeneficiaryName
+        );
+
+        let beneficiary = &mut ctx.accounts.beneficiary;
+        beneficiary.version = Beneficiary::VERSION;
+        beneficiary.wallet = wallet;
+        beneficiary.name = name.clone();
+        beneficiary.active = true;
+        beneficiary.bump = ctx.bumps.beneficiary;
+
+        emit!(BeneficiaryUpdated {
+            wallet,
+            name,
+            active: true,
+        });
+
+        Ok(())
+    }
+
+    /// Deactivated beneficiaries can't be chosen by new challenges; ones
+    /// already configured still receive their forfeitures.
+    pub fn set_beneficiary_active(ctx: Context<SetBeneficiaryActive>, active: bool) -> Result<()> {
+        require!(
+            ctx.accounts.protocol_config.authority == ctx.accounts.authority.key(),
+            ProvenError::Unauthorized
+        );
+
+        let beneficiary = &mut ctx.accounts.beneficiary;
+        beneficiary.active = active;
+
+        emit!(BeneficiaryUpdated {
+            wallet: beneficiary.wallet,
+            name: beneficiary.name.clone(),
+            active,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_oracle_registry(
+        ctx: Context<CreateOracleRegistry>,
+        signer: Pubkey,
+        update_delay: i64,
+    ) -> Result<()> {
+        require!(update_delay >= 0, ProvenError::InvalidDelay);
+
+        let registry = &mut ctx.accounts.oracle_registry;
+        registry.authority = ctx.accounts.authority.key();
+        registry.signer = signer;
+        registry.pending_signer = None;
+        registry.pending_eta = 0;
+        registry.update_delay = update_delay;
+        registry.bump = ctx.bumps.oracle_registry;
+
+        emit!(OracleRegistryCreated {
+            registry: registry.key(),
+            authority: registry.authority,
+            signer,
+            update_delay,
+        });
+
+        Ok(())
+    }
+
+    pub fn rotate_oracle(ctx: Context<RotateOracle>, new_signer: Pubkey) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+        let clock = Clock::get()?;
+
+        if registry.update_delay == 0 {
+            let old_signer = registry.signer;
+            registry.signer = new_signer;
+            registry.pending_signer = None;
+            registry.pending_eta = 0;
+
+            emit!(OracleRotated {
+                registry: registry.key(),
+                old_signer,
+                new_signer,
+            });
+        } else {
+            // Time-locked registries queue the new signer until the delay elapses
+            let eta = clock
+                .unix_timestamp
+                .checked_add(registry.update_delay)
+                .ok_or(ProvenError::MathOverflow)?;
+            registry.pending_signer = Some(new_signer);
+            registry.pending_eta = eta;
+
+            emit!(OracleRotationQueued {
+                registry: registry.key(),
+                new_signer,
+                eta,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_oracle_rotation(ctx: Context<ApplyOracleRotation>) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+        let clock = Clock::get()?;
+
+        let new_signer = registry
+            .pending_signer
+            .ok_or(ProvenError::NoPendingRotation)?;
+        require!(
+            clock.unix_timestamp >= registry.pending_eta,
+            ProvenError::RotationTimelocked
+        );
+
+        let old_signer = registry.signer;
+        registry.signer = new_signer;
+        registry.pending_signer = None;
+        registry.pending_eta = 0;
+
+        emit!(OracleRotated {
+            registry: registry.key(),
+            old_signer,
+            new_signer,
+        });
+
+        Ok(())
+    }
+}
+
+/// Reallocates `account` to `new_len`, topping up rent from `payer`.
+fn resize_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_len: usize,
+) -> Result<()> {
+    let required = Rent::get()?.minimum_balance(new_len);
+    let shortfall = required.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        let cpi_accounts = system_program::Transfer {
+            from: payer.to_account_info(),
+            to: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+        system_program::transfer(cpi_ctx, shortfall)?;
+    }
+    account.realloc(new_len, false)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(challenge_id: String)]
+pub struct CreateChallenge<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Challenge::INIT_SPACE,
+        seeds = [b"challenge", Challenge::hash_id(&challenge_id).as_ref(), admin.key().as_ref()],
+        bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct JoinChallenge<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Participant::INIT_SPACE,
+        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub participant: Account<'info, Participant>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    #[account(
+        seeds = [b"allowlist", challenge.key().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Option<Account<'info, Allowlist>>,
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+    #[account(
+        mut,
+        seeds = [b"referral", challenge.key().as_ref(), referral_accrual.referrer.as_ref()],
+        bump = referral_accrual.bump,
+    )]
+    pub referral_accrual: Option<Box<Account<'info, ReferralAccrual>>>,
+    /// Instructions sysvar; required when the challenge takes vouchers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RecordProof<'info> {
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+}
+
+#[derive(Accounts)]
+pub struct SettleChallenge<'info> {
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+}
+
+#[derive(Accounts)]
+pub struct SettleParticipant<'info> {
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+    #[account(
+        mut,
+        seeds = [b"referral", challenge.key().as_ref(), referral_accrual.referrer.as_ref()],
+        bump = referral_accrual.bump,
+    )]
+    pub referral_accrual: Option<Account<'info, ReferralAccrual>>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSettlement<'info> {
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+    #[account(
+        mut,
+        seeds = [b"team_config", challenge.key().as_ref()],
+        bump = team_config.bump,
+    )]
+    pub team_config: Option<Account<'info, TeamConfig>>,
+    /// Forfeiture mode only: escrow, beneficiary token account and token
+    /// program for routing the loser pool.
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub beneficiary_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct PreviewSettlement<'info> {
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPayout<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelChallenge<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = admin,
+    )]
+    pub admin_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasuryFees<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"protocol"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = protocol_config.treasury,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCharityFees<'info> {
+    pub charity: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = charity,
+    )]
+    pub charity_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseParticipant<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        close = destination,
+        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+    /// CHECK: validated against participant.user in handler
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseChallenge<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct AmendChallenge<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOracle<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    /// The currently linked registry, whose delay may apply.
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+}
+
+#[derive(Accounts)]
+pub struct LinkOracleRegistry<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        seeds = [b"oracle_registry", oracle_registry.authority.as_ref()],
+        bump = oracle_registry.bump,
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+    /// The currently linked registry, whose delay may apply.
+    pub current_registry: Option<Account<'info, OracleRegistry>>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeOracleUpdate<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + PendingOracle::INIT_SPACE,
+        seeds = [b"pending_oracle", challenge.key().as_ref()],
+        bump,
+    )]
+    pub pending_oracle: Account<'info, PendingOracle>,
+    /// The currently linked registry, whose delay may apply.
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+    #[account(
+        seeds = [b"oracle_registry", new_registry.authority.as_ref()],
+        bump = new_registry.bump,
+    )]
+    pub new_registry: Option<Account<'info, OracleRegistry>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyOracleUpdate<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"pending_oracle", challenge.key().as_ref()],
+        bump = pending_oracle.bump,
+    )]
+    pub pending_oracle: Account<'info, PendingOracle>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOracleUpdate<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"pending_oracle", challenge.key().as_ref()],
+        bump = pending_oracle.bump,
+    )]
+    pub pending_oracle: Account<'info, PendingOracle>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateChallenge<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: legacy layout and discriminator are validated in the handler
+    #[account(mut, owner = crate::ID)]
+    pub challenge: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateParticipant<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: legacy layout and discriminator are validated in the handler
+    #[account(mut, owner = crate::ID)]
+    pub participant: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(series_id: String)]
+pub struct CreateSeries<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ChallengeSeries::INIT_SPACE,
+        seeds = [b"series", Challenge::hash_id(&series_id).as_ref(), admin.key().as_ref()],
+        bump,
+    )]
+    pub series: Account<'info, ChallengeSeries>,
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SpawnNext<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"series", Challenge::hash_id(&series.series_id).as_ref(), series.admin.as_ref()],
+        bump = series.bump,
+    )]
+    pub series: Account<'info, ChallengeSeries>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Challenge::INIT_SPACE,
+        seeds = [
+            b"challenge",
+            Challenge::hash_id(&series.edition_id(series.next_edition)).as_ref(),
+            series.admin.as_ref(),
+        ],
+        bump,
+    )]
+    pub challenge: Box<Account<'info, Challenge>>,
+    #[account(address = series.token_mint)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoRoll<'info> {
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+}
+
+#[derive(Accounts)]
+pub struct RollOver<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"series", Challenge::hash_id(&series.series_id).as_ref(), series.admin.as_ref()],
+        bump = series.bump,
+    )]
+    pub series: Account<'info, ChallengeSeries>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Box<Account<'info, Challenge>>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Account<'info, Participant>,
+    /// CHECK: the rolling participant's wallet; constrained to participant.user
+    #[account(address = participant.user)]
+    pub user: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", next_challenge.id_seed(), next_challenge.admin.as_ref()],
+        bump = next_challenge.bump,
+    )]
+    pub next_challenge: Box<Account<'info, Challenge>>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Participant::INIT_SPACE,
+        seeds = [b"participant", next_challenge.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub next_participant: Account<'info, Participant>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = next_challenge,
+    )]
+    pub next_escrow_vault: Box<Account<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAllowlist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Allowlist::INIT_SPACE,
+        seeds = [b"allowlist", challenge.key().as_ref()],
+        bump,
+    )]
+    pub allowlist: Account<'info, Allowlist>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAllowlistRoot<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        seeds = [b"allowlist", challenge.key().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, Allowlist>,
+}
+
+#[derive(Accounts)]
+pub struct SetReferralBps<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+#[instruction(referrer: Pubkey)]
+pub struct OpenReferralAccrual<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + ReferralAccrual::INIT_SPACE,
+        seeds = [b"referral", challenge.key().as_ref(), referrer.as_ref()],
+        bump,
+    )]
+    pub referral_accrual: Account<'info, ReferralAccrual>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferral<'info> {
+    pub referrer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        seeds = [b"referral", challenge.key().as_ref(), referrer.key().as_ref()],
+        bump = referral_accrual.bump,
+    )]
+    pub referral_accrual: Account<'info, ReferralAccrual>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = referrer,
+    )]
+    pub referrer_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetVoucherRequired<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+}
+
+#[derive(Accounts)]
+pub struct EnableForfeiture<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        seeds = [b"beneficiary", beneficiary.wallet.as_ref()],
+        bump = beneficiary.bump,
+    )]
+    pub beneficiary: Account<'info, Beneficiary>,
+}
+
+#[derive(Accounts)]
+pub struct EnableTeams<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + TeamConfig::INIT_SPACE,
+        seeds = [b"team_config", challenge.key().as_ref()],
+        bump,
+    )]
+    pub team_config: Account<'info, TeamConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateTeam<'info> {
+    #[account(mut)]
+    pub captain: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        seeds = [b"team_config", challenge.key().as_ref()],
+        bump = team_config.bump,
+    )]
+    pub team_config: Account<'info, TeamConfig>,
+    #[account(
+        init,
+        payer = captain,
+        space = 8 + Team::INIT_SPACE,
+        seeds = [
+            b"team",
+            challenge.key().as_ref(),
+            team_config.team_count.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub team: Account<'info, Team>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTeam<'info> {
+    #[account(mut)]
+    pub captain: Signer<'info>,
+    #[account(
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    #[account(
+        mut,
+        close = captain,
+        has_one = captain,
+        seeds = [b"team", challenge.key().as_ref(), team.index.to_le_bytes().as_ref()],
+        bump = team.bump,
+    )]
+    pub team: Account<'info, Team>,
+}
+
+#[derive(Accounts)]
+pub struct JoinTeam<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Box<Account<'info, Challenge>>,
+    #[account(
+        mut,
+        seeds = [b"team_config", challenge.key().as_ref()],
+        bump = team_config.bump,
+    )]
+    pub team_config: Box<Account<'info, TeamConfig>>,
+    #[account(
+        mut,
+        seeds = [b"team", challenge.key().as_ref(), team.index.to_le_bytes().as_ref()],
+        bump = team.bump,
+    )]
+    pub team: Box<Account<'info, Team>>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Participant::INIT_SPACE,
+        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub participant: Box<Account<'info, Participant>>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"allowlist", challenge.key().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Option<Box<Account<'info, Allowlist>>>,
+    pub oracle_registry: Option<Box<Account<'info, OracleRegistry>>>,
+    /// Instructions sysvar; required when the challenge takes vouchers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleTeam<'info> {
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Account<'info, Challenge>,
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+    #[account(
+        mut,
+        seeds = [b"team_config", challenge.key().as_ref()],
+        bump = team_config.bump,
+    )]
+    pub team_config: Account<'info, TeamConfig>,
+    #[account(
+        mut,
+        seeds = [b"team", challenge.key().as_ref(), team.index.to_le_bytes().as_ref()],
+        bump = team.bump,
+    )]
+    pub team: Account<'info, Team>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTeamPayout<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"challenge", challenge.id_seed(), challenge.admin.as_ref()],
+        bump = challenge.bump,
+    )]
+    pub challenge: Box<Account<'info, Challenge>>,
+    #[account(
+        seeds = [b"team_config", challenge.key().as_ref()],
+        bump = team_config.bump,
+    )]
+    pub team_config: Box<Account<'info, TeamConfig>>,
+    #[account(
+        mut,
+        seeds = [b"team", challenge.key().as_ref(), team.index.to_le_bytes().as_ref()],
+        bump = team.bump,
+    )]
+    pub team: Box<Account<'info, Team>>,
+    #[account(
+        mut,
+        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
+        bump = participant.bump,
+    )]
+    pub participant: Box<Account<'info, Participant>>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = challenge.token_mint,
+        associated_token::authority = challenge,
+    )]
+    pub escrow_vault: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(duel_id: u64)]
+pub struct CreateDuel<'info> {
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+    #[account(
+        init,
+        payer = challenger,
+        space = 8 + Duel::INIT_SPACE,
+        seeds = [b"duel", challenger.key().as_ref(), duel_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub duel: Account<'info, Duel>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = challenger,
+    )]
+    pub challenger_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = challenger,
+        associated_token::mint = token_mint,
+        associated_token::authority = duel,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptDuel<'info> {
+    pub opponent: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"duel", duel.challenger.as_ref(), duel.duel_id.to_le_bytes().as_ref()],
+        bump = duel.bump,
+    )]
+    pub duel: Account<'info, Duel>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = opponent,
+    )]
+    pub opponent_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = duel,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RecordDuelProof<'info> {
+    pub oracle: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"duel", duel.challenger.as_ref(), duel.duel_id.to_le_bytes().as_ref()],
+        bump = duel.bump,
+    )]
+    pub duel: Account<'info, Duel>,
+}
+
+#[derive(Accounts)]
+pub struct SettleDuel<'info> {
+    #[account(
+        mut,
+        seeds = [b"duel", duel.challenger.as_ref(), duel.duel_id.to_le_bytes().as_ref()],
+        bump = duel.bump,
+    )]
+    pub duel: Account<'info, Duel>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDuel<'info> {
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"duel", duel.challenger.as_ref(), duel.duel_id.to_le_bytes().as_ref()],
+        bump = duel.bump,
+    )]
+    pub duel: Account<'info, Duel>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
         mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = duel,
     )]
-    pub challenge: Account<'info, Challenge>,
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDuel<'info> {
+    pub challenger: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
-        bump = participant.bump,
+        seeds = [b"duel", challenger.key().as_ref(), duel.duel_id.to_le_bytes().as_ref()],
+        bump = duel.bump,
     )]
-    pub participant: Account<'info, Participant>,
+    pub duel: Account<'info, Duel>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = challenger,
+    )]
+    pub challenger_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = duel,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDuel<'info> {
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+    #[account(
+        mut,
+        close = challenger,
+        seeds = [b"duel", challenger.key().as_ref(), duel.duel_id.to_le_bytes().as_ref()],
+        bump = duel.bump,
+    )]
+    pub duel: Account<'info, Duel>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = challenger,
+    )]
+    pub challenger_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = duel.token_mint,
+        associated_token::authority = duel,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(commitment_id: u64)]
+pub struct CreateCommitment<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Commitment::INIT_SPACE,
+        seeds = [b"commitment", user.key().as_ref(), commitment_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub commitment: Account<'info, Commitment>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = token_mint,
+        associated_token::authority = commitment,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct RecordCommitmentProof<'info> {
+    pub referee: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"commitment", commitment.user.as_ref(), commitment.commitment_id.to_le_bytes().as_ref()],
+        bump = commitment.bump,
+    )]
+    pub commitment: Account<'info, Commitment>,
+}
+
+#[derive(Accounts)]
+pub struct SettleCommitment<'info> {
+    /// CHECK: rent destination; must be the committed user
+    #[account(mut, address = commitment.user)]
+    pub user: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"commitment", commitment.user.as_ref(), commitment.commitment_id.to_le_bytes().as_ref()],
+        bump = commitment.bump,
+    )]
+    pub commitment: Account<'info, Commitment>,
+    #[account(mut, token::mint = commitment.token_mint)]
+    pub destination_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = commitment.token_mint,
+        associated_token::authority = commitment,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeProtocol<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProtocolConfig::INIT_SPACE,
+        seeds = [b"protocol"],
+        bump,
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::ProvenStake>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ ProvenError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetProtocolTreasury<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"protocol"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct RegisterBeneficiary<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"protocol"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Beneficiary::INIT_SPACE,
+        seeds = [b"beneficiary", wallet.as_ref()],
+        bump,
+    )]
+    pub beneficiary: Account<'info, Beneficiary>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetBeneficiaryActive<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"protocol"], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        seeds = [b"beneficiary", beneficiary.wallet.as_ref()],
+        bump = beneficiary.bump,
+    )]
+    pub beneficiary: Account<'info, Beneficiary>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOracleRegistry<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + OracleRegistry::INIT_SPACE,
+        seeds = [b"oracle_registry", authority.key().as_ref()],
+        bump,
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RotateOracle<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"oracle_registry", authority.key().as_ref()],
+        bump = oracle_registry.bump,
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyOracleRotation<'info> {
+    #[account(
+        mut,
+        seeds = [b"oracle_registry", oracle_registry.authority.as_ref()],
+        bump = oracle_registry.bump,
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Challenge {
+    pub version: u8,
+    #[max_len(64)]
+    pub challenge_id: String,
+    /// `hash(challenge_id)`; the PDA seed for challenges with `hashed_seeds`.
+    pub id_hash: [u8; 32],
+    /// False for legacy challenges seeded by the raw `challenge_id` bytes.
+    pub hashed_seeds: bool,
+    /// Creator key used in the PDA seeds; never changes.
+    pub admin: Pubkey,
+    pub token_mint: Pubkey,
+    pub escrow_vault: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub threshold_bps: u16,
+    pub platform_fee_bps: u16,
+    pub status: ChallengeStatus,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub participant_count: u32,
+    pub active_participants: u32,
+    pub winner_count: u32,
+    pub loser_count: u32,
+    pub bonus_per_winner: u64,
+    pub fee_amount: u64,
+    pub remainder: u64,
+    pub payouts_claimed_count: u32,
+    pub remainder_claimed: u64,
+    pub oracle_signer: Pubkey,
+    pub oracle_registry: Option<Pubkey>,
+    /// Current admin used for authorization; transferable via
+    /// `propose_admin` / `accept_admin`.
+    pub authority: Pubkey,
+    pub pending_authority: Option<Pubkey>,
+    pub bump: u8,
+    /// Series this challenge was spawned from, if any.
+    pub series: Option<Pubkey>,
+    pub edition: u32,
+    /// Participants join and settle through `Team`s; see `TeamConfig`.
+    pub team_mode: bool,
+    /// Joins require a proof against the challenge's `Allowlist` root.
+    pub allowlisted: bool,
+    /// Joins require a `JoinVoucher` in the preceding ed25519 instruction.
+    pub voucher_required: bool,
+    /// Share of a referred loser's fee contribution owed to the referrer.
+    pub referral_bps: u16,
+    /// Referral rewards accrued but not yet claimed; excluded from `fee_amount`.
+    pub referral_owed: u64,
+    /// Shares of the platform fee for the protocol treasury and `charity`;
+    /// the host keeps the rest in `fee_amount`.
+    pub treasury_fee_bps: u16,
+    pub charity_fee_bps: u16,
+    pub charity: Option<Pubkey>,
+    pub treasury_fee_amount: u64,
+    pub charity_fee_amount: u64,
+    /// Forfeiture mode: the loser pool goes to this registered
+    /// `Beneficiary` wallet instead of the winners.
+    pub forfeit_beneficiary: Option<Pubkey>,
+    pub reserved: [u8; 31],
+}
+
+impl Challenge {
+    pub const VERSION: u8 = 2;
+    /// Serialized size of a version 1 challenge, without discriminator.
+    pub const V1_LEN: usize = 478;
+    /// Must match the `max_len` on `challenge_id`.
+    pub const MAX_ID_LENGTH: usize = 64;
+    /// Most days one amendment may add once the challenge has started.
+    pub const MAX_EXTENSION_DAYS: u32 = 7;
+
+    /// Converts an account in the pre-versioning or version 1 layout,
+    /// discriminator included, into the current layout. Legacy challenges
+    /// keep their string-seeded PDA and their creator as authority.
+    pub fn from_legacy_data(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() == 8 + LegacyChallenge::LEN || data.len() == 8 + Self::V1_LEN,
+            ProvenError::AlreadyMigrated
+        );
+        require!(
+            data[..8] == Self::DISCRIMINATOR,
+            ProvenError::InvalidAccountLayout
+        );
+        if data.len() == 8 + Self::V1_LEN {
+            // Version 2 only appends fields past version 1's zeroed reserved
+            // bytes, so zero-padding a version 1 account yields the defaults
+            let mut padded = data[8..].to_vec();
+            padded.resize(Self::INIT_SPACE, 0);
+            let mut challenge = Self::deserialize(&mut padded.as_slice())
+                .map_err(|_| error!(ProvenError::InvalidAccountLayout))?;
+            challenge.version = Self::VERSION;
+            return Ok(challenge);
+        }
+        let legacy = LegacyChallenge::deserialize(&mut &data[8..])
+            .map_err(|_| error!(ProvenError::InvalidAccountLayout))?;
+
+        Ok(Self {
+            version: Self::VERSION,
+            id_hash: Self::hash_id(&legacy.challenge_id),
+            hashed_seeds: false,
+            challenge_id: legacy.challenge_id,
+            admin: legacy.admin,
+            token_mint: legacy.token_mint,
+            escrow_vault: legacy.escrow_vault,
+            stake_amount: legacy.stake_amount,
+            total_days: legacy.total_days,
+            threshold_bps: legacy.threshold_bps,
+            platform_fee_bps: legacy.platform_fee_bps,
+            status: legacy.status,
+            start_ts: legacy.start_ts,
+            end_ts: legacy.end_ts,
+            participant_count: legacy.participant_count,
+            active_participants: legacy.active_participants,
+            winner_count: legacy.winner_count,
+            loser_count: legacy.loser_count,
+            bonus_per_winner: legacy.bonus_per_winner,
+            fee_amount: legacy.fee_amount,
+            remainder: legacy.remainder,
+            payouts_claimed_count: legacy.payouts_claimed_count,
+            remainder_claimed: legacy.remainder_claimed,
+            oracle_signer: legacy.oracle_signer,
+            oracle_registry: None,
+            authority: legacy.admin,
+            pending_authority: None,
+            bump: legacy.bump,
+            series: None,
+            edition: 0,
+            team_mode: false,
+            allowlisted: false,
+            voucher_required: false,
+            referral_bps: 0,
+            referral_owed: 0,
+            treasury_fee_bps: 0,
+            charity_fee_bps: 0,
+            charity: None,
+            treasury_fee_amount: 0,
+            charity_fee_amount: 0,
+            forfeit_beneficiary: None,
+            reserved: [0; 31],
+        })
+    }
+
+    pub fn validate_terms(
+        stake_amount: u64,
+        total_days: u32,
+        threshold_bps: u16,
+        platform_fee_bps: u16,
+    ) -> Result<()> {
+        require!(stake_amount > 0, ProvenError::InvalidAmount);
+        require!(total_days > 0, ProvenError::InvalidDuration);
+        require!(threshold_bps <= 10000, ProvenError::InvalidThreshold);
+        require!(platform_fee_bps <= 1000, ProvenError::InvalidFee);
+        Ok(())
+    }
+
+    pub fn validate_fee_split(
+        treasury_fee_bps: u16,
+        charity_fee_bps: u16,
+        charity: Option<Pubkey>,
+    ) -> Result<()> {
+        require!(
+            treasury_fee_bps as u32 + charity_fee_bps as u32 <= 10000,
+            ProvenError::InvalidFeeSplit
+        );
+        require!(
+            charity_fee_bps == 0 || charity.is_some(),
+            ProvenError::InvalidFeeSplit
+        );
+        Ok(())
+    }
+
+    pub fn initialize(
+        &mut self,
+        challenge_id: String,
+        admin: Pubkey,
+        token_mint: Pubkey,
+        escrow_vault: Pubkey,
+        stake_amount: u64,
+        total_days: u32,
+        threshold_bps: u16,
+        platform_fee_bps: u16,
+        start_ts: i64,
+        oracle_signer: Pubkey,
+        bump: u8,
+    ) {
+        self.version = Self::VERSION;
+        self.id_hash = Self::hash_id(&challenge_id);
+        self.hashed_seeds = true;
+        self.challenge_id = challenge_id;
+        self.admin = admin;
+        self.authority = admin;
+        self.pending_authority = None;
+        self.token_mint = token_mint;
+        self.escrow_vault = escrow_vault;
+        self.stake_amount = stake_amount;
+        self.total_days = total_days;
+        self.threshold_bps = threshold_bps;
+        self.platform_fee_bps = platform_fee_bps;
+        self.status = ChallengeStatus::Created;
+        self.start_ts = start_ts;
+        self.end_ts = start_ts + (total_days as i64 * 24 * 60 * 60);
+        self.participant_count = 0;
+        self.active_participants = 0;
+        self.winner_count = 0;
+        self.loser_count = 0;
+        self.bonus_per_winner = 0;
+        self.fee_amount = 0;
+        self.remainder = 0;
+        self.payouts_claimed_count = 0;
+        self.remainder_claimed = 0;
+        self.oracle_signer = oracle_signer;
+        self.oracle_registry = None;
+        self.series = None;
+        self.edition = 0;
+        self.team_mode = false;
+        self.allowlisted = false;
+        self.voucher_required = false;
+        self.referral_bps = 0;
+        self.referral_owed = 0;
+        self.treasury_fee_bps = 0;
+        self.charity_fee_bps = 0;
+        self.charity = None;
+        self.treasury_fee_amount = 0;
+        self.charity_fee_amount = 0;
+        self.forfeit_beneficiary = None;
+        self.bump = bump;
+    }
+
+    /// Passes when the challenge has no allowlist or `proof` places `user`
+    /// under the allowlist root.
+    pub fn check_allowlist(
+        &self,
+        allowlist: Option<&Account<Allowlist>>,
+        user: &Pubkey,
+        proof: &[[u8; 32]],
+    ) -> Result<()> {
+        if !self.allowlisted {
+            return Ok(());
+        }
+        let allowlist = allowlist.ok_or(ProvenError::AllowlistMissing)?;
+        require!(allowlist.contains(user, proof), ProvenError::NotAllowlisted);
+        Ok(())
+    }
+
+    /// Passes when the challenge takes no vouchers, or the instruction
+    /// before this one is an ed25519 verification of an unexpired voucher
+    /// for `user` signed by the challenge authority or oracle.
+    ///
+    /// Each voucher is single-use because it names one user, who can only
+    /// create their `Participant` account once.
+    pub fn check_voucher(
+        &self,
+        challenge: Pubkey,
+        instructions: Option<&UncheckedAccount>,
+        registry: Option<&Account<OracleRegistry>>,
+        user: &Pubkey,
+        now: i64,
+    ) -> Result<()> {
+        if !self.voucher_required {
+            return Ok(());
+        }
+        let instructions = instructions.ok_or(ProvenError::VoucherMissing)?;
+        let current = load_current_index_checked(instructions)?;
+        require!(current > 0, ProvenError::VoucherMissing);
+        let ix = load_instruction_at_checked(current as usize - 1, instructions)?;
+        let (signer, voucher) = JoinVoucher::from_ed25519_instruction(&ix)?;
+
+        require!(
+            signer == self.authority || self.verify_oracle(signer, registry).is_ok(),
+            ProvenError::InvalidVoucher
+        );
+        require_keys_eq!(voucher.challenge, challenge, ProvenError::InvalidVoucher);
+        require_keys_eq!(voucher.user, *user, ProvenError::InvalidVoucher);
+        require!(now <= voucher.expiry, ProvenError::VoucherExpired);
+        Ok(())
+    }
+
+    /// Books `fee` across the treasury, charity and host balances, with
+    /// rounding dust going to the host.
+    pub fn split_fee(&mut self, fee: u64) {
+        let split = settlement::split_fee(fee, self.treasury_fee_bps, self.charity_fee_bps);
+        self.treasury_fee_amount = split.treasury;
+        self.charity_fee_amount = split.charity;
+        self.fee_amount = split.host;
+    }
+
+    pub fn fees_collected(&self) -> bool {
+        self.fee_amount == 0 && self.treasury_fee_amount == 0 && self.charity_fee_amount == 0
+    }
+
+    /// Referrer's cut of the platform fee taken from a losing `stake`.
+    pub fn referral_reward(&self, stake: u64) -> u64 {
+        settlement::referral_reward(stake, self.platform_fee_bps, self.referral_bps)
+    }
+
+    /// Validates a winner's claim and books it against the challenge,
+    /// returning stake plus bonus (and one unit of any unclaimed remainder).
+    pub fn take_winner_payout(&mut self, participant: &mut Participant) -> Result<u64> {
+        require!(
+            self.status == ChallengeStatus::Settled,
+            ProvenError::ChallengeNotSettled
+        );
+        require!(!self.team_mode, ProvenError::TeamChallenge);
+        require!(participant.is_settled, ProvenError::NotSettled);
+        require!(participant.is_winner, ProvenError::NotWinner);
+        require!(
+            !participant.payout_claimed,
+            ProvenError::PayoutAlreadyClaimed
+        );
+        require!(
+            self.payouts_claimed_count < self.winner_count,
+            ProvenError::AllPayoutsClaimed
+        );
+
+        // Calculate total payout (original stake + bonus)
+        let (bonus, remainder_increment) = settlement::next_share(
+            self.bonus_per_winner,
+            self.remainder,
+            self.remainder_claimed,
+        )
+        .ok_or(ProvenError::MathOverflow)?;
+        let payout_amount = self
+            .stake_amount
+            .checked_add(bonus)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        participant.payout_claimed = true;
+        self.payouts_claimed_count = self
+            .payouts_claimed_count
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+        self.remainder_claimed = self
+            .remainder_claimed
+            .checked_add(remainder_increment)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        Ok(payout_amount)
+    }
+
+    /// Team counterpart of `take_winner_payout`. The first claim from a
+    /// winning team draws the team's share of the loser pool (plus one unit
+    /// of any unclaimed remainder); members then split that share, with the
+    /// team's own remainder going one unit per claim.
+    pub fn take_team_payout(
+        &mut self,
+        team_config: &TeamConfig,
+        team: &mut Team,
+        participant: &mut Participant,
+    ) -> Result<u64> {
+        require!(
+            self.status == ChallengeStatus::Settled,
+            ProvenError::ChallengeNotSettled
+        );
+        require!(participant.is_settled, ProvenError::NotSettled);
+        require!(participant.is_winner, ProvenError::NotWinner);
+        require!(
+            !participant.payout_claimed,
+            ProvenError::PayoutAlreadyClaimed
+        );
+        require!(
+            self.payouts_claimed_count < self.winner_count,
+            ProvenError::AllPayoutsClaimed
+        );
+
+        if !team.share_taken {
+            let (share, taken) = settlement::next_share(
+                team_config.bonus_per_team,
+                self.remainder,
+                self.remainder_claimed,
+            )
+            .ok_or(ProvenError::MathOverflow)?;
+            self.remainder_claimed += taken;
+            team.bonus_per_member = share / team.member_count as u64;
+            team.remainder = share % team.member_count as u64;
+            team.share_taken = true;
+        }
+
+        let (bonus, taken) = settlement::next_share(
+            team.bonus_per_member,
+            team.remainder,
+            team.remainder_claimed,
+        )
+        .ok_or(ProvenError::MathOverflow)?;
+        team.remainder_claimed += taken;
+        let payout_amount = self
+            .stake_amount
+            .checked_add(bonus)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        participant.payout_claimed = true;
+        self.payouts_claimed_count = self
+            .payouts_claimed_count
+            .checked_add(1)
+            .ok_or(ProvenError::MathOverflow)?;
+
+        Ok(payout_amount)
+    }
+
+    pub fn hash_id(challenge_id: &str) -> [u8; 32] {
+        hash(challenge_id.as_bytes()).to_bytes()
+    }
+
+    /// Middle PDA seed: the id hash, or the raw id for legacy challenges.
+    pub fn id_seed(&self) -> &[u8] {
+        if self.hashed_seeds {
+            &self.id_hash
+        } else {
+            self.challenge_id.as_bytes()
+        }
+    }
+
+    /// How long an oracle change must wait: nothing before the start, then
+    /// the `update_delay` of the linked registry, which must be passed.
+    pub fn oracle_update_delay(
+        &self,
+        registry: Option<&Account<OracleRegistry>>,
+        now: i64,
+    ) -> Result<i64> {
+        if self.status == ChallengeStatus::Created && now < self.start_ts {
+            return Ok(0);
+        }
+        match self.oracle_registry {
+            Some(registry_key) => {
+                let registry = registry.ok_or(ProvenError::OracleRegistryMissing)?;
+                require_keys_eq!(
+                    registry.key(),
+                    registry_key,
+                    ProvenError::OracleRegistryMismatch
+                );
+                Ok(registry.update_delay)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Checks `oracle` against the registry signer when the challenge is
+    /// linked to a registry, otherwise against `oracle_signer`.
+    pub fn verify_oracle(
+        &self,
+        oracle: Pubkey,
+        registry: Option<&Account<OracleRegistry>>,
+    ) -> Result<()> {
+        let expected = match self.oracle_registry {
+            Some(registry_key) => {
+                let registry = registry.ok_or(ProvenError::OracleRegistryMissing)?;
+                require_keys_eq!(
+                    registry.key(),
+                    registry_key,
+                    ProvenError::OracleRegistryMismatch
+                );
+                registry.signer
+            }
+            None => self.oracle_signer,
+        };
+        require_keys_eq!(oracle, expected, ProvenError::InvalidOracle);
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Participant {
+    pub version: u8,
+    pub user: Pubkey,
+    pub challenge: Pubkey,
+    pub joined: bool,
+    pub stake_deposited: u64,
+    pub proof_days: u32,
+    pub is_winner: bool,
+    pub is_settled: bool,
+    pub payout_claimed: bool,
+    pub refund_claimed: bool,
+    pub bump: u8,
+    /// Roll the payout into the next edition of the challenge's series.
+    pub auto_roll: bool,
+    pub team: Option<Pubkey>,
+    /// Wallet credited with referring this participant.
+    pub referrer: Option<Pubkey>,
+    pub reserved: [u8; 30],
+}
+
+impl Participant {
+    pub const VERSION: u8 = 2;
+
+    pub fn initialize(&mut self, user: Pubkey, challenge: Pubkey, stake_deposited: u64, bump: u8) {
+        self.version = Self::VERSION;
+        self.user = user;
+        self.challenge = challenge;
+        self.joined = true;
+        self.stake_deposited = stake_deposited;
+        self.proof_days = 0;
+        self.is_winner = false;
+        self.is_settled = false;
+        self.payout_claimed = false;
+        self.refund_claimed = false;
+        self.auto_roll = false;
+        self.team = None;
+        self.referrer = None;
+        self.bump = bump;
+    }
+
+    /// Converts an account in the pre-versioning or version 1 layout,
+    /// discriminator included, into the current layout.
+    pub fn from_legacy_data(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() == 8 + LegacyParticipant::LEN || data.len() == 8 + ParticipantV1::LEN,
+            ProvenError::AlreadyMigrated
+        );
+        require!(
+            data[..8] == Self::DISCRIMINATOR,
+            ProvenError::InvalidAccountLayout
+        );
+        if data.len() == 8 + ParticipantV1::LEN {
+            let v1 = ParticipantV1::deserialize(&mut &data[8..])
+                .map_err(|_| error!(ProvenError::InvalidAccountLayout))?;
+            return Ok(Self {
+                version: Self::VERSION,
+                user: v1.user,
+                challenge: v1.challenge,
+                joined: v1.joined,
+                stake_deposited: v1.stake_deposited,
+                proof_days: v1.proof_days,
+                is_winner: v1.is_winner,
+                is_settled: v1.is_settled,
+                payout_claimed: v1.payout_claimed,
+                refund_claimed: v1.refund_claimed,
+                bump: v1.bump,
+                auto_roll: v1.auto_roll,
+                team: v1.team,
+                referrer: None,
+                reserved: v1.reserved,
+            });
+        }
+        let legacy = LegacyParticipant::deserialize(&mut &data[8..])
+            .map_err(|_| error!(ProvenError::InvalidAccountLayout))?;
+
+        Ok(Self {
+            version: Self::VERSION,
+            user: legacy.user,
+            challenge: legacy.challenge,
+            joined: legacy.joined,
+            stake_deposited: legacy.stake_deposited,
+            proof_days: legacy.proof_days,
+            is_winner: legacy.is_winner,
+            is_settled: legacy.is_settled,
+            payout_claimed: legacy.payout_claimed,
+            refund_claimed: legacy.refund_claimed,
+            bump: legacy.bump,
+            auto_roll: false,
+            team: None,
+            referrer: None,
+            reserved: [0; 30],
+        })
+    }
+}
+
+/// `Challenge` as deployed before account versioning: 32-byte ids seeded
+/// directly into the PDA, a single fixed admin and no oracle registry.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyChallenge {
+    pub challenge_id: String,
+    pub admin: Pubkey,
+    pub token_mint: Pubkey,
+    pub escrow_vault: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub threshold_bps: u16,
+    pub platform_fee_bps: u16,
+    pub status: ChallengeStatus,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub participant_count: u32,
+    pub active_participants: u32,
+    pub winner_count: u32,
+    pub loser_count: u32,
+    pub bonus_per_winner: u64,
+    pub fee_amount: u64,
+    pub remainder: u64,
+    pub payouts_claimed_count: u32,
+    pub remainder_claimed: u64,
+    pub oracle_signer: Pubkey,
+    pub bump: u8,
+}
+
+impl LegacyChallenge {
+    pub const MAX_ID_LENGTH: usize = 32;
+    pub const LEN: usize = 250;
+}
+
+/// `Participant` as deployed before account versioning.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyParticipant {
+    pub user: Pubkey,
+    pub challenge: Pubkey,
+    pub joined: bool,
+    pub stake_deposited: u64,
+    pub proof_days: u32,
+    pub is_winner: bool,
+    pub is_settled: bool,
+    pub payout_claimed: bool,
+    pub refund_claimed: bool,
+    pub bump: u8,
+}
+
+impl LegacyParticipant {
+    pub const LEN: usize = 82;
+}
+
+/// `Participant` at version 1, before referrals.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ParticipantV1 {
+    pub version: u8,
+    pub user: Pubkey,
+    pub challenge: Pubkey,
+    pub joined: bool,
+    pub stake_deposited: u64,
+    pub proof_days: u32,
+    pub is_winner: bool,
+    pub is_settled: bool,
+    pub payout_claimed: bool,
+    pub refund_claimed: bool,
+    pub bump: u8,
+    pub auto_roll: bool,
+    pub team: Option<Pubkey>,
+    pub reserved: [u8; 30],
+}
+
+impl ParticipantV1 {
+    pub const LEN: usize = 147;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct OracleRegistry {
+    pub authority: Pubkey,
+    pub signer: Pubkey,
+    pub pending_signer: Option<Pubkey>,
+    pub pending_eta: i64,
+    pub update_delay: i64,
+    pub bump: u8,
+}
+
+/// An oracle change queued by `propose_oracle_update`, applicable from `eta`.
+#[account]
+#[derive(InitSpace)]
+pub struct PendingOracle {
+    pub challenge: Pubkey,
+    pub oracle_signer: Pubkey,
+    pub oracle_registry: Option<Pubkey>,
+    pub eta: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ChallengeSeries {
+    pub version: u8,
+    #[max_len(32)]
+    pub series_id: String,
+    pub admin: Pubkey,
+    pub token_mint: Pubkey,
+    pub oracle_signer: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub threshold_bps: u16,
+    pub platform_fee_bps: u16,
+    pub interval_secs: i64,
+    pub next_edition: u32,
+    pub next_start_ts: i64,
+    pub bump: u8,
+    pub reserved: [u8; 64],
+}
+
+impl ChallengeSeries {
+    pub const VERSION: u8 = 1;
+    /// Must match the `max_len` on `series_id`.
+    pub const MAX_ID_LENGTH: usize = 32;
+
+    /// Challenge id of an edition, e.g. `walking-30d-7`.
+    pub fn edition_id(&self, edition: u32) -> String {
+        format!("{}-{}", self.series_id, edition)
+    }
+}
+
+/// A single user's stake on their own goal. Success returns the stake;
+/// failure sends it to `recipient`.
+#[account]
+#[derive(InitSpace)]
+pub struct Commitment {
+    pub version: u8,
+    pub commitment_id: u64,
+    pub user: Pubkey,
+    /// Signs proofs; a friend, coach or the oracle.
+    pub referee: Pubkey,
+    pub recipient: Pubkey,
+    pub token_mint: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub required_days: u32,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub proof_days: u32,
+    pub bump: u8,
+    pub reserved: [u8; 32],
+}
+
+impl Commitment {
+    pub const VERSION: u8 = 1;
+}
+
+/// Protocol-wide settings, created once by the program's upgrade authority.
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolConfig {
+    pub version: u8,
+    pub authority: Pubkey,
+    /// Wallet whose token accounts receive the treasury share of fees.
+    pub treasury: Pubkey,
+    pub bump: u8,
+    pub reserved: [u8; 64],
+}
+
+impl ProtocolConfig {
+    pub const VERSION: u8 = 1;
+}
+
+/// A cause approved by the protocol authority to receive forfeited stakes.
+#[account]
+#[derive(InitSpace)]
+pub struct Beneficiary {
+    pub version: u8,
+    pub wallet: Pubkey,
+    #[max_len(32)]
+    pub name: String,
+    pub active: bool,
+    pub bump: u8,
+    pub reserved: [u8; 32],
+}
+
+impl Beneficiary {
+    pub const VERSION: u8 = 1;
+    /// Must match the `max_len` on `name`.
+    pub const MAX_NAME_LENGTH: usize = 32;
+}
+
+/// Referral rewards owed to one referrer on one challenge.
+#[account]
+#[derive(InitSpace)]
+pub struct ReferralAccrual {
+    pub version: u8,
+    pub challenge: Pubkey,
+    pub referrer: Pubkey,
+    pub referred_count: u32,
+    pub accrued: u64,
+    pub claimed: bool,
+    pub bump: u8,
+    pub reserved: [u8; 32],
+}
+
+impl ReferralAccrual {
+    pub const VERSION: u8 = 1;
+}
+
+/// Off-chain permission for `user` to join `challenge` until `expiry`.
+///
+/// The backend signs `to_message()` with the challenge authority or
+/// oracle key; the joiner submits it in an ed25519 program instruction
+/// placed directly before the join.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JoinVoucher {
+    pub challenge: Pubkey,
+    pub user: Pubkey,
+    pub expiry: i64,
+}
+
+impl JoinVoucher {
+    pub const MESSAGE_LEN: usize = 32 + 32 + 8;
+
+    pub fn to_message(&self) -> [u8; Self::MESSAGE_LEN] {
+        let mut message = [0u8; Self::MESSAGE_LEN];
+        message[..32].copy_from_slice(self.challenge.as_ref());
+        message[32..64].copy_from_slice(self.user.as_ref());
+        message[64..].copy_from_slice(&self.expiry.to_le_bytes());
+        message
+    }
+
+    pub fn from_message(message: &[u8]) -> Result<Self> {
+        require!(
+            message.len() == Self::MESSAGE_LEN,
+            ProvenError::InvalidVoucher
+        );
+        Ok(Self {
+            challenge: Pubkey::new_from_array(message[..32].try_into().unwrap()),
+            user: Pubkey::new_from_array(message[32..64].try_into().unwrap()),
+            expiry: i64::from_le_bytes(message[64..].try_into().unwrap()),
+        })
+    }
+
+    /// Extracts the signer and voucher from a single-signature ed25519
+    /// program instruction whose data is all inline. The runtime has
+    /// already checked the signature by the time this runs.
+    pub fn from_ed25519_instruction(ix: &Instruction) -> Result<(Pubkey, Self)> {
+        require_keys_eq!(
+            ix.program_id,
+            ed25519_program::ID,
+            ProvenError::VoucherMissing
+        );
+        let data = &ix.data;
+        require!(
+            data.len() >= 16 && data[0] == 1,
+            ProvenError::InvalidVoucher
+        );
+        let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        // Offsets header: signature, public key and message offsets, each
+        // with the index of the instruction holding them
+        let pubkey_offset = read_u16(6) as usize;
+        let message_offset = read_u16(10) as usize;
+        let message_size = read_u16(12) as usize;
+        require!(
+            read_u16(4) == u16::MAX && read_u16(8) == u16::MAX && read_u16(14) == u16::MAX,
+            ProvenError::InvalidVoucher
+        );
+
+        let pubkey = data
+            .get(pubkey_offset..pubkey_offset + 32)
+            .ok_or(ProvenError::InvalidVoucher)?;
+        let message = data
+            .get(message_offset..message_offset + message_size)
+            .ok_or(ProvenError::InvalidVoucher)?;
+        Ok((
+            Pubkey::new_from_array(pubkey.try_into().unwrap()),
+            Self::from_message(message)?,
+        ))
+    }
+}
+
+/// Merkle root of the wallets allowed to join a private challenge.
+///
+/// Leaves are `hashv([0x00, wallet])` and interior nodes are
+/// `hashv([0x01, lo, hi])` with the two children sorted, so proofs carry
+/// no left/right flags.
+#[account]
+#[derive(InitSpace)]
+pub struct Allowlist {
+    pub version: u8,
+    pub challenge: Pubkey,
+    pub root: [u8; 32],
+    pub bump: u8,
+    pub reserved: [u8; 32],
+}
+
+impl Allowlist {
+    pub const VERSION: u8 = 1;
+
+    pub fn leaf(user: &Pubkey) -> [u8; 32] {
+        hashv(&[&[0x00], user.as_ref()]).to_bytes()
+    }
+
+    pub fn node(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+        let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+        hashv(&[&[0x01], lo, hi]).to_bytes()
+    }
+
+    pub fn contains(&self, user: &Pubkey, proof: &[[u8; 32]]) -> bool {
+        let computed = proof
+            .iter()
+            .fold(Self::leaf(user), |acc, sibling| Self::node(&acc, sibling));
+        computed == self.root
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TeamConfig {
+    pub version: u8,
+    pub challenge: Pubkey,
+    pub scoring: TeamScoring,
+    pub team_count: u32,
+    pub teams_settled: u32,
+    pub winning_teams: u32,
+    pub bonus_per_team: u64,
+    pub bump: u8,
+    /// Teams created that no one has joined, which settlement skips. Zero
+    /// on configs that predate it, which wait on every team instead.
+    pub empty_teams: u32,
+    pub reserved: [u8; 28],
+}
+
+impl TeamConfig {
+    pub const VERSION: u8 = 1;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Team {
+    pub version: u8,
+    pub challenge: Pubkey,
+    pub index: u32,
+    pub captain: Pubkey,
+    #[max_len(32)]
+    pub name: String,
+    pub member_count: u32,
+    /// Team proof days under the challenge's `TeamScoring`, set at settlement.
+    pub score: u32,
+    pub is_settled: bool,
+    pub is_winner: bool,
+    pub share_taken: bool,
+    pub bonus_per_member: u64,
+    pub remainder: u64,
+    pub remainder_claimed: u64,
+    pub bump: u8,
+    pub reserved: [u8; 32],
+}
+
+impl Team {
+    pub const VERSION: u8 = 1;
+    /// Must match the `max_len` on `name`.
+    pub const MAX_NAME_LENGTH: usize = 32;
+    /// Bounded so `settle_team` fits every member in one transaction.
+    pub const MAX_MEMBERS: u32 = 20;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum TeamScoring {
+    /// Mean of members' proof days, rounded down.
+    Average,
+    /// The weakest member's proof days.
+    Minimum,
+}
+
+impl TeamScoring {
+    pub fn score(&self, proof_days: impl Iterator<Item = u32>) -> u32 {
+        match self {
+            TeamScoring::Average => {
+                let (sum, count) = proof_days.fold((0u64, 0u64), |(sum, count), days| {
+                    (sum + days as u64, count + 1)
+                });
+                sum.checked_div(count).unwrap_or(0) as u32
+            }
+            TeamScoring::Minimum => proof_days.min().unwrap_or(0),
+        }
+    }
+}
+
+/// One-on-one bet: both sides stake the same amount into one escrow,
+/// without `Participant` accounts.
+#[account]
+#[derive(InitSpace)]
+pub struct Duel {
+    pub version: u8,
+    pub duel_id: u64,
+    pub challenger: Pubkey,
+    pub opponent: Pubkey,
+    pub token_mint: Pubkey,
+    pub oracle_signer: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub required_days: u32,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub status: DuelStatus,
+    pub challenger_proof_days: u32,
+    pub opponent_proof_days: u32,
+    pub challenger_payout: u64,
+    pub opponent_payout: u64,
+    pub challenger_claimed: bool,
+    pub opponent_claimed: bool,
+    pub bump: u8,
+    pub reserved: [u8; 32],
+}
+
+impl Duel {
+    pub const VERSION: u8 = 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum DuelStatus {
+    Pending,
+    Active,
+    Settled,
+    Cancelled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum ChallengeStatus {
+    Created,
+    Started,
+    Ended,
+    Settled,
+    Cancelled,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct SettleChallenge<'info> {
-    pub oracle: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
+/// Return data of `preview_settlement`: the counts and amounts
+/// `finalize_settlement` would book.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SettlementPreview {
+    pub winner_count: u32,
+    pub loser_count: u32,
+    /// Fee on the loser pool, before referral rewards and the
+    /// host/treasury/charity split.
+    pub platform_fee: u64,
+    pub bonus_per_winner: u64,
+    /// Bonus units left over after the even split, one per claim.
+    pub remainder: u64,
+    /// Loser pool routed to the forfeiture beneficiary instead of winners.
+    pub forfeited: u64,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct SettleParticipant<'info> {
-    pub oracle: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    #[account(
-        mut,
-        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
-        bump = participant.bump,
-    )]
-    pub participant: Account<'info, Participant>,
+// Events
+#[event]
+pub struct ChallengeCreated {
+    pub challenge_id: Pubkey,
+    pub admin: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub start_ts: i64,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct FinalizeSettlement<'info> {
-    pub oracle: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
+#[event]
+pub struct ChallengeJoined {
+    pub challenge_id: Pubkey,
+    pub user: Pubkey,
+    pub stake_amount: u64,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct ClaimPayout<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    #[account(
-        mut,
-        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
-        bump = participant.bump,
-    )]
-    pub participant: Account<'info, Participant>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = user,
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = challenge,
-    )]
-    pub escrow_vault: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct ProofRecorded {
+    pub challenge_id: Pubkey,
+    pub user: Pubkey,
+    pub proof_days: u32,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct CancelChallenge<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), admin.key().as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
+#[event]
+pub struct ChallengeSettlementStarted {
+    pub challenge_id: Pubkey,
+    pub required_days: u32,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct ClaimRefund<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    #[account(
-        mut,
-        seeds = [b"participant", challenge.key().as_ref(), user.key().as_ref()],
-        bump = participant.bump,
-    )]
-    pub participant: Account<'info, Participant>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = user,
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = challenge,
-    )]
-    pub escrow_vault: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct ParticipantSettled {
+    pub challenge_id: Pubkey,
+    pub user: Pubkey,
+    pub is_winner: bool,
+    pub proof_days: u32,
+    pub required_days: u32,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct WithdrawFees<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), admin.key().as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = admin,
-    )]
-    pub admin_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        associated_token::mint = challenge.token_mint,
-        associated_token::authority = challenge,
-    )]
-    pub escrow_vault: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct ChallengeSettled {
+    pub challenge_id: Pubkey,
+    pub winner_count: u32,
+    pub loser_count: u32,
+    pub bonus_per_winner: u64,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct CloseParticipant<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"challenge", challenge_id.as_bytes(), challenge.admin.as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
-    #[account(
-        mut,
-        close = destination,
-        seeds = [b"participant", challenge.key().as_ref(), participant.user.as_ref()],
-        bump = participant.bump,
-    )]
-    pub participant: Account<'info, Participant>,
-    /// CHECK: validated against participant.user in handler
-    #[account(mut)]
-    pub destination: UncheckedAccount<'info>,
+#[event]
+pub struct PayoutClaimed {
+    pub challenge_id: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
 }
 
-#[derive(Accounts)]
-#[instruction(challenge_id: String)]
-pub struct CloseChallenge<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    #[account(
-        mut,
-        close = admin,
-        seeds = [b"challenge", challenge_id.as_bytes(), admin.key().as_ref()],
-        bump = challenge.bump,
-    )]
-    pub challenge: Account<'info, Challenge>,
+#[event]
+pub struct ChallengeCancelled {
+    pub challenge_id: Pubkey,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct RefundClaimed {
+    pub challenge_id: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FeeWithdrawn {
+    pub challenge_id: Pubkey,
+    pub admin: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ParticipantClosed {
+    pub challenge_id: Pubkey,
+    pub user: Pubkey,
+    pub closed_by: Pubkey,
+}
+
+#[event]
+pub struct ChallengeClosed {
+    pub challenge_id: Pubkey,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct ChallengeAmended {
+    pub challenge_id: Pubkey,
+    pub old_total_days: u32,
+    pub new_total_days: u32,
+    pub old_threshold_bps: u16,
+    pub new_threshold_bps: u16,
+    pub old_end_ts: i64,
+    pub new_end_ts: i64,
+}
+
+#[event]
+pub struct OracleUpdated {
+    pub challenge_id: Pubkey,
+    pub old_oracle: Pubkey,
+    pub new_oracle: Pubkey,
+}
+
+#[event]
+pub struct OracleRegistryLinked {
+    pub challenge_id: Pubkey,
+    pub registry: Pubkey,
+}
+
+#[event]
+pub struct OracleUpdateQueued {
+    pub challenge_id: Pubkey,
+    pub new_oracle: Pubkey,
+    pub new_registry: Option<Pubkey>,
+    pub eta: i64,
+}
+
+#[event]
+pub struct OracleRegistryCreated {
+    pub registry: Pubkey,
+    pub authority: Pubkey,
+    pub signer: Pubkey,
+    pub update_delay: i64,
+}
+
+#[event]
+pub struct OracleRotationQueued {
+    pub registry: Pubkey,
+    pub new_signer: Pubkey,
+    pub eta: i64,
+}
+
+#[event]
+pub struct OracleRotated {
+    pub registry: Pubkey,
+    pub old_signer: Pubkey,
+    pub new_signer: Pubkey,
 }
 
-#[account]
-pub struct Challenge {
-    pub challenge_id: String,
+#[event]
+pub struct AdminProposed {
+    pub challenge_id: Pubkey,
+    pub current_admin: Pubkey,
+    pub proposed_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub challenge_id: Pubkey,
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct SeriesCreated {
+    pub series: Pubkey,
     pub admin: Pubkey,
-    pub token_mint: Pubkey,
-    pub escrow_vault: Pubkey,
     pub stake_amount: u64,
     pub total_days: u32,
-    pub threshold_bps: u16,
-    pub platform_fee_bps: u16,
-    pub status: ChallengeStatus,
-    pub start_ts: i64,
-    pub end_ts: i64,
-    pub participant_count: u32,
-    pub active_participants: u32,
-    pub winner_count: u32,
-    pub loser_count: u32,
-    pub bonus_per_winner: u64,
-    pub fee_amount: u64,
-    pub remainder: u64,
-    pub payouts_claimed_count: u32,
-    pub remainder_claimed: u64,
-    pub oracle_signer: Pubkey,
-    pub bump: u8,
+    pub first_start_ts: i64,
+    pub interval_secs: i64,
 }
 
-impl Challenge {
-    pub const MAX_ID_LENGTH: usize = 32;
-    pub const LEN: usize = 4
-        + Self::MAX_ID_LENGTH
-        + 32
-        + 32
-        + 32
-        + 8
-        + 4
-        + 2
-        + 2
-        + 1
-        + 8
-        + 8
-        + 4
-        + 4
-        + 4
-        + 4
-        + 8
-        + 8
-        + 8
-        + 4
-        + 8
-        + 32
-        + 1; // 218 bytes
+#[event]
+pub struct SeriesEditionSpawned {
+    pub series: Pubkey,
+    pub challenge_id: Pubkey,
+    pub edition: u32,
+    pub start_ts: i64,
 }
 
-#[account]
-pub struct Participant {
+#[event]
+pub struct AutoRollUpdated {
+    pub challenge_id: Pubkey,
     pub user: Pubkey,
-    pub challenge: Pubkey,
-    pub joined: bool,
-    pub stake_deposited: u64,
-    pub proof_days: u32,
-    pub is_winner: bool,
-    pub is_settled: bool,
-    pub payout_claimed: bool,
-    pub refund_claimed: bool,
-    pub bump: u8,
+    pub enabled: bool,
 }
 
-impl Participant {
-    pub const LEN: usize = 32 + 32 + 1 + 8 + 4 + 1 + 1 + 1 + 1 + 1; // 82 bytes
+#[event]
+pub struct StakeRolledOver {
+    pub from_challenge: Pubkey,
+    pub to_challenge: Pubkey,
+    pub user: Pubkey,
+    pub payout_amount: u64,
+    pub rolled_amount: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum ChallengeStatus {
-    Created,
-    Started,
-    Ended,
-    Settled,
-    Cancelled,
+#[event]
+pub struct TeamsEnabled {
+    pub challenge_id: Pubkey,
+    pub scoring: TeamScoring,
 }
 
-// Events
 #[event]
-pub struct ChallengeCreated {
+pub struct TeamCreated {
     pub challenge_id: Pubkey,
-    pub admin: Pubkey,
-    pub stake_amount: u64,
-    pub total_days: u32,
-    pub start_ts: i64,
+    pub team: Pubkey,
+    pub captain: Pubkey,
+    pub index: u32,
 }
 
 #[event]
-pub struct ChallengeJoined {
+pub struct TeamClosed {
     pub challenge_id: Pubkey,
-    pub user: Pubkey,
-    pub stake_amount: u64,
+    pub team: Pubkey,
 }
 
 #[event]
-pub struct ProofRecorded {
+pub struct TeamJoined {
     pub challenge_id: Pubkey,
+    pub team: Pubkey,
     pub user: Pubkey,
-    pub proof_days: u32,
 }
 
 #[event]
-pub struct ChallengeSettlementStarted {
+pub struct TeamSettled {
     pub challenge_id: Pubkey,
+    pub team: Pubkey,
+    pub score: u32,
     pub required_days: u32,
+    pub is_winner: bool,
 }
 
 #[event]
-pub struct ParticipantSettled {
+pub struct AllowlistUpdated {
     pub challenge_id: Pubkey,
+    pub root: [u8; 32],
+}
+
+#[event]
+pub struct CommitmentCreated {
+    pub commitment: Pubkey,
+    pub user: Pubkey,
+    pub referee: Pubkey,
+    pub recipient: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub start_ts: i64,
+}
+
+#[event]
+pub struct CommitmentProofRecorded {
+    pub commitment: Pubkey,
     pub user: Pubkey,
-    pub is_winner: bool,
     pub proof_days: u32,
-    pub required_days: u32,
 }
 
 #[event]
-pub struct ChallengeSettled {
+pub struct CommitmentSettled {
+    pub commitment: Pubkey,
+    pub user: Pubkey,
+    pub succeeded: bool,
+    pub payee: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BeneficiaryUpdated {
+    pub wallet: Pubkey,
+    pub name: String,
+    pub active: bool,
+}
+
+#[event]
+pub struct ForfeitureEnabled {
     pub challenge_id: Pubkey,
-    pub winner_count: u32,
-    pub loser_count: u32,
-    pub bonus_per_winner: u64,
+    pub beneficiary: Pubkey,
 }
 
 #[event]
-pub struct PayoutClaimed {
+pub struct StakesForfeited {
     pub challenge_id: Pubkey,
-    pub user: Pubkey,
+    pub beneficiary: Pubkey,
     pub amount: u64,
 }
 
 #[event]
-pub struct ChallengeCancelled {
+pub struct FeeShareWithdrawn {
     pub challenge_id: Pubkey,
-    pub admin: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
 }
 
 #[event]
-pub struct RefundClaimed {
+pub struct ProtocolTreasuryUpdated {
+    pub treasury: Pubkey,
+}
+
+#[event]
+pub struct ReferralRateUpdated {
+    pub challenge_id: Pubkey,
+    pub referral_bps: u16,
+}
+
+#[event]
+pub struct ReferralRecorded {
     pub challenge_id: Pubkey,
     pub user: Pubkey,
-    pub amount: u64,
+    pub referrer: Pubkey,
 }
 
 #[event]
-pub struct FeeWithdrawn {
+pub struct ReferralRewardClaimed {
     pub challenge_id: Pubkey,
-    pub admin: Pubkey,
+    pub referrer: Pubkey,
     pub amount: u64,
 }
 
 #[event]
-pub struct ParticipantClosed {
+pub struct VoucherRequirementUpdated {
     pub challenge_id: Pubkey,
+    pub required: bool,
+}
+
+#[event]
+pub struct DuelCreated {
+    pub duel: Pubkey,
+    pub challenger: Pubkey,
+    pub opponent: Pubkey,
+    pub stake_amount: u64,
+    pub total_days: u32,
+    pub start_ts: i64,
+}
+
+#[event]
+pub struct DuelAccepted {
+    pub duel: Pubkey,
+    pub opponent: Pubkey,
+}
+
+#[event]
+pub struct DuelProofRecorded {
+    pub duel: Pubkey,
     pub user: Pubkey,
-    pub closed_by: Pubkey,
+    pub proof_days: u32,
 }
 
 #[event]
-pub struct ChallengeClosed {
-    pub challenge_id: Pubkey,
-    pub admin: Pubkey,
+pub struct DuelSettled {
+    pub duel: Pubkey,
+    pub challenger_won: bool,
+    pub opponent_won: bool,
+    pub challenger_payout: u64,
+    pub opponent_payout: u64,
+}
+
+#[event]
+pub struct DuelCancelled {
+    pub duel: Pubkey,
+    pub challenger: Pubkey,
+}
+
+#[event]
+pub struct DuelClosed {
+    pub duel: Pubkey,
+    pub challenger: Pubkey,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub version: u8,
 }
 
 #[error_code]
@@ -1100,4 +5261,728 @@ pub enum ProvenError {
     RefundNotClaimed,
     #[msg("Platform fees still held in escrow")]
     FeesUncollected,
+    #[msg("Invalid delay")]
+    InvalidDelay,
+    #[msg("Oracle registry account required")]
+    OracleRegistryMissing,
+    #[msg("Oracle registry mismatch")]
+    OracleRegistryMismatch,
+    #[msg("No pending oracle rotation")]
+    NoPendingRotation,
+    #[msg("Oracle rotation still time-locked")]
+    RotationTimelocked,
+    #[msg("Signer is not the pending admin")]
+    NotPendingAdmin,
+    #[msg("Account already migrated")]
+    AlreadyMigrated,
+    #[msg("Unrecognized account layout")]
+    InvalidAccountLayout,
+    #[msg("Amendment not allowed after start")]
+    InvalidAmendment,
+    #[msg("Series interval shorter than challenge duration")]
+    InvalidInterval,
+    #[msg("Next edition cannot be spawned yet")]
+    SpawnTooEarly,
+    #[msg("Challenge is not part of a series")]
+    NotSeriesChallenge,
+    #[msg("Challenge belongs to a different series")]
+    SeriesMismatch,
+    #[msg("Challenge is not the next edition")]
+    NotNextEdition,
+    #[msg("Auto-roll not enabled")]
+    AutoRollDisabled,
+    #[msg("Payout does not cover the next stake")]
+    InsufficientRollover,
+    #[msg("Not allowed for team challenges")]
+    TeamChallenge,
+    #[msg("Team config account required")]
+    TeamConfigMissing,
+    #[msg("Invalid team name")]
+    InvalidTeamName,
+    #[msg("Team is full")]
+    TeamFull,
+    #[msg("Participant does not belong to this team")]
+    TeamMismatch,
+    #[msg("Every team member must be settled together")]
+    TeamMembersIncomplete,
+    #[msg("Opponent must differ from challenger")]
+    InvalidOpponent,
+    #[msg("Invalid duel status")]
+    InvalidDuelStatus,
+    #[msg("User is not part of this duel")]
+    NotDuelParticipant,
+    #[msg("Challenge is allowlisted but no allowlist account was provided")]
+    AllowlistMissing,
+    #[msg("Wallet is not on the challenge allowlist")]
+    NotAllowlisted,
+    #[msg("Join voucher instruction missing")]
+    VoucherMissing,
+    #[msg("Join voucher is malformed or signed by the wrong key")]
+    InvalidVoucher,
+    #[msg("Join voucher has expired")]
+    VoucherExpired,
+    #[msg("Users cannot refer themselves")]
+    InvalidReferrer,
+    #[msg("Referral accrual missing or for a different referrer")]
+    ReferralAccrualMissing,
+    #[msg("No referral rewards to claim")]
+    NoReferralRewards,
+    #[msg("Referral rewards must be claimed first")]
+    ReferralsUnclaimed,
+    #[msg("Fee shares exceed 100% or name no charity")]
+    InvalidFeeSplit,
+    #[msg("Beneficiary name must be 1-32 bytes")]
+    InvalidBeneficiaryName,
+    #[msg("Beneficiary is not active")]
+    BeneficiaryInactive,
+    #[msg("Beneficiary token account or escrow not provided")]
+    BeneficiaryMissing,
+    #[msg("Operation not supported for forfeiture challenges")]
+    ForfeitureChallenge,
+    #[msg("Recipient must differ from the committed user")]
+    InvalidRecipient,
+    #[msg("Every participant account must be passed exactly once")]
+    ParticipantsIncomplete,
+    #[msg("Oracle changes on a started challenge wait out its registry's delay")]
+    OracleUpdateTimelocked,
+    #[msg("Only teams no one has joined can be closed")]
+    TeamNotEmpty,
+    #[msg("Gated editions must be joined directly")]
+    GatedEdition,
+    #[msg("Referee must differ from the committed user")]
+    InvalidReferee,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Byte-for-byte pre-versioning `Challenge` account, unpadded.
+    fn legacy_challenge_fields(challenge_id: &str, status: u8) -> Vec<u8> {
+        let mut data = Challenge::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&(challenge_id.len() as u32).to_le_bytes());
+        data.extend_from_slice(challenge_id.as_bytes());
+        data.extend_from_slice(&[1; 32]); // admin
+        data.extend_from_slice(&[2; 32]); // token_mint
+        data.extend_from_slice(&[3; 32]); // escrow_vault
+        data.extend_from_slice(&5_000_000u64.to_le_bytes()); // stake_amount
+        data.extend_from_slice(&30u32.to_le_bytes()); // total_days
+        data.extend_from_slice(&8000u16.to_le_bytes()); // threshold_bps
+        data.extend_from_slice(&500u16.to_le_bytes()); // platform_fee_bps
+        data.push(status);
+        data.extend_from_slice(&1_700_000_000i64.to_le_bytes()); // start_ts
+        data.extend_from_slice(&1_702_592_000i64.to_le_bytes()); // end_ts
+        data.extend_from_slice(&7u32.to_le_bytes()); // participant_count
+        data.extend_from_slice(&6u32.to_le_bytes()); // active_participants
+        data.extend_from_slice(&4u32.to_le_bytes()); // winner_count
+        data.extend_from_slice(&3u32.to_le_bytes()); // loser_count
+        data.extend_from_slice(&3_562_500u64.to_le_bytes()); // bonus_per_winner
+        data.extend_from_slice(&750_000u64.to_le_bytes()); // fee_amount
+        data.extend_from_slice(&2u64.to_le_bytes()); // remainder
+        data.extend_from_slice(&1u32.to_le_bytes()); // payouts_claimed_count
+        data.extend_from_slice(&1u64.to_le_bytes()); // remainder_claimed
+        data.extend_from_slice(&[4; 32]); // oracle_signer
+        data.push(254); // bump
+        data
+    }
+
+    /// Legacy challenge padded to its allocated size, exactly as
+    /// `create_challenge` used to leave it.
+    fn legacy_challenge_bytes(challenge_id: &str, status: u8) -> Vec<u8> {
+        let mut data = legacy_challenge_fields(challenge_id, status);
+        data.resize(8 + LegacyChallenge::LEN, 0);
+        data
+    }
+
+    fn legacy_participant_bytes() -> Vec<u8> {
+        let mut data = Participant::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[5; 32]); // user
+        data.extend_from_slice(&[6; 32]); // challenge
+        data.push(1); // joined
+        data.extend_from_slice(&5_000_000u64.to_le_bytes()); // stake_deposited
+        data.extend_from_slice(&24u32.to_le_bytes()); // proof_days
+        data.push(1); // is_winner
+        data.push(1); // is_settled
+        data.push(0); // payout_claimed
+        data.push(0); // refund_claimed
+        data.push(253); // bump
+        data
+    }
+
+    /// Serializes a maximal instance and checks it exactly fills the space
+    /// allocated at `init`. Every account type must be covered here.
+    fn assert_fills_allocation<T: AccountSerialize + Space>(account: &T) {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + T::INIT_SPACE);
+    }
+
+    fn max_pubkey() -> Pubkey {
+        Pubkey::new_from_array([u8::MAX; 32])
+    }
+
+    #[test]
+    fn challenge_space_matches_maximal_instance() {
+        assert_fills_allocation(&Challenge {
+            version: u8::MAX,
+            challenge_id: "x".repeat(Challenge::MAX_ID_LENGTH),
+            id_hash: [u8::MAX; 32],
+            hashed_seeds: true,
+            admin: max_pubkey(),
+            token_mint: max_pubkey(),
+            escrow_vault: max_pubkey(),
+            stake_amount: u64::MAX,
+            total_days: u32::MAX,
+            threshold_bps: u16::MAX,
+            platform_fee_bps: u16::MAX,
+            status: ChallengeStatus::Cancelled,
+            start_ts: i64::MAX,
+            end_ts: i64::MAX,
+            participant_count: u32::MAX,
+            active_participants: u32::MAX,
+            winner_count: u32::MAX,
+            loser_count: u32::MAX,
+            bonus_per_winner: u64::MAX,
+            fee_amount: u64::MAX,
+            remainder: u64::MAX,
+            payouts_claimed_count: u32::MAX,
+            remainder_claimed: u64::MAX,
+            oracle_signer: max_pubkey(),
+            oracle_registry: Some(max_pubkey()),
+            authority: max_pubkey(),
+            pending_authority: Some(max_pubkey()),
+            bump: u8::MAX,
+            series: Some(max_pubkey()),
+            edition: u32::MAX,
+            team_mode: true,
+            allowlisted: true,
+            voucher_required: true,
+            referral_bps: u16::MAX,
+            referral_owed: u64::MAX,
+            treasury_fee_bps: u16::MAX,
+            charity_fee_bps: u16::MAX,
+            charity: Some(max_pubkey()),
+            treasury_fee_amount: u64::MAX,
+            charity_fee_amount: u64::MAX,
+            forfeit_beneficiary: Some(max_pubkey()),
+            reserved: [u8::MAX; 31],
+        });
+    }
+
+    #[test]
+    fn participant_space_matches_maximal_instance() {
+        assert_fills_allocation(&Participant {
+            version: u8::MAX,
+            user: max_pubkey(),
+            challenge: max_pubkey(),
+            joined: true,
+            stake_deposited: u64::MAX,
+            proof_days: u32::MAX,
+            is_winner: true,
+            is_settled: true,
+            payout_claimed: true,
+            refund_claimed: true,
+            bump: u8::MAX,
+            auto_roll: true,
+            team: Some(max_pubkey()),
+            referrer: Some(max_pubkey()),
+            reserved: [u8::MAX; 30],
+        });
+    }
+
+    #[test]
+    fn referral_accrual_space_matches_maximal_instance() {
+        assert_fills_allocation(&ReferralAccrual {
+            version: u8::MAX,
+            challenge: max_pubkey(),
+            referrer: max_pubkey(),
+            referred_count: u32::MAX,
+            accrued: u64::MAX,
+            claimed: true,
+            bump: u8::MAX,
+            reserved: [u8::MAX; 32],
+        });
+    }
+
+    #[test]
+    fn team_config_space_matches_maximal_instance() {
+        assert_fills_allocation(&TeamConfig {
+            version: u8::MAX,
+            challenge: max_pubkey(),
+            scoring: TeamScoring::Minimum,
+            team_count: u32::MAX,
+            teams_settled: u32::MAX,
+            winning_teams: u32::MAX,
+            bonus_per_team: u64::MAX,
+            bump: u8::MAX,
+            empty_teams: u32::MAX,
+            reserved: [u8::MAX; 28],
+        });
+    }
+
+    #[test]
+    fn team_space_matches_maximal_instance() {
+        assert_fills_allocation(&Team {
+            version: u8::MAX,
+            challenge: max_pubkey(),
+            index: u32::MAX,
+            captain: max_pubkey(),
+            name: "x".repeat(Team::MAX_NAME_LENGTH),
+            member_count: u32::MAX,
+            score: u32::MAX,
+            is_settled: true,
+            is_winner: true,
+            share_taken: true,
+            bonus_per_member: u64::MAX,
+            remainder: u64::MAX,
+            remainder_claimed: u64::MAX,
+            bump: u8::MAX,
+            reserved: [u8::MAX; 32],
+        });
+    }
+
+    #[test]
+    fn series_space_matches_maximal_instance() {
+        assert_fills_allocation(&ChallengeSeries {
+            version: u8::MAX,
+            series_id: "x".repeat(ChallengeSeries::MAX_ID_LENGTH),
+            admin: max_pubkey(),
+            token_mint: max_pubkey(),
+            oracle_signer: max_pubkey(),
+            stake_amount: u64::MAX,
+            total_days: u32::MAX,
+            threshold_bps: u16::MAX,
+            platform_fee_bps: u16::MAX,
+            interval_secs: i64::MAX,
+            next_edition: u32::MAX,
+            next_start_ts: i64::MAX,
+            bump: u8::MAX,
+            reserved: [u8::MAX; 64],
+        });
+    }
+
+    #[test]
+    fn oracle_registry_space_matches_maximal_instance() {
+        assert_fills_allocation(&OracleRegistry {
+            authority: max_pubkey(),
+            signer: max_pubkey(),
+            pending_signer: Some(max_pubkey()),
+            pending_eta: i64::MAX,
+            update_delay: i64::MAX,
+            bump: u8::MAX,
+        });
+    }
+
+    #[test]
+    fn duel_space_matches_maximal_instance() {
+        assert_fills_allocation(&Duel {
+            version: u8::MAX,
+            duel_id: u64::MAX,
+            challenger: max_pubkey(),
+            opponent: max_pubkey(),
+            token_mint: max_pubkey(),
+            oracle_signer: max_pubkey(),
+            stake_amount: u64::MAX,
+            total_days: u32::MAX,
+            required_days: u32::MAX,
+            start_ts: i64::MAX,
+            end_ts: i64::MAX,
+            status: DuelStatus::Cancelled,
+            challenger_proof_days: u32::MAX,
+            opponent_proof_days: u32::MAX,
+            challenger_payout: u64::MAX,
+            opponent_payout: u64::MAX,
+            challenger_claimed: true,
+            opponent_claimed: true,
+            bump: u8::MAX,
+            reserved: [u8::MAX; 32],
+        });
+    }
+
+    #[test]
+    fn pending_oracle_space_matches_maximal_instance() {
+        assert_fills_allocation(&PendingOracle {
+            challenge: max_pubkey(),
+            oracle_signer: max_pubkey(),
+            oracle_registry: Some(max_pubkey()),
+            eta: i64::MAX,
+            bump: u8::MAX,
+        });
+    }
+
+    #[test]
+    fn allowlist_space_matches_maximal_instance() {
+        assert_fills_allocation(&Allowlist {
+            version: u8::MAX,
+            challenge: max_pubkey(),
+            root: [u8::MAX; 32],
+            bump: u8::MAX,
+            reserved: [u8::MAX; 32],
+        });
+    }
+
+    #[test]
+    fn allowlist_proofs_verify_members_only() {
+        let wallets: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = wallets.iter().map(Allowlist::leaf).collect();
+        // Unbalanced tree: ((w0, w1), w2)
+        let left = Allowlist::node(&leaves[0], &leaves[1]);
+        let allowlist = Allowlist {
+            version: Allowlist::VERSION,
+            challenge: Pubkey::new_unique(),
+            root: Allowlist::node(&left, &leaves[2]),
+            bump: 0,
+            reserved: [0; 32],
+        };
+
+        assert!(allowlist.contains(&wallets[0], &[leaves[1], leaves[2]]));
+        assert!(allowlist.contains(&wallets[1], &[leaves[0], leaves[2]]));
+        assert!(allowlist.contains(&wallets[2], &[left]));
+        assert!(!allowlist.contains(&Pubkey::new_unique(), &[left]));
+        assert!(!allowlist.contains(&wallets[2], &[]));
+        // An interior node is not accepted as a leaf
+        assert!(!allowlist.contains(&Pubkey::new_from_array(left), &[leaves[2]]));
+    }
+
+    fn ed25519_instruction(signer: &Pubkey, message: &[u8]) -> Instruction {
+        // Same layout the ed25519 program's instruction builder produces
+        let pubkey_offset: u16 = 16;
+        let signature_offset = pubkey_offset + 32;
+        let message_offset = signature_offset + 64;
+        let mut data = vec![1u8, 0];
+        for field in [
+            signature_offset,
+            u16::MAX,
+            pubkey_offset,
+            u16::MAX,
+            message_offset,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[7; 64]);
+        data.extend_from_slice(message);
+        Instruction {
+            program_id: ed25519_program::ID,
+            accounts: vec![],
+            data,
+        }
+    }
+
+    #[test]
+    fn join_voucher_parses_from_ed25519_instruction() {
+        let signer = Pubkey::new_unique();
+        let voucher = JoinVoucher {
+            challenge: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            expiry: 1_700_000_000,
+        };
+        let ix = ed25519_instruction(&signer, &voucher.to_message());
+
+        assert_eq!(
+            JoinVoucher::from_ed25519_instruction(&ix).ok(),
+            Some((signer, voucher.clone()))
+        );
+
+        let mut other_program = ix.clone();
+        other_program.program_id = Pubkey::new_unique();
+        assert_eq!(
+            JoinVoucher::from_ed25519_instruction(&other_program).err(),
+            Some(ProvenError::VoucherMissing.into())
+        );
+
+        // Message pulled from another instruction
+        let mut external = ix.clone();
+        external.data[14..16].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(
+            JoinVoucher::from_ed25519_instruction(&external).err(),
+            Some(ProvenError::InvalidVoucher.into())
+        );
+
+        let truncated = ed25519_instruction(&signer, &voucher.to_message()[..40]);
+        assert_eq!(
+            JoinVoucher::from_ed25519_instruction(&truncated).err(),
+            Some(ProvenError::InvalidVoucher.into())
+        );
+    }
+
+    #[test]
+    fn legacy_sizes_match_deployed_layout() {
+        let max_id = "x".repeat(LegacyChallenge::MAX_ID_LENGTH);
+        assert_eq!(
+            legacy_challenge_fields(&max_id, 3).len(),
+            8 + LegacyChallenge::LEN
+        );
+        assert_eq!(legacy_participant_bytes().len(), 8 + LegacyParticipant::LEN);
+    }
+
+    #[test]
+    fn migrates_legacy_challenge() {
+        let data = legacy_challenge_bytes("walk-30", 3);
+        let challenge = Challenge::from_legacy_data(&data).unwrap();
+
+        assert_eq!(challenge.version, Challenge::VERSION);
+        assert_eq!(challenge.challenge_id, "walk-30");
+        assert!(!challenge.hashed_seeds);
+        assert_eq!(challenge.id_seed(), b"walk-30");
+        assert_eq!(challenge.id_hash, Challenge::hash_id("walk-30"));
+        assert_eq!(challenge.admin, Pubkey::new_from_array([1; 32]));
+        assert_eq!(challenge.authority, challenge.admin);
+        assert_eq!(challenge.pending_authority, None);
+        assert_eq!(challenge.token_mint, Pubkey::new_from_array([2; 32]));
+        assert_eq!(challenge.escrow_vault, Pubkey::new_from_array([3; 32]));
+        assert_eq!(challenge.stake_amount, 5_000_000);
+        assert_eq!(challenge.total_days, 30);
+        assert_eq!(challenge.threshold_bps, 8000);
+        assert_eq!(challenge.platform_fee_bps, 500);
+        assert!(challenge.status == ChallengeStatus::Settled);
+        assert_eq!(challenge.start_ts, 1_700_000_000);
+        assert_eq!(challenge.end_ts, 1_702_592_000);
+        assert_eq!(challenge.participant_count, 7);
+        assert_eq!(challenge.active_participants, 6);
+        assert_eq!(challenge.winner_count, 4);
+        assert_eq!(challenge.loser_count, 3);
+        assert_eq!(challenge.bonus_per_winner, 3_562_500);
+        assert_eq!(challenge.fee_amount, 750_000);
+        assert_eq!(challenge.remainder, 2);
+        assert_eq!(challenge.payouts_claimed_count, 1);
+        assert_eq!(challenge.remainder_claimed, 1);
+        assert_eq!(challenge.oracle_signer, Pubkey::new_from_array([4; 32]));
+        assert_eq!(challenge.oracle_registry, None);
+        assert_eq!(challenge.bump, 254);
+        assert_eq!(challenge.series, None);
+        assert!(!challenge.team_mode);
+        assert!(!challenge.allowlisted);
+        assert!(!challenge.voucher_required);
+        assert_eq!(challenge.referral_owed, 0);
+        assert_eq!(challenge.charity, None);
+        assert_eq!(challenge.forfeit_beneficiary, None);
+        assert_eq!(challenge.reserved, [0; 31]);
+    }
+
+    #[test]
+    fn migrated_challenge_fits_new_allocation() {
+        let max_id = "x".repeat(LegacyChallenge::MAX_ID_LENGTH);
+        let challenge = Challenge::from_legacy_data(&legacy_challenge_bytes(&max_id, 0)).unwrap();
+
+        let mut data = vec![0u8; 8 + Challenge::INIT_SPACE];
+        challenge.try_serialize(&mut &mut data[..]).unwrap();
+
+        let round_trip = Challenge::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(round_trip.challenge_id, max_id);
+        assert_eq!(round_trip.version, Challenge::VERSION);
+    }
+
+    #[test]
+    fn migrates_legacy_participant() {
+        let participant = Participant::from_legacy_data(&legacy_participant_bytes()).unwrap();
+
+        assert_eq!(participant.version, Participant::VERSION);
+        assert_eq!(participant.user, Pubkey::new_from_array([5; 32]));
+        assert_eq!(participant.challenge, Pubkey::new_from_array([6; 32]));
+        assert!(participant.joined);
+        assert_eq!(participant.stake_deposited, 5_000_000);
+        assert_eq!(participant.proof_days, 24);
+        assert!(participant.is_winner);
+        assert!(participant.is_settled);
+        assert!(!participant.payout_claimed);
+        assert!(!participant.refund_claimed);
+        assert_eq!(participant.bump, 253);
+        assert!(!participant.auto_roll);
+        assert_eq!(participant.team, None);
+        assert_eq!(participant.referrer, None);
+        assert_eq!(participant.reserved, [0; 30]);
+
+        let mut data = [0u8; 8 + Participant::INIT_SPACE];
+        participant.try_serialize(&mut &mut data[..]).unwrap();
+    }
+
+    #[test]
+    fn rejects_migrated_accounts() {
+        let challenge = Challenge::from_legacy_data(&legacy_challenge_bytes("walk-30", 0)).unwrap();
+        let mut data = vec![0u8; 8 + Challenge::INIT_SPACE];
+        challenge.try_serialize(&mut &mut data[..]).unwrap();
+
+        assert_eq!(
+            Challenge::from_legacy_data(&data).err(),
+            Some(ProvenError::AlreadyMigrated.into())
+        );
+    }
+
+    #[test]
+    fn migrates_v1_participant() {
+        let team = Pubkey::new_from_array([9; 32]);
+        let v1 = ParticipantV1 {
+            version: 1,
+            user: Pubkey::new_from_array([5; 32]),
+            challenge: Pubkey::new_from_array([6; 32]),
+            joined: true,
+            stake_deposited: 5_000_000,
+            proof_days: 24,
+            is_winner: true,
+            is_settled: true,
+            payout_claimed: false,
+            refund_claimed: false,
+            bump: 253,
+            auto_roll: true,
+            team: Some(team),
+            reserved: [0; 30],
+        };
+        let mut data = Participant::DISCRIMINATOR.to_vec();
+        v1.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + ParticipantV1::LEN);
+
+        let participant = Participant::from_legacy_data(&data).unwrap();
+        assert_eq!(participant.version, Participant::VERSION);
+        assert_eq!(participant.stake_deposited, 5_000_000);
+        assert_eq!(participant.proof_days, 24);
+        assert!(participant.auto_roll);
+        assert_eq!(participant.team, Some(team));
+        assert_eq!(participant.referrer, None);
+
+        let mut data = vec![0u8; 8 + Participant::INIT_SPACE];
+        participant.try_serialize(&mut &mut data[..]).unwrap();
+        assert_eq!(
+            Participant::from_legacy_data(&data).err(),
+            Some(ProvenError::AlreadyMigrated.into())
+        );
+    }
+
+    #[test]
+    fn migrates_v1_challenge() {
+        let mut v2 = Challenge::from_legacy_data(&legacy_challenge_bytes("walk-30", 3)).unwrap();
+        v2.hashed_seeds = true;
+        v2.team_mode = true;
+        v2.referral_bps = 1500;
+        v2.referral_owed = 42;
+        let mut data = vec![0u8; 8 + Challenge::INIT_SPACE];
+        v2.try_serialize(&mut &mut data[..]).unwrap();
+        // With the version 2 fields at their defaults, the leading bytes are
+        // exactly the version 1 account
+        data.truncate(8 + Challenge::V1_LEN);
+        data[8] = 1;
+
+        let challenge = Challenge::from_legacy_data(&data).unwrap();
+        assert_eq!(challenge.version, Challenge::VERSION);
+        assert_eq!(challenge.challenge_id, "walk-30");
+        assert!(challenge.hashed_seeds);
+        assert!(challenge.team_mode);
+        assert_eq!(challenge.fee_amount, 750_000);
+        assert_eq!(challenge.referral_bps, 1500);
+        assert_eq!(challenge.referral_owed, 42);
+        assert_eq!(challenge.treasury_fee_bps, 0);
+        assert_eq!(challenge.charity, None);
+    }
+
+    #[test]
+    fn v1_challenge_len_matches_layout() {
+        // Maximal id, every option set: the version 1 field list
+        let mut data = Vec::new();
+        let mut challenge = Challenge::from_legacy_data(&legacy_challenge_bytes("x", 0)).unwrap();
+        challenge.challenge_id = "x".repeat(Challenge::MAX_ID_LENGTH);
+        challenge.oracle_registry = Some(max_pubkey());
+        challenge.pending_authority = Some(max_pubkey());
+        challenge.series = Some(max_pubkey());
+        challenge.try_serialize(&mut data).unwrap();
+        let v2_tail = 2 + 2 + 1 + 8 + 8 + 1 + 31;
+        assert_eq!(data.len() - 8 - v2_tail + 14, Challenge::V1_LEN);
+    }
+
+    #[test]
+    fn fee_split_gives_rounding_dust_to_host() {
+        let mut challenge =
+            Challenge::from_legacy_data(&legacy_challenge_bytes("walk-30", 0)).unwrap();
+        challenge.treasury_fee_bps = 3333;
+        challenge.charity_fee_bps = 3333;
+        challenge.split_fee(1_000);
+
+        assert_eq!(challenge.treasury_fee_amount, 333);
+        assert_eq!(challenge.charity_fee_amount, 333);
+        assert_eq!(challenge.fee_amount, 334);
+        assert!(!challenge.fees_collected());
+
+        assert!(Challenge::validate_fee_split(5000, 5000, Some(max_pubkey())).is_ok());
+        assert_eq!(
+            Challenge::validate_fee_split(5000, 5001, Some(max_pubkey())).err(),
+            Some(ProvenError::InvalidFeeSplit.into())
+        );
+        assert_eq!(
+            Challenge::validate_fee_split(0, 100, None).err(),
+            Some(ProvenError::InvalidFeeSplit.into())
+        );
+    }
+
+    #[test]
+    fn beneficiary_space_matches_maximal_instance() {
+        assert_fills_allocation(&Beneficiary {
+            version: u8::MAX,
+            wallet: max_pubkey(),
+            name: "x".repeat(Beneficiary::MAX_NAME_LENGTH),
+            active: true,
+            bump: u8::MAX,
+            reserved: [u8::MAX; 32],
+        });
+    }
+
+    #[test]
+    fn commitment_space_matches_maximal_instance() {
+        assert_fills_allocation(&Commitment {
+            version: u8::MAX,
+            commitment_id: u64::MAX,
+            user: max_pubkey(),
+            referee: max_pubkey(),
+            recipient: max_pubkey(),
+            token_mint: max_pubkey(),
+            stake_amount: u64::MAX,
+            total_days: u32::MAX,
+            required_days: u32::MAX,
+            start_ts: i64::MAX,
+            end_ts: i64::MAX,
+            proof_days: u32::MAX,
+            bump: u8::MAX,
+            reserved: [u8::MAX; 32],
+        });
+    }
+
+    #[test]
+    fn protocol_config_space_matches_maximal_instance() {
+        assert_fills_allocation(&ProtocolConfig {
+            version: u8::MAX,
+            authority: max_pubkey(),
+            treasury: max_pubkey(),
+            bump: u8::MAX,
+            reserved: [u8::MAX; 64],
+        });
+    }
+
+    #[test]
+    fn referral_rewards_fit_within_fee() {
+        let mut challenge =
+            Challenge::from_legacy_data(&legacy_challenge_bytes("walk-30", 0)).unwrap();
+        challenge.platform_fee_bps = 500;
+        challenge.referral_bps = 2000;
+
+        assert_eq!(challenge.referral_reward(5_000_000), 50_000);
+        // Rounds down at both steps, so per-loser rewards never sum past
+        // the fee on the pooled stakes
+        assert_eq!(challenge.referral_reward(1_999), 19);
+        challenge.referral_bps = 10000;
+        assert_eq!(
+            challenge.referral_reward(u64::MAX),
+            (u64::MAX as u128 * 500 / 10000) as u64
+        );
+    }
+
+    #[test]
+    fn rejects_foreign_discriminator() {
+        let mut data = legacy_participant_bytes();
+        data[..8].copy_from_slice(&Challenge::DISCRIMINATOR);
+
+        assert_eq!(
+            Participant::from_legacy_data(&data).err(),
+            Some(ProvenError::InvalidAccountLayout.into())
+        );
+    }
 }
@@ -0,0 +1,349 @@
+//! Settlement arithmetic as pure functions over plain integers.
+//!
+//! The program books every settlement through these functions, and clients
+//! can call them to predict what it will book. Only `core` is used, so the
+//! module can be copied into a `no_std` build unchanged. Every division
+//! rounds down; whatever rounding leaves over is accounted for explicitly
+//! (the fee split's dust goes to the host, a pool's remainder is handed
+//! out one unit per claim).
+//!
+//! Overflow is reported as `None` rather than a program error, so callers
+//! decide how to surface it.
+
+/// Basis points in one whole.
+pub const BPS: u64 = 10_000;
+
+/// `bps` basis points of `amount`, rounded down.
+pub fn bps_of(amount: u64, bps: u16) -> u64 {
+    // Never larger than `amount` for bps <= BPS, and the program rejects
+    // anything higher before it gets here
+    (amount as u128 * bps as u128 / BPS as u128) as u64
+}
+
+/// Proof days a participant needs to win: `threshold_bps` of `total_days`,
+/// rounded down.
+pub fn required_days(total_days: u32, threshold_bps: u16) -> u32 {
+    (total_days as u64 * threshold_bps as u64 / BPS) as u32
+}
+
+/// How the platform fee is shared out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeSplit {
+    pub host: u64,
+    pub treasury: u64,
+    pub charity: u64,
+}
+
+/// Splits `fee` between treasury and charity by their basis points, with
+/// the rest (including rounding dust) going to the host.
+///
+/// Requires `treasury_bps + charity_bps <= BPS`, which the program checks
+/// when the terms are set.
+pub fn split_fee(fee: u64, treasury_bps: u16, charity_bps: u16) -> FeeSplit {
+    let treasury = bps_of(fee, treasury_bps);
+    let charity = bps_of(fee, charity_bps);
+    FeeSplit {
+        host: fee - treasury - charity,
+        treasury,
+        charity,
+    }
+}
+
+/// Referrer's cut of the platform fee taken from one losing `stake`.
+pub fn referral_reward(stake: u64, platform_fee_bps: u16, referral_bps: u16) -> u64 {
+    bps_of(bps_of(stake, platform_fee_bps), referral_bps)
+}
+
+/// Where the losers' stakes go once a challenge is settled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Distribution {
+    /// Every losing stake.
+    pub loser_pool: u64,
+    /// Platform fee on the loser pool, before referral rewards and the
+    /// host/treasury/charity split.
+    pub platform_fee: u64,
+    /// Loser pool less the fee, shared between winners.
+    pub distributable: u64,
+    /// Bonus each winning share receives.
+    pub bonus_per_share: u64,
+    /// Units left after dividing `distributable` evenly; the first
+    /// `remainder` claims take one extra unit each. With no winning shares
+    /// this is the whole distributable amount, which stays in escrow.
+    pub remainder: u64,
+}
+
+/// Splits the stakes of `losers` across `winning_shares` equal shares
+/// (winners in a solo challenge, winning teams in a team one).
+pub fn distribute(
+    stake_amount: u64,
+    losers: u32,
+    winning_shares: u32,
+    platform_fee_bps: u16,
+) -> Option<Distribution> {
+    let loser_pool = stake_amount.checked_mul(losers as u64)?;
+    let platform_fee = bps_of(loser_pool, platform_fee_bps);
+    let distributable = loser_pool - platform_fee;
+    let (bonus_per_share, remainder) = match winning_shares {
+        0 => (0, distributable),
+        n => (distributable / n as u64, distributable % n as u64),
+    };
+    Some(Distribution {
+        loser_pool,
+        platform_fee,
+        distributable,
+        bonus_per_share,
+        remainder,
+    })
+}
+
+/// Bonus for the next claim against a split of `per_share` with
+/// `remainder` units left over, of which `remainder_claimed` are already
+/// taken. Returns the bonus and how many remainder units it consumes.
+pub fn next_share(per_share: u64, remainder: u64, remainder_claimed: u64) -> Option<(u64, u64)> {
+    if remainder_claimed < remainder {
+        Some((per_share.checked_add(1)?, 1))
+    } else {
+        Some((per_share, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bonuses handed to `claims` claimants in turn.
+    fn claim_all(per_share: u64, remainder: u64, claims: u64) -> Vec<u64> {
+        let mut claimed = 0;
+        (0..claims)
+            .map(|_| {
+                let (bonus, taken) = next_share(per_share, remainder, claimed).unwrap();
+                claimed += taken;
+                bonus
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bps_of_rounds_down() {
+        assert_eq!(bps_of(10_000, 1), 1);
+        assert_eq!(bps_of(9_999, 1), 0);
+        assert_eq!(bps_of(19_999, 1), 1);
+        assert_eq!(bps_of(3, 3333), 0);
+        assert_eq!(bps_of(3, 3334), 1);
+        assert_eq!(bps_of(12_345, 0), 0);
+        assert_eq!(bps_of(12_345, 10_000), 12_345);
+    }
+
+    #[test]
+    fn bps_of_does_not_overflow_on_large_amounts() {
+        assert_eq!(bps_of(u64::MAX, 10_000), u64::MAX);
+        assert_eq!(bps_of(u64::MAX, 5_000), u64::MAX / 2);
+        assert_eq!(bps_of(u64::MAX, 1), u64::MAX / 10_000);
+    }
+
+    #[test]
+    fn required_days_rounds_down() {
+        assert_eq!(required_days(30, 8000), 24);
+        assert_eq!(required_days(10, 8000), 8);
+        // 7 * 0.8 = 5.6
+        assert_eq!(required_days(7, 8000), 5);
+        assert_eq!(required_days(3, 6666), 1);
+        assert_eq!(required_days(3, 6667), 2);
+        assert_eq!(required_days(1, 9999), 0);
+        assert_eq!(required_days(1, 10_000), 1);
+        assert_eq!(required_days(5, 0), 0);
+        assert_eq!(required_days(u32::MAX, 10_000), u32::MAX);
+    }
+
+    #[test]
+    fn required_days_never_exceeds_total() {
+        for total_days in 0..=60 {
+            for threshold_bps in 0..=10_000 {
+                let required = required_days(total_days, threshold_bps);
+                assert!(required <= total_days);
+                // Within one day of the exact fraction
+                let exact = total_days as u64 * threshold_bps as u64;
+                assert!(required as u64 * BPS <= exact);
+                assert!(exact < (required as u64 + 1) * BPS);
+            }
+        }
+    }
+
+    #[test]
+    fn fee_split_sends_dust_to_the_host() {
+        assert_eq!(
+            split_fee(50_000, 2000, 3000),
+            FeeSplit {
+                host: 25_000,
+                treasury: 10_000,
+                charity: 15_000,
+            }
+        );
+        // 3 * 0.3333 = 0.9999 each
+        assert_eq!(
+            split_fee(3, 3333, 3333),
+            FeeSplit {
+                host: 3,
+                treasury: 0,
+                charity: 0,
+            }
+        );
+        assert_eq!(
+            split_fee(7, 5000, 5000),
+            FeeSplit {
+                host: 1,
+                treasury: 3,
+                charity: 3,
+            }
+        );
+        assert_eq!(split_fee(0, 5000, 5000), FeeSplit::default());
+        assert_eq!(
+            split_fee(u64::MAX, 10_000, 0),
+            FeeSplit {
+                host: 0,
+                treasury: u64::MAX,
+                charity: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn fee_split_always_adds_up() {
+        for fee in (0..200).chain([9_999, 10_000, 10_001, u64::MAX]) {
+            for treasury_bps in (0..=10_000).step_by(97) {
+                for charity_bps in (0..=10_000 - treasury_bps).step_by(89) {
+                    let split = split_fee(fee, treasury_bps, charity_bps);
+                    assert_eq!(split.host + split.treasury + split.charity, fee);
+                    // Dust is under one unit per rounded share
+                    let exact_host = fee as u128 * (10_000 - treasury_bps - charity_bps) as u128;
+                    assert!(split.host as u128 * 10_000 >= exact_host);
+                    assert!(split.host as u128 * 10_000 < exact_host + 2 * 10_000);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn referral_reward_rounds_each_step_down() {
+        // 5% of 1_000_000, then half of that
+        assert_eq!(referral_reward(1_000_000, 500, 5000), 25_000);
+        // 5% of 39 is 1.95 -> 1, half of 1 -> 0
+        assert_eq!(referral_reward(39, 500, 5000), 0);
+        // 5% of 40 is 2, half of 2 -> 1
+        assert_eq!(referral_reward(40, 500, 5000), 1);
+        assert_eq!(referral_reward(1_000_000, 0, 10_000), 0);
+        assert_eq!(referral_reward(1_000_000, 1000, 0), 0);
+    }
+
+    #[test]
+    fn referral_rewards_never_exceed_the_fee() {
+        for stake in 0..500 {
+            for losers in 0..8u64 {
+                let reward = referral_reward(stake, 1000, 10_000) * losers;
+                assert!(reward <= bps_of(stake * losers, 1000));
+            }
+        }
+    }
+
+    #[test]
+    fn distribution_of_a_typical_challenge() {
+        // Three losers at 5 USDC, 5% fee, four winners
+        let d = distribute(5_000_000, 3, 4, 500).unwrap();
+        assert_eq!(
+            d,
+            Distribution {
+                loser_pool: 15_000_000,
+                platform_fee: 750_000,
+                distributable: 14_250_000,
+                bonus_per_share: 3_562_500,
+                remainder: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn distribution_remainder_is_what_division_leaves() {
+        // 7 * 3 = 21, 1% fee of 21 rounds to 0, 21 = 4 * 5 + 1
+        let d = distribute(7, 3, 5, 100).unwrap();
+        assert_eq!((d.platform_fee, d.bonus_per_share, d.remainder), (0, 4, 1));
+        // Fewer units than winners: everything is remainder
+        let d = distribute(1, 2, 3, 0).unwrap();
+        assert_eq!((d.bonus_per_share, d.remainder), (0, 2));
+        // Exact division leaves nothing
+        let d = distribute(10, 3, 3, 0).unwrap();
+        assert_eq!((d.bonus_per_share, d.remainder), (10, 0));
+    }
+
+    #[test]
+    fn distribution_without_winners_keeps_everything_as_remainder() {
+        let d = distribute(1_000, 4, 0, 1000).unwrap();
+        assert_eq!((d.platform_fee, d.distributable), (400, 3_600));
+        assert_eq!((d.bonus_per_share, d.remainder), (0, 3_600));
+    }
+
+    #[test]
+    fn distribution_without_losers_is_empty() {
+        let d = distribute(1_000, 0, 5, 1000).unwrap();
+        assert_eq!(d, Distribution::default());
+    }
+
+    #[test]
+    fn distribution_overflow_is_reported() {
+        assert_eq!(distribute(u64::MAX, 2, 1, 0), None);
+        assert!(distribute(u64::MAX, 1, 1, 0).is_some());
+        assert!(distribute(u64::MAX / 3, 3, 1, 1000).is_some());
+    }
+
+    #[test]
+    fn distribution_conserves_the_loser_pool() {
+        for stake in (1..60).chain([999_999, 1_000_001]) {
+            for losers in 0..7 {
+                for winners in 0..7 {
+                    for fee_bps in [0, 1, 333, 500, 999, 1000] {
+                        let d = distribute(stake, losers, winners, fee_bps).unwrap();
+                        assert_eq!(d.loser_pool, stake * losers as u64);
+                        assert_eq!(d.platform_fee + d.distributable, d.loser_pool);
+                        if winners > 0 {
+                            assert!(d.remainder < winners as u64);
+                            assert_eq!(
+                                d.bonus_per_share * winners as u64 + d.remainder,
+                                d.distributable
+                            );
+                        } else {
+                            assert_eq!(d.remainder, d.distributable);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn next_share_hands_out_the_remainder_one_unit_at_a_time() {
+        assert_eq!(claim_all(10, 3, 5), [11, 11, 11, 10, 10]);
+        assert_eq!(claim_all(0, 2, 3), [1, 1, 0]);
+        assert_eq!(claim_all(7, 0, 2), [7, 7]);
+        // Already fully claimed, as booked when nobody wins
+        assert_eq!(next_share(0, 5, 5), Some((0, 0)));
+    }
+
+    #[test]
+    fn next_share_overflow_is_reported() {
+        assert_eq!(next_share(u64::MAX, 1, 0), None);
+        assert_eq!(next_share(u64::MAX, 1, 1), Some((u64::MAX, 0)));
+    }
+
+    #[test]
+    fn claims_pay_out_exactly_the_distributable_amount() {
+        for distributable in 0..100 {
+            for winners in 1..12u32 {
+                let d = distribute(distributable, 1, winners, 0).unwrap();
+                let bonuses = claim_all(d.bonus_per_share, d.remainder, winners as u64);
+                assert_eq!(bonuses.iter().sum::<u64>(), distributable);
+                // No two winners differ by more than one unit
+                let (min, max) = (bonuses.iter().min(), bonuses.iter().max());
+                assert!(max.unwrap() - min.unwrap() <= 1);
+            }
+        }
+    }
+}
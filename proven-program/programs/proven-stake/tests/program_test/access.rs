@@ -0,0 +1,242 @@
+//! Join restrictions and referrals: allowlists, signed vouchers and
+//! referral rewards.
+
+use proven_stake::{Allowlist, JoinVoucher, ProvenError, ReferralAccrual};
+use proven_stake_client::{instructions, pda};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::slice;
+
+use crate::common::*;
+
+#[tokio::test]
+async fn allowlisted_challenges_need_a_merkle_proof() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let challenge = env
+        .create(&admin, terms("private", &Pubkey::new_unique()))
+        .await;
+    let (alice, bob, carol) = (env.user().await, env.user().await, env.user().await);
+    let (leaf_a, leaf_b) = (
+        Allowlist::leaf(&alice.pubkey()),
+        Allowlist::leaf(&bob.pubkey()),
+    );
+    let root = Allowlist::node(&leaf_a, &leaf_b);
+
+    let c = env.challenge(challenge).await;
+    let mut forged = c.clone();
+    forged.authority = carol.pubkey();
+    let ix = instructions::create_allowlist(&forged, root);
+    assert_error(env.send(&[ix], &[&carol]).await, ProvenError::Unauthorized);
+    let ix = instructions::create_allowlist(&c, root);
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let c = env.challenge(challenge).await;
+
+    let mut unlisted = c.clone();
+    unlisted.allowlisted = false;
+    let ix = instructions::join_challenge(&unlisted, alice.pubkey(), vec![leaf_b], None);
+    assert_error(
+        env.send(&[ix], &[&alice]).await,
+        ProvenError::AllowlistMissing,
+    );
+    let ix = instructions::join_challenge(&c, carol.pubkey(), vec![leaf_b], None);
+    assert_error(
+        env.send(&[ix], &[&carol]).await,
+        ProvenError::NotAllowlisted,
+    );
+    let ix = instructions::join_challenge(&c, alice.pubkey(), vec![leaf_b], None);
+    env.send(&[ix], &[&alice]).await.unwrap();
+
+    // A new root admits carol without evicting alice
+    let leaf_c = Allowlist::leaf(&carol.pubkey());
+    let ix = instructions::update_allowlist_root(&c, Allowlist::node(&leaf_b, &leaf_c));
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let ix = instructions::join_challenge(&c, carol.pubkey(), vec![leaf_b], None);
+    env.send(&[ix], &[&carol]).await.unwrap();
+    assert_eq!(env.challenge(challenge).await.participant_count, 2);
+
+    env.warp(c.start_ts).await;
+    let ix = instructions::update_allowlist_root(&c, root);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ChallengeStarted,
+    );
+}
+
+#[tokio::test]
+async fn voucher_gated_joins_need_a_fresh_signed_voucher() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("gated", &oracle.pubkey())).await;
+    let c = env.challenge(challenge).await;
+    let ix = instructions::set_voucher_required(&c, true);
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let c = env.challenge(challenge).await;
+
+    let (alice, bob) = (env.user().await, env.user().await);
+    let voucher = |user: &Keypair, expiry: i64| JoinVoucher {
+        challenge,
+        user: user.pubkey(),
+        expiry,
+    };
+    let signed = |signer: &Keypair, voucher: &JoinVoucher| {
+        let signature: [u8; 64] = signer.sign_message(&voucher.to_message()).into();
+        instructions::join_voucher(&signer.pubkey(), &signature, voucher)
+    };
+    let join = |user: &Keypair| instructions::join_challenge(&c, user.pubkey(), vec![], None);
+
+    let mut ungated = c.clone();
+    ungated.voucher_required = false;
+    let ix = instructions::join_challenge(&ungated, alice.pubkey(), vec![], None);
+    assert_error(
+        env.send(&[ix], &[&alice]).await,
+        ProvenError::VoucherMissing,
+    );
+    assert_error(
+        env.send(&[join(&alice)], &[&alice]).await,
+        ProvenError::VoucherMissing,
+    );
+
+    let fresh = voucher(&alice, T0 + DAY);
+    let stranger = Keypair::new();
+    assert_error(
+        env.send(&[signed(&stranger, &fresh), join(&alice)], &[&alice])
+            .await,
+        ProvenError::InvalidVoucher,
+    );
+    assert_error(
+        env.send(&[signed(&admin, &fresh), join(&bob)], &[&bob])
+            .await,
+        ProvenError::InvalidVoucher,
+    );
+    let stale = voucher(&alice, T0 - 1);
+    assert_error(
+        env.send(&[signed(&admin, &stale), join(&alice)], &[&alice])
+            .await,
+        ProvenError::VoucherExpired,
+    );
+
+    // Either the authority or the oracle may sign
+    env.send(&[signed(&admin, &fresh), join(&alice)], &[&alice])
+        .await
+        .unwrap();
+    let ix = signed(&oracle, &voucher(&bob, T0 + DAY));
+    env.send(&[ix, join(&bob)], &[&bob]).await.unwrap();
+    assert_eq!(env.challenge(challenge).await.participant_count, 2);
+}
+
+#[tokio::test]
+async fn referrers_earn_a_share_of_referred_losers_fees() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env
+        .create(&admin, terms("referrals", &oracle.pubkey()))
+        .await;
+    let c = env.challenge(challenge).await;
+
+    let stranger = env.wallet().await;
+    let mut forged = c.clone();
+    forged.authority = stranger.pubkey();
+    let ix = instructions::set_referral_bps(&forged, 5000);
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let ix = instructions::set_referral_bps(&c, 10_001);
+    assert_error(env.send(&[ix], &[&admin]).await, ProvenError::InvalidFee);
+    let ix = instructions::set_referral_bps(&c, 5000);
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    let (referrer, idle) = (env.wallet().await, env.wallet().await);
+    let (winner, loser) = (env.user().await, env.user().await);
+    let payer = env.payer();
+    for wallet in [&referrer, &idle, &loser] {
+        let ix = instructions::open_referral_accrual(&c, payer, wallet.pubkey());
+        env.send(&[ix], &[]).await.unwrap();
+    }
+
+    let ix = instructions::join_challenge(&c, loser.pubkey(), vec![], Some(loser.pubkey()));
+    assert_error(
+        env.send(&[ix], &[&loser]).await,
+        ProvenError::InvalidReferrer,
+    );
+    let accrual = pda::referral_accrual(&challenge, &referrer.pubkey());
+    let ix = instructions::join_challenge(&c, loser.pubkey(), vec![], Some(referrer.pubkey()));
+    assert_error(
+        env.send(&[omit_account(ix.clone(), &accrual)], &[&loser])
+            .await,
+        ProvenError::ReferralAccrualMissing,
+    );
+    env.send(&[ix], &[&loser]).await.unwrap();
+    env.join(challenge, &winner).await.unwrap();
+
+    let claim = instructions::claim_referral(&c, referrer.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&claim), &[&referrer]).await,
+        ProvenError::ChallengeNotSettled,
+    );
+
+    env.prove_days(challenge, &oracle, &winner.pubkey(), 8)
+        .await;
+    env.warp(c.end_ts + 1).await;
+    let ix = instructions::settle_challenge(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    let p = env.participant(challenge, &loser.pubkey()).await;
+    assert_eq!(p.referrer, Some(referrer.pubkey()));
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    assert_error(
+        env.send(&[omit_account(ix.clone(), &accrual)], &[&oracle])
+            .await,
+        ProvenError::ReferralAccrualMissing,
+    );
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    let p = env.participant(challenge, &winner.pubkey()).await;
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    let ix = instructions::finalize_settlement(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+
+    // The reward comes out of the fee and stays in escrow until claimed
+    let reward = STAKE / 20 / 2;
+    let c = env.challenge(challenge).await;
+    assert_eq!(
+        (c.referral_owed, c.fee_amount),
+        (reward, STAKE / 20 - reward)
+    );
+    env.send(&[instructions::withdraw_fees(&c)], &[&admin])
+        .await
+        .unwrap();
+    let ix = instructions::claim_payout(&c, winner.pubkey());
+    env.send(&[ix], &[&winner]).await.unwrap();
+    let close = instructions::close_challenge(&c);
+    assert_error(
+        env.send(slice::from_ref(&close), &[&admin]).await,
+        ProvenError::ReferralsUnclaimed,
+    );
+
+    env.send(slice::from_ref(&claim), &[&referrer])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&referrer.pubkey()).await, reward);
+    let accrual: ReferralAccrual = env.account(accrual).await;
+    assert!(accrual.claimed);
+    assert_error(
+        env.send(&[claim], &[&referrer]).await,
+        ProvenError::PayoutAlreadyClaimed,
+    );
+    let ix = instructions::claim_referral(&c, idle.pubkey());
+    assert_error(
+        env.send(&[ix], &[&idle]).await,
+        ProvenError::NoReferralRewards,
+    );
+    assert_eq!(env.balance(&challenge).await, 0);
+
+    for user in [&winner, &loser] {
+        let ix = instructions::close_participant(&c, user.pubkey(), user.pubkey());
+        env.send(&[ix], &[user]).await.unwrap();
+    }
+    env.send(&[close], &[&admin]).await.unwrap();
+    assert!(!env.exists(challenge).await);
+}
@@ -0,0 +1,433 @@
+//! Challenge administration, oracle registries, account migration and
+//! protocol settings.
+
+use anchor_lang::{AnchorSerialize, Discriminator};
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account;
+use anchor_spl::token;
+use proven_stake::{
+    Beneficiary, Challenge, ChallengeStatus, LegacyChallenge, LegacyParticipant, OracleRegistry,
+    Participant, PendingOracle, ProvenError, ID,
+};
+use proven_stake_client::{instructions, pda};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::slice;
+
+use crate::common::*;
+
+#[tokio::test]
+async fn amendments_only_relax_terms_once_started() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("amend", &oracle.pubkey())).await;
+    let c = env.challenge(challenge).await;
+
+    let stranger = env.wallet().await;
+    let mut forged = c.clone();
+    forged.authority = stranger.pubkey();
+    let ix = instructions::amend_challenge(&forged, 12, 8000);
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let ix = instructions::amend_challenge(&c, 0, 8000);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidDuration,
+    );
+    let ix = instructions::amend_challenge(&c, 10, 10_001);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidThreshold,
+    );
+
+    // Anything goes before the start
+    let ix = instructions::amend_challenge(&c, 5, 9000);
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    env.warp(c.start_ts).await;
+    let ix = instructions::amend_challenge(&c, 4, 9000);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidAmendment,
+    );
+    let ix = instructions::amend_challenge(&c, 5, 10_000);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidAmendment,
+    );
+    // A lower bar over more days may still demand more of them: 4 -> 9
+    let ix = instructions::amend_challenge(&c, 12, 8000);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidAmendment,
+    );
+    // Past the extension cap, however easy
+    let ix = instructions::amend_challenge(&c, 13, 0);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidAmendment,
+    );
+    let ix = instructions::amend_challenge(&c, 12, 4000);
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    let c = env.challenge(challenge).await;
+    assert_eq!((c.total_days, c.threshold_bps), (12, 4000));
+    assert_eq!(c.end_ts, c.start_ts + 12 * DAY);
+
+    env.warp(c.end_ts + 1).await;
+    let ix = instructions::amend_challenge(&c, 14, 8000);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ChallengeEnded,
+    );
+}
+
+#[tokio::test]
+async fn admin_transfer_needs_the_proposed_key() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env
+        .create(&admin, terms("handover", &oracle.pubkey()))
+        .await;
+    let c = env.challenge(challenge).await;
+    let (successor, stranger) = (env.wallet().await, env.wallet().await);
+
+    let mut forged = c.clone();
+    forged.authority = stranger.pubkey();
+    let ix = instructions::propose_admin(&forged, stranger.pubkey());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+
+    let ix = instructions::propose_admin(&c, successor.pubkey());
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let ix = instructions::accept_admin(&c, stranger.pubkey());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::NotPendingAdmin,
+    );
+    let ix = instructions::accept_admin(&c, successor.pubkey());
+    env.send(&[ix], &[&successor]).await.unwrap();
+
+    let c = env.challenge(challenge).await;
+    assert_eq!(c.authority, successor.pubkey());
+    assert_eq!(c.admin, admin.pubkey());
+    assert_eq!(c.pending_authority, None);
+
+    // The creator keeps the PDA seed but loses control
+    let ix = instructions::cancel_challenge(&c, admin.pubkey());
+    assert_error(env.send(&[ix], &[&admin]).await, ProvenError::Unauthorized);
+    let ix = instructions::cancel_challenge(&c, successor.pubkey());
+    env.send(&[ix], &[&successor]).await.unwrap();
+
+    // Nothing about a cancelled challenge can change
+    let ix = instructions::update_oracle(&c, Pubkey::new_unique());
+    assert_error(
+        env.send(&[ix], &[&successor]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+    let ix = instructions::create_oracle_registry(successor.pubkey(), oracle.pubkey(), 0);
+    env.send(&[ix], &[&successor]).await.unwrap();
+    let ix = instructions::link_oracle_registry(&c, &successor.pubkey());
+    assert_error(
+        env.send(&[ix], &[&successor]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+    let ix = instructions::amend_challenge(&c, 12, 8000);
+    assert_error(
+        env.send(&[ix], &[&successor]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+}
+
+#[tokio::test]
+async fn oracle_registry_rotation_is_time_locked() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let operator = env.wallet().await;
+    let (oracle, next_oracle) = (Keypair::new(), Keypair::new());
+
+    let ix = instructions::create_oracle_registry(operator.pubkey(), oracle.pubkey(), -1);
+    assert_error(
+        env.send(&[ix], &[&operator]).await,
+        ProvenError::InvalidDelay,
+    );
+    let ix = instructions::create_oracle_registry(operator.pubkey(), oracle.pubkey(), DAY);
+    env.send(&[ix], &[&operator]).await.unwrap();
+
+    // The challenge's own oracle is ignored once a registry is linked
+    let challenge = env
+        .create(&admin, terms("registry", &Pubkey::new_unique()))
+        .await;
+    let user = env.user().await;
+    env.join(challenge, &user).await.unwrap();
+    let c = env.challenge(challenge).await;
+
+    let stranger = env.wallet().await;
+    let mut forged = c.clone();
+    forged.authority = stranger.pubkey();
+    let ix = instructions::link_oracle_registry(&forged, &operator.pubkey());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let ix = instructions::link_oracle_registry(&c, &operator.pubkey());
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    let c = env.challenge(challenge).await;
+    assert_eq!(
+        c.oracle_registry,
+        Some(pda::oracle_registry(&operator.pubkey()))
+    );
+    env.warp(c.start_ts).await;
+
+    let mut unlinked = c.clone();
+    unlinked.oracle_registry = None;
+    let ix = instructions::record_proof(&unlinked, oracle.pubkey(), user.pubkey());
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::OracleRegistryMissing,
+    );
+
+    let other_operator = env.wallet().await;
+    let ix = instructions::create_oracle_registry(other_operator.pubkey(), oracle.pubkey(), 0);
+    env.send(&[ix], &[&other_operator]).await.unwrap();
+    let mut mislinked = c.clone();
+    mislinked.oracle_registry = Some(pda::oracle_registry(&other_operator.pubkey()));
+    let ix = instructions::record_proof(&mislinked, oracle.pubkey(), user.pubkey());
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::OracleRegistryMismatch,
+    );
+
+    env.record_proof(challenge, &oracle, &user.pubkey())
+        .await
+        .unwrap();
+
+    let apply = instructions::apply_oracle_rotation(&operator.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&apply), &[]).await,
+        ProvenError::NoPendingRotation,
+    );
+    let ix = instructions::rotate_oracle(operator.pubkey(), next_oracle.pubkey());
+    env.send(&[ix], &[&operator]).await.unwrap();
+    assert_error(
+        env.send(slice::from_ref(&apply), &[]).await,
+        ProvenError::RotationTimelocked,
+    );
+
+    // Until applied, the old signer stays in charge
+    env.record_proof(challenge, &oracle, &user.pubkey())
+        .await
+        .unwrap();
+    let now = env.now().await;
+    env.warp(now + DAY).await;
+    env.send(&[apply], &[]).await.unwrap();
+
+    let registry: OracleRegistry = env.account(pda::oracle_registry(&operator.pubkey())).await;
+    assert_eq!(registry.signer, next_oracle.pubkey());
+    assert_eq!(registry.pending_signer, None);
+
+    assert_error(
+        env.record_proof(challenge, &oracle, &user.pubkey()).await,
+        ProvenError::InvalidOracle,
+    );
+    env.record_proof(challenge, &next_oracle, &user.pubkey())
+        .await
+        .unwrap();
+
+    // Once started, leaving the registry waits out its delay too
+    let direct = Keypair::new();
+    let ix = instructions::update_oracle(&c, direct.pubkey());
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::OracleUpdateTimelocked,
+    );
+    let ix = instructions::link_oracle_registry(&c, &other_operator.pubkey());
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::OracleUpdateTimelocked,
+    );
+    let ix = instructions::propose_oracle_update(&c, direct.pubkey());
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let pending: PendingOracle = env.account(pda::pending_oracle(&challenge)).await;
+    assert_eq!(pending.eta, env.now().await + DAY);
+
+    let apply = instructions::apply_oracle_update(&c);
+    assert_error(
+        env.send(slice::from_ref(&apply), &[&admin]).await,
+        ProvenError::RotationTimelocked,
+    );
+    env.record_proof(challenge, &next_oracle, &user.pubkey())
+        .await
+        .unwrap();
+    let now = env.now().await;
+    env.warp(now + DAY).await;
+    env.send(&[apply], &[&admin]).await.unwrap();
+    assert_eq!(env.challenge(challenge).await.oracle_registry, None);
+    assert_error(
+        env.record_proof(challenge, &next_oracle, &user.pubkey())
+            .await,
+        ProvenError::InvalidOracle,
+    );
+    env.record_proof(challenge, &direct, &user.pubkey())
+        .await
+        .unwrap();
+    assert_eq!(
+        env.participant(challenge, &user.pubkey()).await.proof_days,
+        5
+    );
+}
+
+fn legacy_challenge(id: &str, admin: Pubkey, mint: Pubkey, bump: u8) -> Vec<u8> {
+    let address =
+        Pubkey::find_program_address(&[b"challenge", id.as_bytes(), admin.as_ref()], &ID).0;
+    let legacy = LegacyChallenge {
+        challenge_id: id.to_string(),
+        admin,
+        token_mint: mint,
+        escrow_vault: pda::escrow_vault(&address, &mint),
+        stake_amount: STAKE,
+        total_days: 10,
+        threshold_bps: 8000,
+        platform_fee_bps: 500,
+        status: ChallengeStatus::Created,
+        start_ts: T0 + DAY,
+        end_ts: T0 + 11 * DAY,
+        participant_count: 0,
+        active_participants: 0,
+        winner_count: 0,
+        loser_count: 0,
+        bonus_per_winner: 0,
+        fee_amount: 0,
+        remainder: 0,
+        payouts_claimed_count: 0,
+        remainder_claimed: 0,
+        oracle_signer: Pubkey::new_unique(),
+        bump,
+    };
+    let mut data = Challenge::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    data.resize(8 + LegacyChallenge::LEN, 0);
+    data
+}
+
+#[tokio::test]
+async fn legacy_accounts_migrate_once() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let id = "legacy-walk";
+    let (address, bump) =
+        Pubkey::find_program_address(&[b"challenge", id.as_bytes(), admin.pubkey().as_ref()], &ID);
+    let mint = env.mint;
+    env.set_raw_account(address, legacy_challenge(id, admin.pubkey(), mint, bump));
+
+    // Legacy challenges still escrow in the ATA of their PDA
+    let payer = env.payer();
+    let ix = create_associated_token_account(&payer, &address, &mint, &token::ID);
+    env.send(&[ix], &[]).await.unwrap();
+
+    let migrate = instructions::migrate_challenge(payer, address);
+    env.send(slice::from_ref(&migrate), &[]).await.unwrap();
+    assert_error(
+        env.send(&[migrate], &[]).await,
+        ProvenError::AlreadyMigrated,
+    );
+
+    let c = env.challenge(address).await;
+    assert_eq!(c.version, Challenge::VERSION);
+    assert!(!c.hashed_seeds);
+    assert_eq!(c.authority, admin.pubkey());
+    assert_eq!(pda::challenge_of(&c), address);
+
+    // The migrated challenge is usable through the current instructions
+    let user = env.user().await;
+    env.join(address, &user).await.unwrap();
+    assert_eq!(env.challenge(address).await.participant_count, 1);
+
+    let mut bad = legacy_challenge("bad-layout", admin.pubkey(), mint, 0);
+    bad[..8].copy_from_slice(&Participant::DISCRIMINATOR);
+    let bad_address = Pubkey::new_unique();
+    env.set_raw_account(bad_address, bad);
+    let ix = instructions::migrate_challenge(payer, bad_address);
+    assert_error(
+        env.send(&[ix], &[]).await,
+        ProvenError::InvalidAccountLayout,
+    );
+
+    let legacy = LegacyParticipant {
+        user: Pubkey::new_unique(),
+        challenge: address,
+        joined: true,
+        stake_deposited: STAKE,
+        proof_days: 3,
+        is_winner: false,
+        is_settled: false,
+        payout_claimed: false,
+        refund_claimed: false,
+        bump: 255,
+    };
+    let mut data = Participant::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    let participant = Pubkey::new_unique();
+    env.set_raw_account(participant, data);
+
+    let migrate = instructions::migrate_participant(payer, participant);
+    env.send(slice::from_ref(&migrate), &[]).await.unwrap();
+    assert_error(
+        env.send(&[migrate], &[]).await,
+        ProvenError::AlreadyMigrated,
+    );
+    let migrated: Participant = env.account(participant).await;
+    assert_eq!(migrated.version, Participant::VERSION);
+    assert_eq!((migrated.user, migrated.proof_days), (legacy.user, 3));
+    assert_eq!(migrated.referrer, None);
+}
+
+#[tokio::test]
+async fn protocol_settings_need_the_protocol_authority() {
+    let mut env = Env::new().await;
+    let (authority, stranger) = (env.wallet().await, env.wallet().await);
+    env.set_protocol(&authority.pubkey(), &Pubkey::new_unique());
+
+    let treasury = Pubkey::new_unique();
+    let ix = instructions::set_protocol_treasury(stranger.pubkey(), treasury);
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let ix = instructions::set_protocol_treasury(authority.pubkey(), treasury);
+    env.send(&[ix], &[&authority]).await.unwrap();
+
+    let charity = Pubkey::new_unique();
+    let ix = instructions::register_beneficiary(stranger.pubkey(), charity, "Food Bank".into());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    for name in [String::new(), "x".repeat(Beneficiary::MAX_NAME_LENGTH + 1)] {
+        let ix = instructions::register_beneficiary(authority.pubkey(), charity, name);
+        assert_error(
+            env.send(&[ix], &[&authority]).await,
+            ProvenError::InvalidBeneficiaryName,
+        );
+    }
+    let ix = instructions::register_beneficiary(authority.pubkey(), charity, "Food Bank".into());
+    env.send(&[ix], &[&authority]).await.unwrap();
+
+    let ix = instructions::set_beneficiary_active(stranger.pubkey(), &charity, false);
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let ix = instructions::set_beneficiary_active(authority.pubkey(), &charity, false);
+    env.send(&[ix], &[&authority]).await.unwrap();
+
+    let beneficiary: Beneficiary = env.account(pda::beneficiary(&charity)).await;
+    assert_eq!(beneficiary.name, "Food Bank");
+    assert!(!beneficiary.active);
+}
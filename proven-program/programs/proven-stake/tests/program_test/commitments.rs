@@ -0,0 +1,175 @@
+//! Solo commitments refereed by a third party, with failed stakes going to
+//! a recipient.
+
+use proven_stake::{instruction, Commitment, ProvenError};
+use proven_stake_client::{instructions, pda};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::common::*;
+
+/// Ten days, eight required, starting a day after [`T0`].
+fn commitment_terms(
+    commitment_id: u64,
+    referee: &Pubkey,
+    recipient: &Pubkey,
+) -> instruction::CreateCommitment {
+    instruction::CreateCommitment {
+        commitment_id,
+        stake_amount: STAKE,
+        total_days: 10,
+        threshold_bps: 8000,
+        start_ts: T0 + DAY,
+        referee: *referee,
+        recipient: *recipient,
+    }
+}
+
+impl Env {
+    async fn commit(&mut self, user: &Keypair, terms: instruction::CreateCommitment) -> Commitment {
+        let address = pda::commitment(&user.pubkey(), terms.commitment_id);
+        let ix = instructions::create_commitment(user.pubkey(), self.mint, terms);
+        self.send(&[ix], &[user]).await.unwrap();
+        self.account(address).await
+    }
+
+    async fn record_commitment_proof(
+        &mut self,
+        commitment: &Commitment,
+        referee: &Keypair,
+    ) -> TxResult {
+        let ix = instructions::record_commitment_proof(commitment);
+        self.send(&[ix], &[referee]).await
+    }
+}
+
+#[tokio::test]
+async fn success_returns_the_stake_early() {
+    let mut env = Env::new().await;
+    let (user, recipient) = (env.user().await, env.wallet().await);
+    let referee = Keypair::new();
+
+    let ix = instructions::create_commitment(
+        user.pubkey(),
+        env.mint,
+        commitment_terms(1, &referee.pubkey(), &user.pubkey()),
+    );
+    assert_error(
+        env.send(&[ix], &[&user]).await,
+        ProvenError::InvalidRecipient,
+    );
+    // Nor can the user referee their own commitment
+    let ix = instructions::create_commitment(
+        user.pubkey(),
+        env.mint,
+        commitment_terms(1, &user.pubkey(), &recipient.pubkey()),
+    );
+    assert_error(env.send(&[ix], &[&user]).await, ProvenError::InvalidReferee);
+
+    let commitment = env
+        .commit(
+            &user,
+            commitment_terms(1, &referee.pubkey(), &recipient.pubkey()),
+        )
+        .await;
+    assert_eq!(commitment.required_days, 8);
+    assert_eq!(env.balance(&user.pubkey()).await, FUNDING - STAKE);
+
+    assert_error(
+        env.record_commitment_proof(&commitment, &referee).await,
+        ProvenError::ChallengeNotStarted,
+    );
+    let impostor = Keypair::new();
+    let mut forged = commitment.clone();
+    forged.referee = impostor.pubkey();
+    assert_error(
+        env.record_commitment_proof(&forged, &impostor).await,
+        ProvenError::InvalidOracle,
+    );
+
+    let settle = instructions::settle_commitment(&commitment);
+    assert_error(
+        env.send(&[settle], &[]).await,
+        ProvenError::ChallengeNotEnded,
+    );
+
+    for day in 0..8 {
+        env.warp(commitment.start_ts + day * DAY).await;
+        env.record_commitment_proof(&commitment, &referee)
+            .await
+            .unwrap();
+    }
+
+    // Settles as soon as the goal is met, before the end
+    let commitment: Commitment = env.account(pda::commitment_of(&commitment)).await;
+    env.send(&[instructions::settle_commitment(&commitment)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&user.pubkey()).await, FUNDING);
+    assert_eq!(env.balance(&recipient.pubkey()).await, 0);
+    assert!(!env.exists(pda::commitment_of(&commitment)).await);
+}
+
+#[tokio::test]
+async fn failure_pays_the_recipient() {
+    let mut env = Env::new().await;
+    let (user, recipient) = (env.user().await, env.wallet().await);
+    let referee = Keypair::new();
+    let commitment = env
+        .commit(
+            &user,
+            commitment_terms(2, &referee.pubkey(), &recipient.pubkey()),
+        )
+        .await;
+
+    for day in 0..3 {
+        env.warp(commitment.start_ts + day * DAY).await;
+        env.record_commitment_proof(&commitment, &referee)
+            .await
+            .unwrap();
+    }
+    env.warp(commitment.end_ts + 1).await;
+    assert_error(
+        env.record_commitment_proof(&commitment, &referee).await,
+        ProvenError::ChallengeEnded,
+    );
+
+    // The stake can only go to the recipient
+    let commitment: Commitment = env.account(pda::commitment_of(&commitment)).await;
+    let mut claimed_success = commitment.clone();
+    claimed_success.proof_days = commitment.required_days;
+    let ix = instructions::settle_commitment(&claimed_success);
+    assert_error(env.send(&[ix], &[]).await, ProvenError::Unauthorized);
+
+    env.send(&[instructions::settle_commitment(&commitment)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&recipient.pubkey()).await, STAKE);
+    assert_eq!(env.balance(&user.pubkey()).await, FUNDING - STAKE);
+    assert!(!env.exists(pda::commitment_of(&commitment)).await);
+}
+
+#[tokio::test]
+async fn dust_in_the_escrow_does_not_block_settling() {
+    let mut env = Env::new().await;
+    let (user, recipient) = (env.user().await, env.wallet().await);
+    let referee = Keypair::new();
+    let commitment = env
+        .commit(
+            &user,
+            commitment_terms(3, &referee.pubkey(), &recipient.pubkey()),
+        )
+        .await;
+
+    let address = pda::commitment_of(&commitment);
+    let escrow = pda::escrow_vault(&address, &env.mint);
+    env.mint_to(escrow, 1).await;
+
+    env.warp(commitment.end_ts + 1).await;
+    env.send(&[instructions::settle_commitment(&commitment)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&recipient.pubkey()).await, STAKE + 1);
+    assert!(!env.exists(escrow).await);
+    assert!(!env.exists(address).await);
+}
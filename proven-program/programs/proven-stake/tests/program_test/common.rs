@@ -0,0 +1,403 @@
+//! Shared harness: a bank running the program natively alongside SPL
+//! Token, one test mint, and helpers for funded wallets, clock warping
+//! and asserting program errors.
+
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountDeserialize, AccountSerialize, Space};
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account;
+use anchor_spl::token::spl_token;
+use proven_stake::{instruction, Challenge, Participant, ProtocolConfig, ID};
+use proven_stake_client::{instructions, pda};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::clock::Clock;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::entrypoint::ProgramResult;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+pub const DAY: i64 = 24 * 60 * 60;
+/// Clock time every test starts from.
+pub const T0: i64 = 1_800_000_000;
+pub const STAKE: u64 = 1_000_000;
+/// Tokens minted to each wallet from [`Env::user`].
+pub const FUNDING: u64 = 100 * STAKE;
+
+pub type TxResult = Result<(), BanksClientError>;
+
+/// `entry` ties its account slice to the accounts' own lifetime, which
+/// the `processor!` signature can't express; leaking the slice for the
+/// duration of the test satisfies it.
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    proven_stake::entry(program_id, accounts, data)
+}
+
+pub struct Env {
+    pub ctx: ProgramTestContext,
+    pub mint: Pubkey,
+    /// Makes every transaction unique so identical instructions, such as
+    /// repeated proofs, aren't rejected as duplicates.
+    nonce: u64,
+}
+
+impl Env {
+    pub async fn new() -> Self {
+        Self::with_accounts(Vec::new()).await
+    }
+
+    /// Starts a bank preloaded with `accounts`, for state no instruction
+    /// can produce here, such as legacy layouts.
+    pub async fn with_accounts(accounts: Vec<(Pubkey, Account)>) -> Self {
+        let mut program_test =
+            ProgramTest::new("proven_stake", ID, processor!(process_instruction));
+        for (address, account) in accounts {
+            program_test.add_account(address, account);
+        }
+        let ctx = program_test.start_with_context().await;
+        let mut env = Self {
+            ctx,
+            mint: Pubkey::default(),
+            nonce: 0,
+        };
+
+        let mint = Keypair::new();
+        let payer = env.payer();
+        env.send(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    Rent::default().minimum_balance(spl_token::state::Mint::LEN),
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::ID,
+                ),
+                spl_token::instruction::initialize_mint(
+                    &spl_token::ID,
+                    &mint.pubkey(),
+                    &payer,
+                    None,
+                    6,
+                )
+                .unwrap(),
+            ],
+            &[&mint],
+        )
+        .await
+        .unwrap();
+        env.mint = mint.pubkey();
+        env.warp(T0).await;
+        env
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.ctx.payer.pubkey()
+    }
+
+    async fn transaction(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Transaction, BanksClientError> {
+        self.nonce += 1;
+        let mut all = vec![ComputeBudgetInstruction::set_compute_unit_price(self.nonce)];
+        all.extend_from_slice(ixs);
+
+        // Slots advance in real time, so a long test outlives any one blockhash
+        let blockhash = self.ctx.banks_client.get_latest_blockhash().await?;
+        let mut keypairs = vec![&self.ctx.payer];
+        keypairs.extend_from_slice(signers);
+        Ok(Transaction::new_signed_with_payer(
+            &all,
+            Some(&self.ctx.payer.pubkey()),
+            &keypairs,
+            blockhash,
+        ))
+    }
+
+    pub async fn send(&mut self, ixs: &[Instruction], signers: &[&Keypair]) -> TxResult {
+        let tx = self.transaction(ixs, signers).await?;
+        self.ctx.banks_client.process_transaction(tx).await
+    }
+
+    /// Simulates `ixs` without committing anything and returns the
+    /// program's return data.
+    pub async fn simulate(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Vec<u8>, BanksClientError> {
+        let tx = self.transaction(ixs, signers).await?;
+        let simulation = self.ctx.banks_client.simulate_transaction(tx).await?;
+        if let Some(Err(err)) = simulation.result {
+            return Err(err.into());
+        }
+        Ok(simulation
+            .simulation_details
+            .and_then(|details| details.return_data)
+            .map(|return_data| return_data.data)
+            .unwrap_or_default())
+    }
+
+    pub async fn now(&mut self) -> i64 {
+        let clock: Clock = self.ctx.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp
+    }
+
+    /// Moves the bank clock to `unix_timestamp`.
+    pub async fn warp(&mut self, unix_timestamp: i64) {
+        let mut clock: Clock = self.ctx.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = unix_timestamp;
+        self.ctx.set_sysvar(&clock);
+    }
+
+    /// A wallet with SOL for rent and an empty token account.
+    pub async fn wallet(&mut self) -> Keypair {
+        let wallet = Keypair::new();
+        let payer = self.payer();
+        let mint = self.mint;
+        self.send(
+            &[
+                system_instruction::transfer(&payer, &wallet.pubkey(), 1_000_000_000),
+                create_associated_token_account(&payer, &wallet.pubkey(), &mint, &spl_token::ID),
+            ],
+            &[],
+        )
+        .await
+        .unwrap();
+        wallet
+    }
+
+    /// A wallet holding [`FUNDING`] tokens.
+    pub async fn user(&mut self) -> Keypair {
+        let user = self.wallet().await;
+        let ata = self.ata(&user.pubkey());
+        self.mint_to(ata, FUNDING).await;
+        user
+    }
+
+    /// Mints `amount` straight into a token account, as anyone holding the
+    /// tokens could send them.
+    pub async fn mint_to(&mut self, account: Pubkey, amount: u64) {
+        let payer = self.payer();
+        let ix = spl_token::instruction::mint_to(
+            &spl_token::ID,
+            &self.mint,
+            &account,
+            &payer,
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    pub fn ata(&self, owner: &Pubkey) -> Pubkey {
+        get_associated_token_address(owner, &self.mint)
+    }
+
+    pub async fn balance(&mut self, owner: &Pubkey) -> u64 {
+        let address = self.ata(owner);
+        let account = self.ctx.banks_client.get_account(address).await.unwrap();
+        spl_token::state::Account::unpack(&account.unwrap().data)
+            .unwrap()
+            .amount
+    }
+
+    pub async fn exists(&mut self, address: Pubkey) -> bool {
+        self.ctx
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .is_some()
+    }
+
+    pub async fn account<T: AccountDeserialize>(&mut self, address: Pubkey) -> T {
+        let account = self.ctx.banks_client.get_account(address).await.unwrap();
+        T::try_deserialize(&mut &account.unwrap().data[..]).unwrap()
+    }
+
+    pub async fn challenge(&mut self, address: Pubkey) -> Challenge {
+        self.account(address).await
+    }
+
+    pub async fn participant(&mut self, challenge: Pubkey, user: &Pubkey) -> Participant {
+        self.account(pda::participant(&challenge, user)).await
+    }
+
+    /// Creates a challenge from `terms` administered by `admin`.
+    pub async fn try_create(
+        &mut self,
+        admin: &Keypair,
+        terms: instruction::CreateChallenge,
+    ) -> TxResult {
+        let ix = instructions::create_challenge(admin.pubkey(), self.mint, terms);
+        self.send(&[ix], &[admin]).await
+    }
+
+    pub async fn create(&mut self, admin: &Keypair, terms: instruction::CreateChallenge) -> Pubkey {
+        let address = pda::challenge(&terms.challenge_id, &admin.pubkey());
+        self.try_create(admin, terms).await.unwrap();
+        address
+    }
+
+    pub async fn join(&mut self, challenge: Pubkey, user: &Keypair) -> TxResult {
+        let c = self.challenge(challenge).await;
+        let ix = instructions::join_challenge(&c, user.pubkey(), vec![], None);
+        self.send(&[ix], &[user]).await
+    }
+
+    pub async fn record_proof(
+        &mut self,
+        challenge: Pubkey,
+        oracle: &Keypair,
+        user: &Pubkey,
+    ) -> TxResult {
+        let c = self.challenge(challenge).await;
+        let ix = instructions::record_proof(&c, oracle.pubkey(), *user);
+        self.send(&[ix], &[oracle]).await
+    }
+
+    /// Records `days` proofs for `user`, one per day from the challenge
+    /// start.
+    pub async fn prove_days(
+        &mut self,
+        challenge: Pubkey,
+        oracle: &Keypair,
+        user: &Pubkey,
+        days: u32,
+    ) {
+        let start_ts = self.challenge(challenge).await.start_ts;
+        for day in 0..days {
+            self.warp(start_ts + day as i64 * DAY).await;
+            self.record_proof(challenge, oracle, user).await.unwrap();
+        }
+    }
+
+    /// Ends the challenge and settles every listed participant, then
+    /// finalizes.
+    pub async fn settle_all(&mut self, challenge: Pubkey, oracle: &Keypair, users: &[&Pubkey]) {
+        let end_ts = self.challenge(challenge).await.end_ts;
+        if self.now().await <= end_ts {
+            self.warp(end_ts + 1).await;
+        }
+        let c = self.challenge(challenge).await;
+        self.send(
+            &[instructions::settle_challenge(&c, oracle.pubkey())],
+            &[oracle],
+        )
+        .await
+        .unwrap();
+        for user in users {
+            let p = self.participant(challenge, user).await;
+            let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+            self.send(&[ix], &[oracle]).await.unwrap();
+        }
+        let c = self.challenge(challenge).await;
+        self.send(
+            &[instructions::finalize_settlement(&c, oracle.pubkey())],
+            &[oracle],
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Writes `account` over whatever is at `address`; for states the
+    /// instructions can't reach.
+    pub fn set_account<T: AccountSerialize>(&mut self, address: Pubkey, value: &T, len: usize) {
+        let mut data = Vec::with_capacity(len);
+        value.try_serialize(&mut data).unwrap();
+        data.resize(len, 0);
+        self.set_raw_account(address, data);
+    }
+
+    /// Installs a `ProtocolConfig`; `initialize_protocol` needs the
+    /// program's upgrade authority, which a natively loaded program lacks.
+    pub fn set_protocol(&mut self, authority: &Pubkey, treasury: &Pubkey) {
+        let (address, bump) = Pubkey::find_program_address(&[b"protocol"], &ID);
+        let config = ProtocolConfig {
+            version: ProtocolConfig::VERSION,
+            authority: *authority,
+            treasury: *treasury,
+            bump,
+            reserved: [0; 64],
+        };
+        self.set_account(address, &config, 8 + ProtocolConfig::INIT_SPACE);
+    }
+
+    pub fn set_raw_account(&mut self, address: Pubkey, data: Vec<u8>) {
+        let account = Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.ctx.set_account(&address, &account.into());
+    }
+}
+
+/// Default terms: [`STAKE`], 10 days with 8 required, 5% fee, starting a
+/// day after [`T0`].
+pub fn terms(id: &str, oracle: &Pubkey) -> instruction::CreateChallenge {
+    instruction::CreateChallenge {
+        challenge_id: id.to_string(),
+        stake_amount: STAKE,
+        total_days: 10,
+        threshold_bps: 8000,
+        platform_fee_bps: 500,
+        start_ts: T0 + DAY,
+        oracle_signer: *oracle,
+        treasury_fee_bps: 0,
+        charity_fee_bps: 0,
+        charity: None,
+    }
+}
+
+/// Passes `address` as an absent optional account, which Anchor encodes
+/// as the program id.
+pub fn omit_account(mut ix: Instruction, address: &Pubkey) -> Instruction {
+    for meta in ix
+        .accounts
+        .iter_mut()
+        .filter(|meta| meta.pubkey == *address)
+    {
+        *meta = AccountMeta::new_readonly(ID, false);
+    }
+    ix
+}
+
+fn custom_code<T>(result: Result<T, BanksClientError>) -> Option<u32> {
+    let err = match result {
+        Ok(_) => return None,
+        Err(BanksClientError::TransactionError(err)) => err,
+        Err(BanksClientError::SimulationError { err, .. }) => err,
+        Err(err) => panic!("unexpected client error: {err}"),
+    };
+    match err {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(code),
+        err => panic!("expected a custom program error, got {err}"),
+    }
+}
+
+/// Asserts the transaction failed with `expected`, a `ProvenError` or an
+/// Anchor `ErrorCode`.
+#[track_caller]
+pub fn assert_error<T>(result: Result<T, BanksClientError>, expected: impl Into<u32>) {
+    let expected = expected.into();
+    match custom_code(result) {
+        Some(code) => assert_eq!(code, expected, "expected error {expected}, got {code}"),
+        None => panic!("expected error {expected}, but the transaction succeeded"),
+    }
+}
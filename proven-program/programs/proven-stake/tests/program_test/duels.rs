@@ -0,0 +1,240 @@
+//! One-on-one duels: offer, acceptance, proofs, settlement and cleanup.
+
+use proven_stake::{instruction, Duel, DuelStatus, ProvenError};
+use proven_stake_client::{instructions, pda};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::slice;
+
+use crate::common::*;
+
+/// Ten days, eight required, starting a day after [`T0`].
+fn duel_terms(duel_id: u64, opponent: &Pubkey, oracle: &Pubkey) -> instruction::CreateDuel {
+    instruction::CreateDuel {
+        duel_id,
+        opponent: *opponent,
+        stake_amount: STAKE,
+        total_days: 10,
+        threshold_bps: 8000,
+        start_ts: T0 + DAY,
+        oracle_signer: *oracle,
+    }
+}
+
+impl Env {
+    async fn create_duel(&mut self, challenger: &Keypair, terms: instruction::CreateDuel) -> Duel {
+        let address = pda::duel(&challenger.pubkey(), terms.duel_id);
+        let ix = instructions::create_duel(challenger.pubkey(), self.mint, terms);
+        self.send(&[ix], &[challenger]).await.unwrap();
+        self.account(address).await
+    }
+
+    async fn duel(&mut self, duel: &Duel) -> Duel {
+        self.account(pda::duel_of(duel)).await
+    }
+
+    async fn record_duel_proof(
+        &mut self,
+        duel: &Duel,
+        oracle: &Keypair,
+        user: &Pubkey,
+    ) -> TxResult {
+        let ix = instructions::record_duel_proof(duel, *user);
+        self.send(&[ix], &[oracle]).await
+    }
+}
+
+#[tokio::test]
+async fn sole_completer_takes_the_pot() {
+    let mut env = Env::new().await;
+    let (challenger, opponent, stranger) = (env.user().await, env.user().await, env.user().await);
+    let oracle = Keypair::new();
+
+    let ix = instructions::create_duel(
+        challenger.pubkey(),
+        env.mint,
+        duel_terms(1, &challenger.pubkey(), &oracle.pubkey()),
+    );
+    assert_error(
+        env.send(&[ix], &[&challenger]).await,
+        ProvenError::InvalidOpponent,
+    );
+
+    let duel = env
+        .create_duel(
+            &challenger,
+            duel_terms(1, &opponent.pubkey(), &oracle.pubkey()),
+        )
+        .await;
+    assert_eq!(duel.required_days, 8);
+    assert_eq!(env.balance(&challenger.pubkey()).await, FUNDING - STAKE);
+
+    // Pending
+    let mut impersonated = duel.clone();
+    impersonated.opponent = stranger.pubkey();
+    let ix = instructions::accept_duel(&impersonated);
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    assert_error(
+        env.record_duel_proof(&duel, &oracle, &challenger.pubkey())
+            .await,
+        ProvenError::InvalidDuelStatus,
+    );
+    assert_error(
+        env.send(&[instructions::settle_duel(&duel)], &[]).await,
+        ProvenError::InvalidDuelStatus,
+    );
+    assert_error(
+        env.send(&[instructions::close_duel(&duel)], &[&challenger])
+            .await,
+        ProvenError::ChallengeStillActive,
+    );
+
+    let accept = instructions::accept_duel(&duel);
+    env.send(slice::from_ref(&accept), &[&opponent])
+        .await
+        .unwrap();
+    assert_error(
+        env.send(&[accept], &[&opponent]).await,
+        ProvenError::InvalidDuelStatus,
+    );
+    assert_error(
+        env.send(&[instructions::cancel_duel(&duel)], &[&challenger])
+            .await,
+        ProvenError::InvalidDuelStatus,
+    );
+
+    // Active
+    assert_error(
+        env.record_duel_proof(&duel, &oracle, &challenger.pubkey())
+            .await,
+        ProvenError::ChallengeNotStarted,
+    );
+    for day in 0..8 {
+        env.warp(duel.start_ts + day * DAY).await;
+        env.record_duel_proof(&duel, &oracle, &challenger.pubkey())
+            .await
+            .unwrap();
+    }
+    assert_error(
+        env.record_duel_proof(&duel, &oracle, &stranger.pubkey())
+            .await,
+        ProvenError::NotDuelParticipant,
+    );
+    let impostor = Keypair::new();
+    let mut forged = duel.clone();
+    forged.oracle_signer = impostor.pubkey();
+    assert_error(
+        env.record_duel_proof(&forged, &impostor, &opponent.pubkey())
+            .await,
+        ProvenError::InvalidOracle,
+    );
+    env.record_duel_proof(&duel, &oracle, &opponent.pubkey())
+        .await
+        .unwrap();
+
+    let claim = instructions::claim_duel(&duel, challenger.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&claim), &[&challenger]).await,
+        ProvenError::ChallengeNotSettled,
+    );
+    assert_error(
+        env.send(&[instructions::settle_duel(&duel)], &[]).await,
+        ProvenError::ChallengeNotEnded,
+    );
+
+    // Settled
+    env.warp(duel.end_ts + 1).await;
+    assert_error(
+        env.record_duel_proof(&duel, &oracle, &opponent.pubkey())
+            .await,
+        ProvenError::ChallengeEnded,
+    );
+    env.send(&[instructions::settle_duel(&duel)], &[])
+        .await
+        .unwrap();
+    let settled = env.duel(&duel).await;
+    assert!(settled.status == DuelStatus::Settled);
+    assert_eq!(
+        (settled.challenger_payout, settled.opponent_payout),
+        (2 * STAKE, 0)
+    );
+
+    let ix = instructions::claim_duel(&duel, opponent.pubkey());
+    assert_error(
+        env.send(&[ix], &[&opponent]).await,
+        ProvenError::PayoutAlreadyClaimed,
+    );
+    let ix = instructions::claim_duel(&duel, stranger.pubkey());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::NotDuelParticipant,
+    );
+    assert_error(
+        env.send(&[instructions::close_duel(&duel)], &[&challenger])
+            .await,
+        ProvenError::PendingWinnerPayouts,
+    );
+
+    env.send(&[claim], &[&challenger]).await.unwrap();
+    assert_eq!(env.balance(&challenger.pubkey()).await, FUNDING + STAKE);
+    assert_eq!(env.balance(&opponent.pubkey()).await, FUNDING - STAKE);
+
+    env.send(&[instructions::close_duel(&duel)], &[&challenger])
+        .await
+        .unwrap();
+    assert!(!env.exists(pda::duel_of(&duel)).await);
+}
+
+#[tokio::test]
+async fn unaccepted_duels_are_cancelled() {
+    let mut env = Env::new().await;
+    let (challenger, opponent) = (env.user().await, env.user().await);
+    let oracle = Pubkey::new_unique();
+    let duel = env
+        .create_duel(&challenger, duel_terms(7, &opponent.pubkey(), &oracle))
+        .await;
+
+    env.warp(duel.start_ts).await;
+    let ix = instructions::accept_duel(&duel);
+    assert_error(
+        env.send(&[ix], &[&opponent]).await,
+        ProvenError::ChallengeStarted,
+    );
+
+    env.send(&[instructions::cancel_duel(&duel)], &[&challenger])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&challenger.pubkey()).await, FUNDING);
+    assert!(env.duel(&duel).await.status == DuelStatus::Cancelled);
+
+    env.send(&[instructions::close_duel(&duel)], &[&challenger])
+        .await
+        .unwrap();
+    assert!(!env.exists(pda::duel_of(&duel)).await);
+}
+
+#[tokio::test]
+async fn dust_in_the_escrow_does_not_block_closing() {
+    let mut env = Env::new().await;
+    let (challenger, opponent) = (env.user().await, env.user().await);
+    let oracle = Pubkey::new_unique();
+    let duel = env
+        .create_duel(&challenger, duel_terms(8, &opponent.pubkey(), &oracle))
+        .await;
+    env.send(&[instructions::cancel_duel(&duel)], &[&challenger])
+        .await
+        .unwrap();
+
+    let escrow = pda::escrow_vault(&pda::duel_of(&duel), &env.mint);
+    env.mint_to(escrow, 1).await;
+
+    env.send(&[instructions::close_duel(&duel)], &[&challenger])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&challenger.pubkey()).await, FUNDING + 1);
+    assert!(!env.exists(escrow).await);
+    assert!(!env.exists(pda::duel_of(&duel)).await);
+}
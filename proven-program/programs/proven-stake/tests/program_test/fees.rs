@@ -0,0 +1,199 @@
+//! Where the loser pool and platform fee go: the host/treasury/charity
+//! split and forfeiture to a registered beneficiary.
+
+use proven_stake::{ProvenError, TeamScoring};
+use proven_stake_client::instructions;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::slice;
+
+use crate::common::*;
+
+#[tokio::test]
+async fn platform_fee_is_split_between_host_treasury_and_charity() {
+    let mut env = Env::new().await;
+    let (admin, protocol, treasury, charity) = (
+        env.user().await,
+        env.wallet().await,
+        env.wallet().await,
+        env.wallet().await,
+    );
+    env.set_protocol(&protocol.pubkey(), &treasury.pubkey());
+    let oracle = Keypair::new();
+
+    let mut t = terms("split", &oracle.pubkey());
+    t.treasury_fee_bps = 2000;
+    t.charity_fee_bps = 3000;
+    t.charity = Some(charity.pubkey());
+    let challenge = env.create(&admin, t).await;
+    let (alice, bob, carol) = (env.user().await, env.user().await, env.user().await);
+    for user in [&alice, &bob, &carol] {
+        env.join(challenge, user).await.unwrap();
+    }
+
+    let c = env.challenge(challenge).await;
+    let withdraw_treasury =
+        instructions::withdraw_treasury_fees(&c, protocol.pubkey(), treasury.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&withdraw_treasury), &[&protocol])
+            .await,
+        ProvenError::InvalidChallengeStatus,
+    );
+
+    for user in [&alice, &bob] {
+        env.prove_days(challenge, &oracle, &user.pubkey(), 8).await;
+    }
+    env.settle_all(
+        challenge,
+        &oracle,
+        &[&alice.pubkey(), &bob.pubkey(), &carol.pubkey()],
+    )
+    .await;
+
+    // Carol's 5% fee, split 20/30/50
+    let c = env.challenge(challenge).await;
+    assert_eq!(
+        (c.treasury_fee_amount, c.charity_fee_amount, c.fee_amount),
+        (10_000, 15_000, 25_000)
+    );
+
+    let stranger = env.wallet().await;
+    let ix = instructions::withdraw_treasury_fees(&c, stranger.pubkey(), treasury.pubkey());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let mut redirected = c.clone();
+    redirected.charity = Some(stranger.pubkey());
+    let ix = instructions::withdraw_charity_fees(&redirected).unwrap();
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+
+    for user in [&alice, &bob, &carol] {
+        let close = instructions::close_participant(&c, user.pubkey(), user.pubkey());
+        if user.pubkey() != carol.pubkey() {
+            let claim = instructions::claim_payout(&c, user.pubkey());
+            env.send(&[claim, close], &[user]).await.unwrap();
+        } else {
+            env.send(&[close], &[user]).await.unwrap();
+        }
+    }
+    let close = instructions::close_challenge(&c);
+    assert_error(
+        env.send(slice::from_ref(&close), &[&admin]).await,
+        ProvenError::FeesUncollected,
+    );
+
+    env.send(slice::from_ref(&withdraw_treasury), &[&protocol])
+        .await
+        .unwrap();
+    let withdraw_charity = instructions::withdraw_charity_fees(&c).unwrap();
+    env.send(slice::from_ref(&withdraw_charity), &[&charity])
+        .await
+        .unwrap();
+    env.send(&[instructions::withdraw_fees(&c)], &[&admin])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&treasury.pubkey()).await, 10_000);
+    assert_eq!(env.balance(&charity.pubkey()).await, 15_000);
+    assert_eq!(env.balance(&admin.pubkey()).await, FUNDING + 25_000);
+    assert_eq!(env.balance(&challenge).await, 0);
+
+    assert_error(
+        env.send(&[withdraw_treasury], &[&protocol]).await,
+        ProvenError::NoFees,
+    );
+    assert_error(
+        env.send(&[withdraw_charity], &[&charity]).await,
+        ProvenError::NoFees,
+    );
+    env.send(&[close], &[&admin]).await.unwrap();
+}
+
+#[tokio::test]
+async fn forfeiture_sends_the_loser_pool_to_the_beneficiary() {
+    let mut env = Env::new().await;
+    let (admin, protocol, beneficiary) = (env.user().await, env.wallet().await, env.wallet().await);
+    env.set_protocol(&protocol.pubkey(), &Pubkey::new_unique());
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("forfeit", &oracle.pubkey())).await;
+    let c = env.challenge(challenge).await;
+
+    let ix = instructions::register_beneficiary(
+        protocol.pubkey(),
+        beneficiary.pubkey(),
+        "food bank".into(),
+    );
+    env.send(&[ix], &[&protocol]).await.unwrap();
+    let deactivate =
+        instructions::set_beneficiary_active(protocol.pubkey(), &beneficiary.pubkey(), false);
+    env.send(&[deactivate], &[&protocol]).await.unwrap();
+
+    let stranger = env.wallet().await;
+    let mut forged = c.clone();
+    forged.authority = stranger.pubkey();
+    let ix = instructions::enable_forfeiture(&forged, &beneficiary.pubkey());
+    assert_error(
+        env.send(&[ix], &[&stranger]).await,
+        ProvenError::Unauthorized,
+    );
+    let enable = instructions::enable_forfeiture(&c, &beneficiary.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&enable), &[&admin]).await,
+        ProvenError::BeneficiaryInactive,
+    );
+    let activate =
+        instructions::set_beneficiary_active(protocol.pubkey(), &beneficiary.pubkey(), true);
+    env.send(&[activate], &[&protocol]).await.unwrap();
+    env.send(&[enable], &[&admin]).await.unwrap();
+
+    // Forfeiture and teams are mutually exclusive, in either order
+    let ix = instructions::enable_teams(&c, TeamScoring::Average);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ForfeitureChallenge,
+    );
+    let teams = env.create(&admin, terms("teams", &oracle.pubkey())).await;
+    let t = env.challenge(teams).await;
+    let ix = instructions::enable_teams(&t, TeamScoring::Average);
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let ix = instructions::enable_forfeiture(&t, &beneficiary.pubkey());
+    assert_error(env.send(&[ix], &[&admin]).await, ProvenError::TeamChallenge);
+
+    let (winner, loser) = (env.user().await, env.user().await);
+    for user in [&winner, &loser] {
+        env.join(challenge, user).await.unwrap();
+    }
+    env.prove_days(challenge, &oracle, &winner.pubkey(), 8)
+        .await;
+    env.warp(c.end_ts + 1).await;
+    let ix = instructions::settle_challenge(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    for user in [&winner, &loser] {
+        let p = env.participant(challenge, &user.pubkey()).await;
+        let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+        env.send(&[ix], &[&oracle]).await.unwrap();
+    }
+
+    let c = env.challenge(challenge).await;
+    let mut unrouted = c.clone();
+    unrouted.forfeit_beneficiary = None;
+    let ix = instructions::finalize_settlement(&unrouted, oracle.pubkey());
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::BeneficiaryMissing,
+    );
+    let ix = instructions::finalize_settlement(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+
+    // The winner only gets their stake back
+    assert_eq!(env.balance(&beneficiary.pubkey()).await, STAKE - STAKE / 20);
+    let c = env.challenge(challenge).await;
+    assert_eq!(c.bonus_per_winner, 0);
+    let ix = instructions::claim_payout(&c, winner.pubkey());
+    env.send(&[ix], &[&winner]).await.unwrap();
+    assert_eq!(env.balance(&winner.pubkey()).await, FUNDING);
+    assert_eq!(env.balance(&challenge).await, STAKE / 20);
+}
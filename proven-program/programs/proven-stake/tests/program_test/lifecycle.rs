@@ -0,0 +1,487 @@
+//! The core flow: create, join, prove, settle, claim and close, plus
+//! cancellation and refunds.
+
+use anchor_lang::AnchorDeserialize;
+use proven_stake::{instruction, ChallengeStatus, ProvenError, SettlementPreview};
+use proven_stake_client::{instructions, pda};
+use solana_program_test::BanksClientError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::slice;
+
+use crate::common::*;
+
+#[tokio::test]
+async fn full_lifecycle_pays_winners_and_closes() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("walk-10", &oracle.pubkey())).await;
+
+    let (alice, bob, carol) = (env.user().await, env.user().await, env.user().await);
+    for user in [&alice, &bob, &carol] {
+        env.join(challenge, user).await.unwrap();
+    }
+    assert_eq!(env.balance(&challenge).await, 3 * STAKE);
+
+    env.prove_days(challenge, &oracle, &alice.pubkey(), 8).await;
+    env.prove_days(challenge, &oracle, &bob.pubkey(), 10).await;
+    env.prove_days(challenge, &oracle, &carol.pubkey(), 7).await;
+    assert!(env.challenge(challenge).await.status == ChallengeStatus::Started);
+
+    env.settle_all(
+        challenge,
+        &oracle,
+        &[&alice.pubkey(), &bob.pubkey(), &carol.pubkey()],
+    )
+    .await;
+
+    // Carol's stake less the 5% fee is split between the two winners
+    let c = env.challenge(challenge).await;
+    assert!(c.status == ChallengeStatus::Settled);
+    assert_eq!((c.winner_count, c.loser_count), (2, 1));
+    assert_eq!(c.fee_amount, STAKE / 20);
+    assert_eq!(c.bonus_per_winner, (STAKE - STAKE / 20) / 2);
+    assert!(!env.participant(challenge, &carol.pubkey()).await.is_winner);
+
+    for user in [&alice, &bob] {
+        let ix = instructions::claim_payout(&c, user.pubkey());
+        env.send(&[ix], &[user]).await.unwrap();
+        assert_eq!(
+            env.balance(&user.pubkey()).await,
+            FUNDING + c.bonus_per_winner
+        );
+    }
+    assert_eq!(env.balance(&carol.pubkey()).await, FUNDING - STAKE);
+
+    let admin_before = env.balance(&admin.pubkey()).await;
+    env.send(&[instructions::withdraw_fees(&c)], &[&admin])
+        .await
+        .unwrap();
+    assert_eq!(
+        env.balance(&admin.pubkey()).await,
+        admin_before + c.fee_amount
+    );
+    assert_eq!(env.balance(&challenge).await, 0);
+
+    for user in [&alice, &bob, &carol] {
+        let ix = instructions::close_participant(&c, user.pubkey(), user.pubkey());
+        env.send(&[ix], &[user]).await.unwrap();
+        assert!(
+            !env.exists(pda::participant(&challenge, &user.pubkey()))
+                .await
+        );
+    }
+    env.send(&[instructions::close_challenge(&c)], &[&admin])
+        .await
+        .unwrap();
+    assert!(!env.exists(challenge).await);
+}
+
+impl Env {
+    async fn preview(
+        &mut self,
+        challenge: Pubkey,
+        users: &[Pubkey],
+    ) -> Result<SettlementPreview, BanksClientError> {
+        let c = self.challenge(challenge).await;
+        let ix = instructions::preview_settlement(&c, users);
+        let data = self.simulate(&[ix], &[]).await?;
+        Ok(SettlementPreview::try_from_slice(&data).unwrap())
+    }
+}
+
+#[tokio::test]
+async fn settlement_preview_matches_what_finalize_books() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("preview", &oracle.pubkey())).await;
+    let users = [
+        env.user().await,
+        env.user().await,
+        env.user().await,
+        env.user().await,
+    ];
+    for user in &users {
+        env.join(challenge, user).await.unwrap();
+    }
+    let keys: Vec<Pubkey> = users.iter().map(|u| u.pubkey()).collect();
+
+    // Before the start nobody has proved anything yet
+    let preview = env.preview(challenge, &keys).await.unwrap();
+    assert_eq!(
+        (
+            preview.winner_count,
+            preview.loser_count,
+            preview.bonus_per_winner
+        ),
+        (0, 4, 0)
+    );
+    assert_eq!(preview.remainder, 4 * STAKE - 4 * STAKE / 20);
+
+    // Every participant, each exactly once
+    assert_error(
+        env.preview(challenge, &keys[1..]).await,
+        ProvenError::ParticipantsIncomplete,
+    );
+    let repeated = [keys[0], keys[0], keys[1], keys[2]];
+    assert_error(
+        env.preview(challenge, &repeated).await,
+        ProvenError::ParticipantsIncomplete,
+    );
+    let other = env.create(&admin, terms("other", &oracle.pubkey())).await;
+    env.join(other, &users[3]).await.unwrap();
+    let c = env.challenge(challenge).await;
+    let mut ix = instructions::preview_settlement(&c, &keys);
+    ix.accounts[4].pubkey = pda::participant(&other, &keys[3]);
+    assert_error(
+        env.simulate(&[ix], &[]).await,
+        ProvenError::ParticipantsIncomplete,
+    );
+
+    for (user, days) in keys.iter().zip([8, 10, 8, 3]) {
+        env.prove_days(challenge, &oracle, user, days).await;
+    }
+    let c = env.challenge(challenge).await;
+    env.warp(c.end_ts + 1).await;
+    let ix = instructions::settle_challenge(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    let p = env.participant(challenge, &keys[0]).await;
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    env.send(&[ix], &[&oracle]).await.unwrap();
+
+    // One loser's stake less the fee, split three ways with two units over
+    let preview = env.preview(challenge, &keys).await.unwrap();
+    assert_eq!(
+        preview,
+        SettlementPreview {
+            winner_count: 3,
+            loser_count: 1,
+            platform_fee: STAKE / 20,
+            bonus_per_winner: (STAKE - STAKE / 20) / 3,
+            remainder: 2,
+            forfeited: 0,
+        }
+    );
+    // Simulation leaves the challenge as it was
+    let c = env.challenge(challenge).await;
+    assert_eq!((c.winner_count, c.loser_count), (1, 0));
+
+    for user in &keys[1..] {
+        let p = env.participant(challenge, user).await;
+        let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+        env.send(&[ix], &[&oracle]).await.unwrap();
+    }
+    let ix = instructions::finalize_settlement(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    let c = env.challenge(challenge).await;
+    assert_eq!(
+        (c.winner_count, c.loser_count, c.fee_amount),
+        (
+            preview.winner_count,
+            preview.loser_count,
+            preview.platform_fee
+        )
+    );
+    assert_eq!(
+        (c.bonus_per_winner, c.remainder),
+        (preview.bonus_per_winner, preview.remainder)
+    );
+    assert_error(
+        env.preview(challenge, &keys).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+}
+
+#[tokio::test]
+async fn create_validates_terms() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Pubkey::new_unique();
+
+    type Edit = fn(&mut instruction::CreateChallenge);
+    let cases: Vec<(Edit, ProvenError)> = vec![
+        (|t| t.stake_amount = 0, ProvenError::InvalidAmount),
+        (|t| t.total_days = 0, ProvenError::InvalidDuration),
+        (|t| t.threshold_bps = 10_001, ProvenError::InvalidThreshold),
+        (|t| t.platform_fee_bps = 1_001, ProvenError::InvalidFee),
+        (|t| t.start_ts = T0, ProvenError::InvalidStartTime),
+        (
+            |t| t.challenge_id = String::new(),
+            ProvenError::ChallengeIdEmpty,
+        ),
+        (
+            |t| t.challenge_id = "x".repeat(65),
+            ProvenError::ChallengeIdTooLong,
+        ),
+        (
+            |t| {
+                (t.treasury_fee_bps, t.charity_fee_bps, t.charity) =
+                    (6_000, 5_000, Some(Pubkey::new_unique()))
+            },
+            ProvenError::InvalidFeeSplit,
+        ),
+        (|t| t.charity_fee_bps = 100, ProvenError::InvalidFeeSplit),
+    ];
+    for (i, (edit, expected)) in cases.into_iter().enumerate() {
+        let mut t = terms(&format!("bad-{i}"), &oracle);
+        edit(&mut t);
+        assert_error(env.try_create(&admin, t).await, expected);
+    }
+
+    env.create(&admin, terms("ok", &oracle)).await;
+}
+
+#[tokio::test]
+async fn each_stage_rejects_out_of_order_calls() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("stages", &oracle.pubkey())).await;
+    let (winner, loser) = (env.user().await, env.user().await);
+    env.join(challenge, &winner).await.unwrap();
+    env.join(challenge, &loser).await.unwrap();
+    let c = env.challenge(challenge).await;
+
+    // Before the start
+    assert_error(
+        env.record_proof(challenge, &oracle, &winner.pubkey()).await,
+        ProvenError::ChallengeNotStarted,
+    );
+    let ix = instructions::close_participant(&c, winner.pubkey(), winner.pubkey());
+    assert_error(
+        env.send(&[ix], &[&winner]).await,
+        ProvenError::ChallengeStillActive,
+    );
+    assert_error(
+        env.send(&[instructions::close_challenge(&c)], &[&admin])
+            .await,
+        ProvenError::ChallengeStillActive,
+    );
+    assert_error(
+        env.send(&[instructions::withdraw_fees(&c)], &[&admin])
+            .await,
+        ProvenError::InvalidChallengeStatus,
+    );
+
+    // Running
+    env.warp(c.start_ts).await;
+    let late = env.user().await;
+    assert_error(
+        env.join(challenge, &late).await,
+        ProvenError::ChallengeStarted,
+    );
+    let impostor = Keypair::new();
+    assert_error(
+        env.record_proof(challenge, &impostor, &winner.pubkey())
+            .await,
+        ProvenError::InvalidOracle,
+    );
+    env.prove_days(challenge, &oracle, &winner.pubkey(), 8)
+        .await;
+    let ix = instructions::settle_challenge(&c, oracle.pubkey());
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::ChallengeNotEnded,
+    );
+    let p = env.participant(challenge, &winner.pubkey()).await;
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+
+    // Ended
+    env.warp(c.end_ts + 1).await;
+    assert_error(
+        env.record_proof(challenge, &oracle, &winner.pubkey()).await,
+        ProvenError::ChallengeEnded,
+    );
+    let ix = instructions::settle_challenge(&c, impostor.pubkey());
+    assert_error(
+        env.send(&[ix], &[&impostor]).await,
+        ProvenError::InvalidOracle,
+    );
+    let ix = instructions::settle_challenge(&c, oracle.pubkey());
+    env.send(slice::from_ref(&ix), &[&oracle]).await.unwrap();
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    env.send(slice::from_ref(&ix), &[&oracle]).await.unwrap();
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::AlreadySettled,
+    );
+
+    let finalize = instructions::finalize_settlement(&c, oracle.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&finalize), &[&oracle]).await,
+        ProvenError::SettlementIncomplete,
+    );
+    let claim = instructions::claim_payout(&c, winner.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&claim), &[&winner]).await,
+        ProvenError::ChallengeNotSettled,
+    );
+
+    let p = env.participant(challenge, &loser.pubkey()).await;
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    env.send(&[ix], &[&oracle]).await.unwrap();
+    env.send(slice::from_ref(&finalize), &[&oracle])
+        .await
+        .unwrap();
+    assert_error(
+        env.send(&[finalize], &[&oracle]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+
+    // Settled
+    let ix = instructions::claim_payout(&c, loser.pubkey());
+    assert_error(env.send(&[ix], &[&loser]).await, ProvenError::NotWinner);
+    let ix = instructions::close_participant(&c, winner.pubkey(), winner.pubkey());
+    assert_error(
+        env.send(&[ix], &[&winner]).await,
+        ProvenError::PayoutNotClaimed,
+    );
+    let ix = instructions::close_participant(&c, loser.pubkey(), winner.pubkey());
+    assert_error(env.send(&[ix], &[&loser]).await, ProvenError::Unauthorized);
+    assert_error(
+        env.send(&[instructions::close_challenge(&c)], &[&admin])
+            .await,
+        ProvenError::PendingWinnerPayouts,
+    );
+
+    env.send(slice::from_ref(&claim), &[&winner]).await.unwrap();
+    assert_error(
+        env.send(&[claim], &[&winner]).await,
+        ProvenError::PayoutAlreadyClaimed,
+    );
+
+    let mut not_admin = c.clone();
+    not_admin.authority = loser.pubkey();
+    assert_error(
+        env.send(&[instructions::withdraw_fees(&not_admin)], &[&loser])
+            .await,
+        ProvenError::Unauthorized,
+    );
+    assert_error(
+        env.send(&[instructions::close_challenge(&not_admin)], &[&loser])
+            .await,
+        ProvenError::Unauthorized,
+    );
+    assert_error(
+        env.send(&[instructions::close_challenge(&c)], &[&admin])
+            .await,
+        ProvenError::FeesUncollected,
+    );
+    env.send(&[instructions::withdraw_fees(&c)], &[&admin])
+        .await
+        .unwrap();
+    assert_error(
+        env.send(&[instructions::withdraw_fees(&c)], &[&admin])
+            .await,
+        ProvenError::NoFees,
+    );
+    assert_error(
+        env.send(&[instructions::close_challenge(&c)], &[&admin])
+            .await,
+        ProvenError::ParticipantsRemaining,
+    );
+
+    // The admin may close on a participant's behalf; rent goes to them
+    for user in [&winner, &loser] {
+        let ix = instructions::close_participant(&c, admin.pubkey(), user.pubkey());
+        env.send(&[ix], &[&admin]).await.unwrap();
+    }
+    env.send(&[instructions::close_challenge(&c)], &[&admin])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn cancelled_challenges_refund_stakes() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("cancel", &oracle.pubkey())).await;
+    let (alice, bob) = (env.user().await, env.user().await);
+    env.join(challenge, &alice).await.unwrap();
+    env.join(challenge, &bob).await.unwrap();
+    let c = env.challenge(challenge).await;
+
+    let refund = instructions::claim_refund(&c, alice.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&refund), &[&alice]).await,
+        ProvenError::NotCancelled,
+    );
+    let stranger = env.user().await;
+    assert_error(
+        env.send(
+            &[instructions::cancel_challenge(&c, stranger.pubkey())],
+            &[&stranger],
+        )
+        .await,
+        ProvenError::Unauthorized,
+    );
+
+    let cancel = instructions::cancel_challenge(&c, admin.pubkey());
+    env.send(slice::from_ref(&cancel), &[&admin]).await.unwrap();
+    assert_error(
+        env.send(&[cancel], &[&admin]).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+    assert_error(
+        env.join(challenge, &stranger).await,
+        ProvenError::InvalidChallengeStatus,
+    );
+
+    let ix = instructions::close_participant(&c, alice.pubkey(), alice.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&ix), &[&alice]).await,
+        ProvenError::RefundNotClaimed,
+    );
+
+    env.send(slice::from_ref(&refund), &[&alice]).await.unwrap();
+    assert_eq!(env.balance(&alice.pubkey()).await, FUNDING);
+    assert_error(
+        env.send(&[refund], &[&alice]).await,
+        ProvenError::AlreadyClaimed,
+    );
+    env.send(&[ix], &[&alice]).await.unwrap();
+
+    // Stakes of the remaining participants hold the challenge open
+    assert_error(
+        env.send(&[instructions::close_challenge(&c)], &[&admin])
+            .await,
+        ProvenError::ParticipantsRemaining,
+    );
+    env.send(&[instructions::claim_refund(&c, bob.pubkey())], &[&bob])
+        .await
+        .unwrap();
+    let ix = instructions::close_participant(&c, bob.pubkey(), bob.pubkey());
+    env.send(&[ix], &[&bob]).await.unwrap();
+    env.send(&[instructions::close_challenge(&c)], &[&admin])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&challenge).await, 0);
+}
+
+#[tokio::test]
+async fn cancel_is_only_allowed_before_the_start() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env.create(&admin, terms("late", &oracle.pubkey())).await;
+    let c = env.challenge(challenge).await;
+
+    env.warp(c.start_ts).await;
+    let ix = instructions::cancel_challenge(&c, admin.pubkey());
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ChallengeStarted,
+    );
+}
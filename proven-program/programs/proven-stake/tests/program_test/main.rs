@@ -0,0 +1,22 @@
+//! In-process tests for `proven_stake`: the program runs natively inside
+//! a `solana-program-test` bank, with the clock moved by hand.
+//!
+//! Every `ProvenError` a transaction can produce is asserted somewhere in
+//! these modules. A few guard invariants no instruction sequence breaks
+//! and are not exercised: `MathOverflow`, `NotJoined` (participants are
+//! created joined), `NotSettled` and `AllPayoutsClaimed` on claims (finalize
+//! requires every participant settled, and each winner claims once),
+//! `PendingRemainderDistribution` (each claim drains one remainder unit),
+//! `InsufficientRollover` (editions share the series stake), and
+//! `ChallengeIdMismatch`, which nothing raises.
+
+mod access;
+mod admin;
+mod commitments;
+mod common;
+mod duels;
+mod fees;
+mod lifecycle;
+mod series;
+mod solvency;
+mod teams;
@@ -0,0 +1,217 @@
+//! Recurring series: spawning editions on schedule and rolling winnings
+//! into the next edition.
+
+use proven_stake::{instruction, ChallengeSeries, ProvenError, TeamScoring};
+use proven_stake_client::{instructions, pda};
+use solana_program_test::BanksClientError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::common::*;
+
+/// Two-day editions every three days, the first starting at `T0 + 4 days`.
+fn series_terms(oracle: &Pubkey) -> instruction::CreateSeries {
+    instruction::CreateSeries {
+        series_id: "sprint".to_string(),
+        stake_amount: STAKE,
+        total_days: 2,
+        threshold_bps: 5000,
+        platform_fee_bps: 500,
+        first_start_ts: T0 + 4 * DAY,
+        interval_secs: 3 * DAY,
+        oracle_signer: *oracle,
+    }
+}
+
+impl Env {
+    async fn series(&mut self, admin: &Pubkey) -> ChallengeSeries {
+        self.account(pda::series("sprint", admin)).await
+    }
+
+    /// Spawns the series' next edition, returning its address.
+    async fn spawn(&mut self, admin: &Pubkey) -> Result<Pubkey, BanksClientError> {
+        let series = self.series(admin).await;
+        let address = pda::challenge(&series.edition_id(series.next_edition), admin);
+        let payer = self.payer();
+        self.send(&[instructions::spawn_next(&series, payer)], &[])
+            .await
+            .map(|()| address)
+    }
+}
+
+#[tokio::test]
+async fn series_terms_are_validated() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Pubkey::new_unique();
+    let mint = env.mint;
+
+    let mut t = series_terms(&oracle);
+    t.interval_secs = 2 * DAY - 1;
+    let ix = instructions::create_series(admin.pubkey(), mint, t);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidInterval,
+    );
+
+    let mut t = series_terms(&oracle);
+    t.first_start_ts = T0;
+    let ix = instructions::create_series(admin.pubkey(), mint, t);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::InvalidStartTime,
+    );
+
+    let mut t = series_terms(&oracle);
+    t.series_id = "x".repeat(ChallengeSeries::MAX_ID_LENGTH + 1);
+    let ix = instructions::create_series(admin.pubkey(), mint, t);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ChallengeIdTooLong,
+    );
+}
+
+#[tokio::test]
+async fn editions_spawn_on_schedule_and_roll_over() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let mint = env.mint;
+    let ix = instructions::create_series(admin.pubkey(), mint, series_terms(&oracle.pubkey()));
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    // Editions open at most one interval before they start
+    assert_error(env.spawn(&admin.pubkey()).await, ProvenError::SpawnTooEarly);
+    env.warp(T0 + DAY).await;
+    let first = env.spawn(&admin.pubkey()).await.unwrap();
+    assert_error(env.spawn(&admin.pubkey()).await, ProvenError::SpawnTooEarly);
+
+    let c = env.challenge(first).await;
+    assert_eq!((c.edition, c.start_ts), (1, T0 + 4 * DAY));
+    assert_eq!(c.challenge_id, "sprint-1");
+
+    let (alice, bob, carol) = (env.user().await, env.user().await, env.user().await);
+    for user in [&alice, &bob, &carol] {
+        env.join(first, user).await.unwrap();
+    }
+
+    // Auto-roll is a series feature
+    let mut t = terms("standalone", &oracle.pubkey());
+    t.start_ts = c.start_ts;
+    let standalone = env.create(&admin, t).await;
+    env.join(standalone, &alice).await.unwrap();
+    let s = env.challenge(standalone).await;
+    let ix = instructions::set_auto_roll(&s, alice.pubkey(), true);
+    assert_error(
+        env.send(&[ix], &[&alice]).await,
+        ProvenError::NotSeriesChallenge,
+    );
+
+    for user in [&alice, &bob] {
+        let ix = instructions::set_auto_roll(&c, user.pubkey(), true);
+        env.send(&[ix], &[user]).await.unwrap();
+        env.prove_days(first, &oracle, &user.pubkey(), 1).await;
+    }
+    env.settle_all(
+        first,
+        &oracle,
+        &[&alice.pubkey(), &bob.pubkey(), &carol.pubkey()],
+    )
+    .await;
+
+    let second = env.spawn(&admin.pubkey()).await.unwrap();
+    let series = env.series(&admin.pubkey()).await;
+    let c = env.challenge(first).await;
+    let next = env.challenge(second).await;
+    assert_eq!((next.edition, next.start_ts), (2, T0 + 7 * DAY));
+
+    let payer = env.payer();
+    let ix = instructions::roll_over(&series, &c, &next, carol.pubkey(), payer);
+    assert_error(env.send(&[ix], &[]).await, ProvenError::AutoRollDisabled);
+    let ix = instructions::roll_over(&series, &c, &s, bob.pubkey(), payer);
+    assert_error(env.send(&[ix], &[]).await, ProvenError::SeriesMismatch);
+
+    // Gated editions must be joined with a proof or voucher
+    let ix = instructions::set_voucher_required(&next, true);
+    env.send(&[ix], &[&admin]).await.unwrap();
+    let gated = env.challenge(second).await;
+    let ix = instructions::roll_over(&series, &c, &gated, alice.pubkey(), payer);
+    assert_error(env.send(&[ix], &[]).await, ProvenError::GatedEdition);
+    let ix = instructions::set_voucher_required(&gated, false);
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    // Alice's stake moves into edition two and the bonus is paid out
+    let ix = instructions::roll_over(&series, &c, &next, alice.pubkey(), payer);
+    env.send(&[ix], &[]).await.unwrap();
+    assert_eq!(
+        env.balance(&alice.pubkey()).await,
+        FUNDING - 2 * STAKE + c.bonus_per_winner
+    );
+    let rolled = env.participant(second, &alice.pubkey()).await;
+    assert!(rolled.auto_roll);
+    assert_eq!(rolled.stake_deposited, STAKE);
+    assert_eq!(env.challenge(second).await.participant_count, 1);
+    assert_eq!(env.balance(&second).await, STAKE);
+
+    let ix = instructions::set_auto_roll(&c, alice.pubkey(), false);
+    assert_error(
+        env.send(&[ix], &[&alice]).await,
+        ProvenError::AlreadyClaimed,
+    );
+
+    // Skipping an edition or rolling into a running one is refused
+    env.warp(next.start_ts).await;
+    let third = env.spawn(&admin.pubkey()).await.unwrap();
+    let later = env.challenge(third).await;
+    let ix = instructions::roll_over(&series, &c, &later, bob.pubkey(), payer);
+    assert_error(env.send(&[ix], &[]).await, ProvenError::NotNextEdition);
+    let ix = instructions::roll_over(&series, &c, &next, bob.pubkey(), payer);
+    assert_error(env.send(&[ix], &[]).await, ProvenError::ChallengeStarted);
+
+    // The payout can still be claimed directly
+    env.send(&[instructions::claim_payout(&c, bob.pubkey())], &[&bob])
+        .await
+        .unwrap();
+    assert_eq!(
+        env.balance(&bob.pubkey()).await,
+        FUNDING + c.bonus_per_winner
+    );
+}
+
+#[tokio::test]
+async fn winnings_never_roll_into_team_editions() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let mint = env.mint;
+    let ix = instructions::create_series(admin.pubkey(), mint, series_terms(&oracle.pubkey()));
+    env.send(&[ix], &[&admin]).await.unwrap();
+    env.warp(T0 + DAY).await;
+    let first = env.spawn(&admin.pubkey()).await.unwrap();
+
+    let alice = env.user().await;
+    env.join(first, &alice).await.unwrap();
+    let c = env.challenge(first).await;
+    let ix = instructions::set_auto_roll(&c, alice.pubkey(), true);
+    env.send(&[ix], &[&alice]).await.unwrap();
+    env.prove_days(first, &oracle, &alice.pubkey(), 1).await;
+    env.settle_all(first, &oracle, &[&alice.pubkey()]).await;
+
+    // Still empty, so the admin may turn it into a team edition
+    let second = env.spawn(&admin.pubkey()).await.unwrap();
+    let next = env.challenge(second).await;
+    let ix = instructions::enable_teams(&next, TeamScoring::Average);
+    env.send(&[ix], &[&admin]).await.unwrap();
+
+    let (series, c) = (
+        env.series(&admin.pubkey()).await,
+        env.challenge(first).await,
+    );
+    let next = env.challenge(second).await;
+    let ix = instructions::roll_over(&series, &c, &next, alice.pubkey(), env.payer());
+    assert_error(env.send(&[ix], &[]).await, ProvenError::TeamChallenge);
+
+    env.send(&[instructions::claim_payout(&c, alice.pubkey())], &[&alice])
+        .await
+        .unwrap();
+}
@@ -0,0 +1,326 @@
+//! Randomized solvency checks: arbitrary challenges are played to the end
+//! and the escrow is reconciled against an independent model of what it
+//! still owes after every step.
+//!
+//! Runs 24 cases by default; set `QUICKCHECK_TESTS` for longer runs.
+
+use std::cell::RefCell;
+use std::env;
+
+use proven_stake::ProvenError;
+use proven_stake_client::instructions;
+use quickcheck::{Arbitrary, Gen, QuickCheck};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::common::*;
+
+const MAX_PARTICIPANTS: usize = 6;
+const MAX_DAYS: u32 = 5;
+
+#[derive(Clone, Debug)]
+struct Scenario {
+    stake: u64,
+    total_days: u32,
+    threshold_bps: u16,
+    platform_fee_bps: u16,
+    treasury_fee_bps: u16,
+    charity_fee_bps: u16,
+    /// Proof days recorded for each participant.
+    proofs: Vec<u32>,
+    /// Cancel before the start instead of playing the challenge out.
+    cancel: bool,
+    /// Claims and fee withdrawals after settlement, in order.
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    /// The participant claims a payout, or a refund if cancelled.
+    Claim(usize),
+    HostFee,
+    TreasuryFee,
+    CharityFee,
+}
+
+impl Arbitrary for Scenario {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let total_days = 1 + u32::arbitrary(g) % MAX_DAYS;
+        let treasury_fee_bps = u16::arbitrary(g) % 10_001;
+        let participants = 1 + usize::arbitrary(g) % MAX_PARTICIPANTS;
+
+        let mut steps: Vec<Step> = (0..participants).map(Step::Claim).collect();
+        steps.extend([Step::HostFee, Step::TreasuryFee, Step::CharityFee]);
+        for i in (1..steps.len()).rev() {
+            steps.swap(i, usize::arbitrary(g) % (i + 1));
+        }
+
+        Self {
+            // Odd stakes make the fee and bonus divisions round
+            stake: 1 + u64::arbitrary(g) % (2 * STAKE),
+            total_days,
+            threshold_bps: u16::arbitrary(g) % 10_001,
+            platform_fee_bps: u16::arbitrary(g) % 1_001,
+            treasury_fee_bps,
+            charity_fee_bps: u16::arbitrary(g) % (10_001 - treasury_fee_bps),
+            proofs: (0..participants)
+                .map(|_| u32::arbitrary(g) % (total_days + 1))
+                .collect(),
+            cancel: u8::arbitrary(g) % 8 == 0,
+            steps,
+        }
+    }
+
+    /// Drops one participant at a time, keeping the step order.
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        if self.proofs.len() == 1 {
+            return quickcheck::empty_shrinker();
+        }
+        let this = self.clone();
+        Box::new((0..self.proofs.len()).rev().map(move |dropped| {
+            let mut smaller = this.clone();
+            smaller.proofs.remove(dropped);
+            smaller.steps.retain(|step| *step != Step::Claim(dropped));
+            for step in &mut smaller.steps {
+                match step {
+                    Step::Claim(i) if *i > dropped => *i -= 1,
+                    _ => {}
+                }
+            }
+            smaller
+        }))
+    }
+}
+
+/// What settlement should produce, computed without the program.
+#[derive(Debug)]
+struct Ledger {
+    winners: Vec<bool>,
+    bonus: u64,
+    remainder: u64,
+    host_fee: u64,
+    treasury_fee: u64,
+    charity_fee: u64,
+    /// Loser pool left in escrow for good when nobody wins.
+    stranded: u64,
+}
+
+impl Ledger {
+    fn settle(s: &Scenario) -> Self {
+        let required = s.total_days as u64 * s.threshold_bps as u64 / 10_000;
+        let winners: Vec<bool> = s.proofs.iter().map(|&p| p as u64 >= required).collect();
+        let winner_count = winners.iter().filter(|&&w| w).count() as u64;
+        let pool = (winners.len() as u64 - winner_count) * s.stake;
+
+        let fee = pool * s.platform_fee_bps as u64 / 10_000;
+        let treasury_fee = (fee as u128 * s.treasury_fee_bps as u128 / 10_000) as u64;
+        let charity_fee = (fee as u128 * s.charity_fee_bps as u128 / 10_000) as u64;
+        let distributable = pool - fee;
+        let (bonus, remainder, stranded) = match winner_count {
+            0 => (0, 0, distributable),
+            n => (distributable / n, distributable % n, 0),
+        };
+
+        Self {
+            winners,
+            bonus,
+            remainder,
+            host_fee: fee - treasury_fee - charity_fee,
+            treasury_fee,
+            charity_fee,
+            stranded,
+        }
+    }
+
+    /// Everything the escrow owes right after settlement.
+    fn obligations(&self, stake: u64) -> u64 {
+        let winner_count = self.winners.iter().filter(|&&w| w).count() as u64;
+        winner_count * (stake + self.bonus)
+            + self.remainder
+            + self.host_fee
+            + self.treasury_fee
+            + self.charity_fee
+            + self.stranded
+    }
+}
+
+#[test]
+fn escrow_always_covers_outstanding_obligations() {
+    let mut qc = QuickCheck::new();
+    if env::var_os("QUICKCHECK_TESTS").is_none() {
+        qc = qc.tests(24);
+    }
+    qc.quickcheck(escrow_solvency as fn(Scenario));
+}
+
+thread_local! {
+    /// One bank serves every case: each new bank leaks its service threads.
+    static BANK: RefCell<Option<(Runtime, Env)>> = const { RefCell::new(None) };
+}
+
+fn escrow_solvency(s: Scenario) {
+    BANK.with(|bank| {
+        let mut bank = bank.borrow_mut();
+        let (runtime, env) = bank.get_or_insert_with(|| {
+            let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+            let env = runtime.block_on(Env::new());
+            (runtime, env)
+        });
+        runtime.block_on(play(env, s));
+    });
+}
+
+/// Plays `s` under a fresh admin, so challenge addresses never collide.
+async fn play(env: &mut Env, s: Scenario) {
+    let (admin, protocol, treasury, charity) = (
+        env.wallet().await,
+        env.wallet().await,
+        env.wallet().await,
+        env.wallet().await,
+    );
+    env.set_protocol(&protocol.pubkey(), &treasury.pubkey());
+    let oracle = Keypair::new();
+
+    let mut t = terms("solvency", &oracle.pubkey());
+    t.stake_amount = s.stake;
+    t.total_days = s.total_days;
+    t.threshold_bps = s.threshold_bps;
+    t.platform_fee_bps = s.platform_fee_bps;
+    t.treasury_fee_bps = s.treasury_fee_bps;
+    t.charity_fee_bps = s.charity_fee_bps;
+    t.charity = Some(charity.pubkey());
+    t.start_ts = env.now().await + DAY;
+    let challenge = env.create(&admin, t).await;
+
+    let mut users = Vec::new();
+    for _ in &s.proofs {
+        let user = env.user().await;
+        env.join(challenge, &user).await.unwrap();
+        users.push(user);
+        let deposited = users.len() as u64 * s.stake;
+        assert_eq!(env.balance(&challenge).await, deposited);
+    }
+
+    if s.cancel {
+        return refund(env, &s, challenge, &admin, &users).await;
+    }
+
+    for (user, &days) in users.iter().zip(&s.proofs) {
+        env.prove_days(challenge, &oracle, &user.pubkey(), days)
+            .await;
+    }
+    let keys: Vec<Pubkey> = users.iter().map(|u| u.pubkey()).collect();
+    env.settle_all(challenge, &oracle, &keys.iter().collect::<Vec<_>>())
+        .await;
+
+    let ledger = Ledger::settle(&s);
+    let c = env.challenge(challenge).await;
+    // With no winners the pool is booked as an already-claimed remainder
+    assert_eq!(
+        (c.bonus_per_winner, c.remainder - c.remainder_claimed),
+        (ledger.bonus, ledger.remainder)
+    );
+    assert_eq!(
+        (c.fee_amount, c.treasury_fee_amount, c.charity_fee_amount),
+        (ledger.host_fee, ledger.treasury_fee, ledger.charity_fee)
+    );
+    let mut owed = ledger.obligations(s.stake);
+    assert_eq!(env.balance(&challenge).await, owed);
+
+    let mut claims = 0;
+    let mut bonuses = 0;
+    for &step in &s.steps {
+        let (result, amount) = match step {
+            Step::Claim(i) => {
+                let user = &users[i];
+                let ix = instructions::claim_payout(&c, user.pubkey());
+                let result = env.send(&[ix], &[user]).await;
+                if !ledger.winners[i] {
+                    assert_error(result, ProvenError::NotWinner);
+                    continue;
+                }
+                // The first `remainder` claims each take one extra unit
+                let bonus = ledger.bonus + u64::from(claims < ledger.remainder);
+                claims += 1;
+                bonuses += bonus;
+                result.unwrap();
+                let balance = env.balance(&user.pubkey()).await;
+                assert_eq!(balance, FUNDING + bonus);
+                (Ok(()), s.stake + bonus)
+            }
+            Step::HostFee => {
+                let ix = instructions::withdraw_fees(&c);
+                (env.send(&[ix], &[&admin]).await, ledger.host_fee)
+            }
+            Step::TreasuryFee => {
+                let ix =
+                    instructions::withdraw_treasury_fees(&c, protocol.pubkey(), treasury.pubkey());
+                (env.send(&[ix], &[&protocol]).await, ledger.treasury_fee)
+            }
+            Step::CharityFee => {
+                let ix = instructions::withdraw_charity_fees(&c).unwrap();
+                (env.send(&[ix], &[&charity]).await, ledger.charity_fee)
+            }
+        };
+        if amount == 0 {
+            assert_error(result, ProvenError::NoFees);
+        } else {
+            result.unwrap();
+        }
+        owed -= amount;
+        assert_eq!(env.balance(&challenge).await, owed, "after {step:?}");
+    }
+
+    // Bonuses and fees split the loser pool exactly
+    assert_eq!(owed, ledger.stranded);
+    let losers = ledger.winners.iter().filter(|&&w| !w).count() as u64;
+    let fees = ledger.host_fee + ledger.treasury_fee + ledger.charity_fee;
+    assert_eq!(bonuses + fees + ledger.stranded, losers * s.stake);
+    for (user, won) in users.iter().zip(&ledger.winners) {
+        if !won {
+            assert_eq!(env.balance(&user.pubkey()).await, FUNDING - s.stake);
+        }
+    }
+    close(env, challenge, &admin, &users).await;
+}
+
+async fn refund(
+    env: &mut Env,
+    s: &Scenario,
+    challenge: Pubkey,
+    admin: &Keypair,
+    users: &[Keypair],
+) {
+    let c = env.challenge(challenge).await;
+    let ix = instructions::cancel_challenge(&c, admin.pubkey());
+    env.send(&[ix], &[admin]).await.unwrap();
+
+    let mut owed = users.len() as u64 * s.stake;
+    for &step in &s.steps {
+        let Step::Claim(i) = step else {
+            let ix = instructions::withdraw_fees(&c);
+            assert_error(env.send(&[ix], &[admin]).await, ProvenError::NoFees);
+            continue;
+        };
+        let ix = instructions::claim_refund(&c, users[i].pubkey());
+        env.send(&[ix], &[&users[i]]).await.unwrap();
+        owed -= s.stake;
+        assert_eq!(env.balance(&challenge).await, owed, "after {step:?}");
+        assert_eq!(env.balance(&users[i].pubkey()).await, FUNDING);
+    }
+    close(env, challenge, admin, users).await;
+}
+
+/// Closing every account must always be possible once claims are done.
+async fn close(env: &mut Env, challenge: Pubkey, admin: &Keypair, users: &[Keypair]) {
+    let c = env.challenge(challenge).await;
+    for user in users {
+        let ix = instructions::close_participant(&c, user.pubkey(), user.pubkey());
+        env.send(&[ix], &[user]).await.unwrap();
+    }
+    env.send(&[instructions::close_challenge(&c)], &[admin])
+        .await
+        .unwrap();
+    assert!(!env.exists(challenge).await);
+}
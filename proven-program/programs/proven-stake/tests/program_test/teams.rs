@@ -0,0 +1,197 @@
+//! Team challenges: members join through teams, teams settle as a unit and
+//! winning teams split the loser pool.
+
+use proven_stake::{Challenge, ProvenError, Team, TeamConfig, TeamScoring};
+use proven_stake_client::{instructions, pda};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::slice;
+
+use crate::common::*;
+
+impl Env {
+    /// A team challenge with one team per entry of `names`, captained by
+    /// `admin`.
+    async fn team_challenge(&mut self, admin: &Keypair, oracle: &Pubkey, names: &[&str]) -> Pubkey {
+        let challenge = self.create(admin, terms("teams", oracle)).await;
+        let c = self.challenge(challenge).await;
+        let ix = instructions::enable_teams(&c, TeamScoring::Average);
+        self.send(&[ix], &[admin]).await.unwrap();
+        for (index, name) in names.iter().enumerate() {
+            let ix = instructions::create_team(&c, admin.pubkey(), index as u32, name.to_string());
+            self.send(&[ix], &[admin]).await.unwrap();
+        }
+        challenge
+    }
+
+    async fn join_team(&mut self, challenge: Pubkey, user: &Keypair, team: u32) -> TxResult {
+        let c = self.challenge(challenge).await;
+        let ix = instructions::join_team(&c, user.pubkey(), team, vec![]);
+        self.send(&[ix], &[user]).await
+    }
+
+    async fn settle_team(
+        &mut self,
+        c: &Challenge,
+        oracle: &Keypair,
+        team: u32,
+        members: &[Pubkey],
+    ) -> TxResult {
+        let ix = instructions::settle_team(c, oracle.pubkey(), team, members);
+        self.send(&[ix], &[oracle]).await
+    }
+}
+
+#[tokio::test]
+async fn teams_are_set_up_before_anyone_joins() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Pubkey::new_unique();
+
+    let solo = env.create(&admin, terms("solo", &oracle)).await;
+    let user = env.user().await;
+    env.join(solo, &user).await.unwrap();
+    let c = env.challenge(solo).await;
+    let ix = instructions::enable_teams(&c, TeamScoring::Minimum);
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ParticipantsRemaining,
+    );
+
+    let challenge = env.team_challenge(&admin, &oracle, &["red"]).await;
+    assert_error(env.join(challenge, &user).await, ProvenError::TeamChallenge);
+    let c = env.challenge(challenge).await;
+    for name in [String::new(), "x".repeat(Team::MAX_NAME_LENGTH + 1)] {
+        let ix = instructions::create_team(&c, admin.pubkey(), 1, name);
+        assert_error(
+            env.send(&[ix], &[&admin]).await,
+            ProvenError::InvalidTeamName,
+        );
+    }
+
+    for _ in 0..Team::MAX_MEMBERS {
+        let member = env.user().await;
+        env.join_team(challenge, &member, 0).await.unwrap();
+    }
+    assert_error(
+        env.join_team(challenge, &user, 0).await,
+        ProvenError::TeamFull,
+    );
+
+    env.warp(c.start_ts).await;
+    let ix = instructions::create_team(&c, admin.pubkey(), 1, "late".into());
+    assert_error(
+        env.send(&[ix], &[&admin]).await,
+        ProvenError::ChallengeStarted,
+    );
+}
+
+#[tokio::test]
+async fn winning_teams_split_the_loser_pool() {
+    let mut env = Env::new().await;
+    let admin = env.user().await;
+    let oracle = Keypair::new();
+    let challenge = env
+        .team_challenge(&admin, &oracle.pubkey(), &["red", "blue"])
+        .await;
+
+    let red = [env.user().await, env.user().await];
+    let blue = [env.user().await, env.user().await];
+    for (team, members) in [(0, &red), (1, &blue)] {
+        for member in members {
+            env.join_team(challenge, member, team).await.unwrap();
+        }
+    }
+    let [a, b] = red.each_ref().map(|k| k.pubkey());
+    let [c_, d] = blue.each_ref().map(|k| k.pubkey());
+
+    // Empty teams hold up nothing, and their captain can reclaim the rent
+    let c = env.challenge(challenge).await;
+    let spammer = env.user().await;
+    for (index, name) in [(2, "spam"), (3, "more spam")] {
+        let ix = instructions::create_team(&c, spammer.pubkey(), index, name.into());
+        env.send(&[ix], &[&spammer]).await.unwrap();
+    }
+    let ix = instructions::close_team(&c, admin.pubkey(), 0);
+    assert_error(env.send(&[ix], &[&admin]).await, ProvenError::TeamNotEmpty);
+    let ix = instructions::close_team(&c, admin.pubkey(), 2);
+    assert!(env.send(&[ix], &[&admin]).await.is_err());
+    let ix = instructions::close_team(&c, spammer.pubkey(), 2);
+    env.send(&[ix], &[&spammer]).await.unwrap();
+    let config: TeamConfig = env.account(pda::team_config(&challenge)).await;
+    assert_eq!((config.team_count, config.empty_teams), (4, 2));
+
+    // Red averages eight days; blue only four
+    for user in [&a, &b, &c_] {
+        env.prove_days(challenge, &oracle, user, 8).await;
+    }
+    env.warp(c.end_ts + 1).await;
+    let ix = instructions::settle_challenge(&c, oracle.pubkey());
+    env.send(&[ix], &[&oracle]).await.unwrap();
+
+    let p = env.participant(challenge, &a).await;
+    let ix = instructions::settle_participant(&c, oracle.pubkey(), &p);
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::TeamChallenge,
+    );
+
+    assert_error(
+        env.settle_team(&c, &oracle, 0, &[a]).await,
+        ProvenError::TeamMembersIncomplete,
+    );
+    assert_error(
+        env.settle_team(&c, &oracle, 0, &[a, a]).await,
+        ProvenError::TeamMembersIncomplete,
+    );
+    assert_error(
+        env.settle_team(&c, &oracle, 0, &[a, c_]).await,
+        ProvenError::TeamMismatch,
+    );
+    env.settle_team(&c, &oracle, 0, &[a, b]).await.unwrap();
+    assert_error(
+        env.settle_team(&c, &oracle, 0, &[a, b]).await,
+        ProvenError::AlreadySettled,
+    );
+
+    let finalize = instructions::finalize_settlement(&c, oracle.pubkey());
+    assert_error(
+        env.send(slice::from_ref(&finalize), &[&oracle]).await,
+        ProvenError::SettlementIncomplete,
+    );
+    env.settle_team(&c, &oracle, 1, &[c_, d]).await.unwrap();
+
+    let mut untracked = c.clone();
+    untracked.team_mode = false;
+    let ix = instructions::finalize_settlement(&untracked, oracle.pubkey());
+    assert_error(
+        env.send(&[ix], &[&oracle]).await,
+        ProvenError::TeamConfigMissing,
+    );
+    env.send(&[finalize], &[&oracle]).await.unwrap();
+
+    let config: TeamConfig = env.account(pda::team_config(&challenge)).await;
+    let pool = 2 * STAKE - 2 * STAKE / 20;
+    assert_eq!((config.winning_teams, config.bonus_per_team), (1, pool));
+
+    let ix = instructions::claim_payout(&c, a);
+    assert_error(
+        env.send(&[ix], &[&red[0]]).await,
+        ProvenError::TeamChallenge,
+    );
+    let ix = instructions::claim_team_payout(&c, c_, 1);
+    assert_error(env.send(&[ix], &[&blue[0]]).await, ProvenError::NotWinner);
+    let ix = instructions::claim_team_payout(&c, a, 1);
+    assert_error(env.send(&[ix], &[&red[0]]).await, ProvenError::TeamMismatch);
+
+    for member in &red {
+        let ix = instructions::claim_team_payout(&c, member.pubkey(), 0);
+        env.send(slice::from_ref(&ix), &[member]).await.unwrap();
+        assert_error(
+            env.send(&[ix], &[member]).await,
+            ProvenError::PayoutAlreadyClaimed,
+        );
+        assert_eq!(env.balance(&member.pubkey()).await, FUNDING + pool / 2);
+    }
+    assert_eq!(env.balance(&challenge).await, 2 * STAKE / 20);
+}